@@ -0,0 +1,204 @@
+//! `parse_content` as it was before the pull parser, copied here to benchmark against. Only the
+//! parsing is kept, elements are never rendered.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+#[derive(PartialEq, Debug)]
+pub struct Content {
+    pub elements: Vec<Element>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Element {
+    Text(String),
+    Break,
+    Header {
+        level: usize,
+        elements: Vec<Element>,
+    },
+    Link {
+        text: String,
+        link: String,
+    },
+    List {
+        list_type: ListType,
+        items: Vec<Vec<Element>>,
+    },
+    Code {
+        lang: String,
+        code: String,
+    },
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ListType {
+    Ordered { symbol: String },
+    Unordered { symbol: String },
+}
+
+pub fn parse_content(content: &str) -> Content {
+    let start_of_content = content.find("------\n").unwrap() + "------\n".len();
+    let content = &content[start_of_content..];
+    let content = content.replace(" -- ", " — ");
+    let mut elements = vec![];
+    let blocks = content.split("\n\n");
+    let mut _blocks = Vec::new();
+    let mut _block = String::new();
+    let mut in_code_block = false;
+    for block in blocks {
+        if in_code_block && block.ends_with("```") {
+            _block.push_str("\n\n");
+            _block.push_str(block);
+            _blocks.push(_block);
+            _block = String::new();
+            in_code_block = false;
+            continue;
+        } else if in_code_block {
+            _block.push_str("\n\n");
+            _block.push_str(block);
+            continue;
+        }
+        if block.starts_with("```") && block.ends_with("```") {
+        } else if block.starts_with("```") {
+            in_code_block = true;
+            _block.push_str(block);
+            continue;
+        }
+
+        _blocks.push(block.to_string());
+    }
+    for block in _blocks {
+        let block_elements = parse_block(&block);
+        elements.extend(block_elements);
+        elements.push(Element::Break);
+    }
+    elements.pop();
+    Content { elements }
+}
+
+static LINK_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[(.*?)\]\((.*?)\)").expect("Failed to compile link pattern"));
+
+fn parse_block(block: &str) -> Vec<Element> {
+    let mut elements = vec![];
+    use Element::*;
+
+    // NOTE: ORDER IS IMPORTANT, matching links first breaks matching list items that have links
+    if block.starts_with('#') {
+        let (level, text) = block.split_once(' ').unwrap();
+        let header = Header {
+            level: level.len(),
+            elements: parse_block(text),
+        };
+        elements.push(header);
+    } else if is_code(block) {
+        let block = block.trim();
+        let (_, lang) = block.lines().next().unwrap().split_once("```").unwrap();
+        let code = block
+            .lines()
+            .skip(1)
+            .collect::<Vec<&str>>()
+            .into_iter()
+            .rev()
+            .skip(1)
+            .rev()
+            .map(|s| {
+                let mut s = s.to_string();
+                s.push('\n');
+                s
+            })
+            .collect::<String>();
+        let lang = lang.to_string();
+        let code = code.trim().to_string();
+        let code = Code { lang, code };
+        elements.push(code);
+    } else if is_a_list(block) {
+        let mut items = vec![];
+        let mut nested_list_lines = vec![];
+        for line in block.trim().lines() {
+            // parse nested list
+            if is_indented(line) && is_a_list(line) {
+                let mut line = line.to_string();
+                line.push('\n');
+                nested_list_lines.push(line);
+                continue;
+            }
+            if !nested_list_lines.is_empty() {
+                let nested_list_block = nested_list_lines.iter().map(|s| s.trim_start()).collect::<String>();
+                items.push(parse_block(&nested_list_block));
+                nested_list_lines = vec![];
+            }
+            let (_list_symbol, item) = line.split_once(' ').unwrap();
+            items.push(parse_block(item.trim()));
+        }
+        let list_type = parse_list_type(block);
+        let list = List { list_type, items };
+        elements.push(list);
+    } else if LINK_PATTERN.is_match(block) {
+        let link_capture = LINK_PATTERN.captures(block).unwrap();
+        let link_match = link_capture.get(0).unwrap();
+        let (link_start_idx, link_end_idx) = (link_match.range().start, link_match.range().end);
+        if link_start_idx > 0 {
+            elements.push(Text(block[..link_start_idx].to_string()));
+        }
+        let link = Link {
+            text: link_capture.get(1).unwrap().as_str().to_string(),
+            link: link_capture.get(2).unwrap().as_str().to_string(),
+        };
+        elements.push(link);
+        if link_end_idx != block.len() {
+            let rest_of_block = &block[link_end_idx..];
+            elements.extend(parse_block(rest_of_block));
+        }
+    } else {
+        elements.push(Text(block.to_string()));
+    }
+    elements
+}
+
+fn is_code(block: &str) -> bool {
+    let block = block.trim();
+    block.starts_with("```") && block.ends_with("```")
+}
+
+const LIST_TYPES: &[&str] = &["-", "+", "1.", "a)"];
+const UNORDERED_LIST_TYPES: &[&str] = &["-", "+"];
+const ORDERED_LIST_TYPES: &[&str] = &["1.", "a)"];
+
+fn is_a_list(block: &str) -> bool {
+    let lines = block.lines();
+    for line in lines {
+        let Some((line_start, _)) = line.trim().split_once(' ') else {
+            return false;
+        };
+        if !LIST_TYPES.contains(&line_start) {
+            return false;
+        }
+    }
+    true
+}
+
+fn is_indented(s: &str) -> bool {
+    s.starts_with("    ")
+}
+
+fn parse_list_type(s: &str) -> ListType {
+    let s = s.trim();
+    for u_type in UNORDERED_LIST_TYPES {
+        if s.starts_with(u_type) {
+            return ListType::Unordered {
+                symbol: u_type.to_string(),
+            };
+        }
+    }
+    for o_type in ORDERED_LIST_TYPES {
+        if s.starts_with(o_type) {
+            return ListType::Ordered {
+                symbol: o_type.to_string(),
+            };
+        }
+    }
+    eprintln!("Must always return a valid ListType, didn't for: {}", s);
+    panic!();
+}
@@ -0,0 +1,84 @@
+//! Compares building the element tree of a 5 MB document with the pull parser, with the
+//! `parse_content` it replaced and with iterating over its events. Run with
+//! `cargo bench -p libwebdotmd`.
+
+mod baseline;
+
+use libwebdotmd::{Document, Event, Parser};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+const SIZE: usize = 5 * 1024 * 1024;
+const RUNS: usize = 5;
+/// Lines of the paragraph full of links on each page.
+const LINK_LINES: usize = 500;
+
+/// Pages of prose with the usual markup and a long paragraph with a link on every line, repeated
+/// until the document is `SIZE` bytes long. `parse_content` scans the rest of a paragraph again
+/// after each of its links.
+fn document() -> String {
+    let section = "## Section header
+
+Some *emphasized* and **strong** text with a [link](https://example.com \"title\") and
+`inline code`, followed by a second line with an ![image](picture.png) and a [[wiki link]].
+
+> A quote spanning
+> two lines of text.
+
+- first item
+- second item with *emphasis*
+  1. nested
+  2. list
+
+```rust
+fn main() {
+    println!(\"Hello\");
+}
+```
+
+Term
+: Definition of the term.
+
+Plain HTML paragraph &amp; an entity.
+
+";
+    let links = "Some text with a [link](https://example.com/page) and *more* words here,\n".repeat(LINK_LINES);
+    let page = format!("{section}{links}\n");
+    let abbreviations = "*[HTML]: Hyper Text Markup Language\n\n";
+    abbreviations.to_string() + &page.repeat(SIZE / page.len() + 1)
+}
+
+fn fastest(run: impl Fn() -> usize) -> (Duration, usize) {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let count = run();
+            (start.elapsed(), count)
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let markdown = document();
+    println!("{} bytes, fastest of {RUNS} runs", markdown.len());
+
+    let (elapsed, count) = fastest(|| Document::parse(&markdown).elements().len());
+    println!("Document::parse  {elapsed:>12?}  {count} elements");
+
+    // the old parser skips the page's metadata up to its separator, and panics on an empty block
+    // at the end
+    let page = format!("------\n{}", markdown.trim_end());
+    let (elapsed, count) = fastest(|| baseline::parse_content(&page).elements.len());
+    println!("parse_content    {elapsed:>12?}  {count} elements");
+
+    let (elapsed, count) = fastest(|| Parser::new(&markdown).count());
+    println!("Parser           {elapsed:>12?}  {count} events");
+
+    let (elapsed, count) = fastest(|| {
+        Parser::new(&markdown)
+            .filter(|event| matches!(event, Event::Text(Cow::Borrowed(_))))
+            .count()
+    });
+    println!("borrowed text    {elapsed:>12?}  {count} events");
+}
@@ -0,0 +1,1205 @@
+//! First phase of parsing, splits a document into blocks line by line: quotes, lists, headers,
+//! code, html and paragraphs, following the CommonMark rules for container and leaf blocks. Text
+//! of paragraphs and headers is handed to the inline parser later, by `events::Parser`. Lines are
+//! only read until the next block at the top of the document is finished, and blocks are dropped
+//! once they were walked through.
+
+use crate::inline::{parse_link_reference, unescape, References, CLOSE_TAG, OPEN_TAG};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+/// Syntax the site supports on top of CommonMark.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Extensions {
+    /// `[[page#heading|text]]` links between pages.
+    pub(crate) wiki_links: bool,
+    /// A term followed by `: definition` lines.
+    pub(crate) definition_lists: bool,
+    /// `*[term]: title` lines giving a title to every occurrence of the term.
+    pub(crate) abbreviations: bool,
+    /// Ordered lists marked with `a)`.
+    pub(crate) lettered_lists: bool,
+    /// `[text]{lang=pl}` spans of text in another language.
+    pub(crate) spans: bool,
+}
+
+impl Extensions {
+    pub(crate) const ALL: Extensions = Extensions {
+        wiki_links: true,
+        definition_lists: true,
+        abbreviations: true,
+        lettered_lists: true,
+        spans: true,
+    };
+    /// Plain CommonMark.
+    #[cfg(test)]
+    pub(crate) const NONE: Extensions = Extensions {
+        wiki_links: false,
+        definition_lists: false,
+        abbreviations: false,
+        lettered_lists: false,
+        spans: false,
+    };
+}
+
+pub(crate) const DOCUMENT: usize = 0;
+const CODE_INDENT: usize = 4;
+
+static ABBREVIATION_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\*\[(.+?)\]:\s*(.*)$").expect("Failed to compile abbreviation pattern"));
+
+/// Patterns of the lines starting the seven kinds of html blocks, the first one is unused.
+static HTML_BLOCK_OPEN: Lazy<Vec<Regex>> = Lazy::new(|| {
+    let block_tags = "address|article|aside|base|basefont|blockquote|body|caption|center|col|colgroup|dd|details|\
+                      dialog|dir|div|dl|dt|fieldset|figcaption|figure|footer|form|frame|frameset|h[123456]|head|\
+                      header|hr|html|iframe|legend|li|link|main|menu|menuitem|meta|nav|noframes|ol|optgroup|option|\
+                      p|param|section|source|title|summary|table|tbody|td|tfoot|th|thead|title|tr|track|ul";
+    [
+        "^$".to_string(),
+        r"(?i)^<(?:script|pre|style)(?:\s|>|$)".to_string(),
+        "^<!--".to_string(),
+        r"^<[?]".to_string(),
+        "^<![A-Z]".to_string(),
+        r"^<!\[CDATA\[".to_string(),
+        format!(r"(?i)^</?(?:{block_tags})(?:\s|/?>|$)"),
+        format!(r"(?i)^(?:{OPEN_TAG}|{CLOSE_TAG})\s*$"),
+    ]
+    .iter()
+    .map(|pattern| Regex::new(pattern).expect("Failed to compile html block pattern"))
+    .collect()
+});
+/// Patterns of the lines ending the first five kinds of html blocks, the others end on a blank line.
+static HTML_BLOCK_CLOSE: Lazy<Vec<Regex>> = Lazy::new(|| {
+    ["^$", r"(?i)</(?:script|pre|style)>", "-->", r"\?>", ">", r"\]\]>"]
+        .iter()
+        .map(|pattern| Regex::new(pattern).expect("Failed to compile html block pattern"))
+        .collect()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ListKind {
+    Bullet(u8),
+    /// Numbered with `.` or `)` after the number.
+    Ordered(u8),
+    Lettered,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ListData {
+    pub(crate) kind: ListKind,
+    pub(crate) start: usize,
+    /// Indentation of the marker.
+    marker_offset: usize,
+    /// Width of the marker and the spaces after it, lines of the item are indented by it.
+    padding: usize,
+}
+
+/// Opening line of a fenced code block, the closing one has to use the same character and be at
+/// least as long.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fence {
+    character: u8,
+    length: usize,
+    /// Indentation of the opening fence, removed from the lines of code too.
+    offset: usize,
+}
+
+#[derive(Debug)]
+pub(crate) enum BlockKind {
+    Document,
+    BlockQuote,
+    List {
+        data: ListData,
+        tight: bool,
+    },
+    Item(ListData),
+    Paragraph,
+    Heading(usize),
+    ThematicBreak,
+    /// Code blocks without a fence are indented by four spaces.
+    CodeBlock {
+        info: Content,
+        fence: Option<Fence>,
+    },
+    HtmlBlock(usize),
+}
+
+impl BlockKind {
+    fn can_contain(&self, child: &BlockKind) -> bool {
+        use BlockKind::*;
+        match self {
+            Document | BlockQuote | Item(_) => !matches!(child, Item(_)),
+            List { .. } => matches!(child, Item(_)),
+            _ => false,
+        }
+    }
+
+    fn accepts_lines(&self) -> bool {
+        matches!(
+            self,
+            BlockKind::Paragraph | BlockKind::CodeBlock { .. } | BlockKind::HtmlBlock(_)
+        )
+    }
+}
+
+/// Text of a leaf block, one line of input after another. It stays a range of the input while
+/// the lines follow each other there, and is copied once they don't, e.g. inside of a quote.
+#[derive(Debug)]
+pub(crate) enum Content {
+    Input(Range<usize>),
+    Owned(String),
+}
+
+impl Content {
+    fn text<'a>(&'a self, source: &'a str) -> &'a str {
+        match self {
+            Content::Input(range) => &source[range.clone()],
+            Content::Owned(text) => text,
+        }
+    }
+
+    /// Keeps just the `range` of the text.
+    fn keep(&mut self, range: Range<usize>) {
+        match self {
+            Content::Input(input) => *input = input.start + range.start..input.start + range.end,
+            Content::Owned(text) => {
+                text.truncate(range.end);
+                text.drain(..range.start);
+            }
+        }
+    }
+}
+
+/// Block of the document, linked to its parent and siblings like the nodes of inlines.
+#[derive(Debug)]
+pub(crate) struct Block {
+    pub(crate) kind: BlockKind,
+    pub(crate) parent: Option<usize>,
+    pub(crate) first_child: Option<usize>,
+    last_child: Option<usize>,
+    prev: Option<usize>,
+    pub(crate) next: Option<usize>,
+    open: bool,
+    content: Content,
+    /// Number of lines of a paragraph.
+    lines: usize,
+    /// Lines of a paragraph that are indented, definition lists are told apart by them.
+    indented_lines: Vec<usize>,
+    last_line_blank: bool,
+    last_line_checked: bool,
+    start_line: usize,
+}
+
+impl Block {
+    fn new(kind: BlockKind, parent: Option<usize>, start_line: usize) -> Block {
+        Block {
+            kind,
+            parent,
+            first_child: None,
+            last_child: None,
+            prev: None,
+            next: None,
+            open: true,
+            content: Content::Input(0..0),
+            lines: 0,
+            indented_lines: vec![],
+            last_line_blank: false,
+            last_line_checked: false,
+            start_line,
+        }
+    }
+}
+
+/// How a line continues an open block.
+enum Continuation {
+    Matched,
+    NotMatched,
+    /// The line closed the block and nothing else is left on it.
+    Finished,
+}
+
+/// Kind of block started by a line.
+enum BlockStart {
+    None,
+    Container,
+    Leaf,
+}
+
+pub(crate) struct BlockParser<'a> {
+    source: &'a str,
+    /// Start of the next line of the source.
+    next_line: usize,
+    pub(crate) blocks: Vec<Block>,
+    /// Blocks at the top of the document that are finished but weren't returned yet.
+    finished: VecDeque<usize>,
+    tip: usize,
+    old_tip: usize,
+    last_matched_container: usize,
+    all_closed: bool,
+    line: Cow<'a, str>,
+    /// Byte offset of the line in the source, unless the line had to be changed.
+    line_start: usize,
+    line_number: usize,
+    /// Byte offset into the line.
+    offset: usize,
+    /// Column of the offset, tabs stop every four columns.
+    column: usize,
+    next_nonspace: usize,
+    next_nonspace_column: usize,
+    indent: usize,
+    indented: bool,
+    blank: bool,
+    /// Only some of the columns of the tab at the offset are consumed.
+    partially_consumed_tab: bool,
+    /// The source has `\0` characters, they are replaced in every line.
+    has_nul: bool,
+    /// Start of the last line with a `]:`, which can be a link reference definition or an
+    /// abbreviation. They apply to the text before them too.
+    last_definition: Option<usize>,
+    /// The definitions were collected by reading ahead, so they are skipped when they come up.
+    read_ahead: bool,
+    pub(crate) references: References,
+    pub(crate) abbreviations: Vec<(String, String)>,
+    pub(crate) extensions: Extensions,
+}
+
+impl<'a> BlockParser<'a> {
+    pub(crate) fn new(source: &'a str, extensions: Extensions) -> BlockParser<'a> {
+        BlockParser {
+            source,
+            next_line: 0,
+            blocks: vec![Block::new(BlockKind::Document, None, 1)],
+            finished: VecDeque::new(),
+            tip: DOCUMENT,
+            old_tip: DOCUMENT,
+            last_matched_container: DOCUMENT,
+            all_closed: true,
+            line: Cow::Borrowed(""),
+            line_start: 0,
+            line_number: 0,
+            offset: 0,
+            column: 0,
+            next_nonspace: 0,
+            next_nonspace_column: 0,
+            indent: 0,
+            indented: false,
+            blank: false,
+            partially_consumed_tab: false,
+            has_nul: source.contains('\0'),
+            last_definition: source
+                .rfind("]:")
+                .map(|end| source[..end].rfind('\n').map_or(0, |start| start + 1)),
+            read_ahead: false,
+            references: HashMap::new(),
+            abbreviations: vec![],
+            extensions,
+        }
+    }
+
+    /// Reads lines until a block at the top of the document is finished, and returns it. The
+    /// blocks returned before it are dropped, they have to be walked through by then.
+    pub(crate) fn next_block(&mut self) -> Option<usize> {
+        if self.finished.is_empty() {
+            self.drop_finished_blocks();
+        }
+        while self.finished.is_empty() {
+            if self.next_line < self.source.len() {
+                let start = self.next_line;
+                let end = self.source[start..]
+                    .find('\n')
+                    .map_or(self.source.len(), |end| start + end);
+                let line = &self.source[start..end];
+                self.next_line = end + 1;
+                self.add_line_of_input(line.strip_suffix('\r').unwrap_or(line), start);
+            } else if self.blocks[DOCUMENT].open {
+                loop {
+                    let tip = self.tip;
+                    self.finalize(tip);
+                    if tip == DOCUMENT {
+                        break;
+                    }
+                }
+            } else {
+                return None;
+            }
+        }
+        self.finished.pop_front()
+    }
+
+    /// Makes sure that all link reference definitions and abbreviations of the document are
+    /// known, reading ahead up to the last of them if they might not be.
+    pub(crate) fn read_definitions(&mut self) {
+        if self.definitions_known() {
+            return;
+        }
+        let mut lookahead = BlockParser::new(self.source, self.extensions);
+        while !lookahead.definitions_known() && lookahead.next_block().is_some() {}
+        self.references = lookahead.references;
+        self.abbreviations = lookahead.abbreviations;
+        self.last_definition = None;
+        self.read_ahead = true;
+    }
+
+    /// Blocks started before the current line are finished once a block at the top of the
+    /// document is, so they only can't hold definitions yet when the current line is past the
+    /// last one.
+    fn definitions_known(&self) -> bool {
+        self.last_definition
+            .is_none_or(|line| line < self.line_start || !self.blocks[DOCUMENT].open)
+    }
+
+    /// Drops all blocks but the document and the one still open in it. Blocks are numbered in
+    /// the order they started in, so the open one and its children come after all the others.
+    fn drop_finished_blocks(&mut self) {
+        let open = self.blocks[DOCUMENT].last_child.filter(|&last| self.blocks[last].open);
+        let Some(first) = open else {
+            self.blocks.truncate(DOCUMENT + 1);
+            self.blocks[DOCUMENT].first_child = None;
+            self.blocks[DOCUMENT].last_child = None;
+            return;
+        };
+        let shift = first - (DOCUMENT + 1);
+        if shift == 0 {
+            return;
+        }
+        self.blocks.drain(DOCUMENT + 1..first);
+        // the only link to a dropped block is from the open one to the block before it
+        let moved = |block: Option<usize>| match block {
+            Some(DOCUMENT) => Some(DOCUMENT),
+            Some(block) if block >= first => Some(block - shift),
+            _ => None,
+        };
+        for block in &mut self.blocks[DOCUMENT + 1..] {
+            block.parent = moved(block.parent);
+            block.first_child = moved(block.first_child);
+            block.last_child = moved(block.last_child);
+            block.prev = moved(block.prev);
+            block.next = moved(block.next);
+        }
+        self.blocks[DOCUMENT].first_child = Some(DOCUMENT + 1);
+        self.blocks[DOCUMENT].last_child = Some(DOCUMENT + 1);
+        for block in [&mut self.tip, &mut self.old_tip, &mut self.last_matched_container] {
+            *block = if *block >= first { *block - shift } else { DOCUMENT };
+        }
+    }
+
+    pub(crate) fn content(&self, block: usize) -> &str {
+        self.blocks[block].content.text(self.source)
+    }
+
+    /// Moves the text out of a leaf block, borrowed from the input unless it had to be copied.
+    pub(crate) fn take_content(&mut self, block: usize) -> Cow<'a, str> {
+        let content = std::mem::replace(&mut self.blocks[block].content, Content::Input(0..0));
+        self.text_of(content)
+    }
+
+    /// Info string of a fenced code block.
+    pub(crate) fn take_info(&mut self, block: usize) -> Cow<'a, str> {
+        match &mut self.blocks[block].kind {
+            BlockKind::CodeBlock { info, .. } => {
+                let info = std::mem::replace(info, Content::Input(0..0));
+                self.text_of(info)
+            }
+            _ => Cow::Borrowed(""),
+        }
+    }
+
+    fn text_of(&self, content: Content) -> Cow<'a, str> {
+        match content {
+            Content::Input(range) => Cow::Borrowed(&self.source[range]),
+            Content::Owned(text) => Cow::Owned(text),
+        }
+    }
+
+    fn add_line_of_input(&mut self, line: &'a str, line_start: usize) {
+        self.old_tip = self.tip;
+        self.offset = 0;
+        self.column = 0;
+        self.blank = false;
+        self.partially_consumed_tab = false;
+        self.line_number += 1;
+        self.line = if self.has_nul && line.contains('\0') {
+            Cow::Owned(line.replace('\0', "\u{fffd}"))
+        } else {
+            Cow::Borrowed(line)
+        };
+        self.line_start = line_start;
+
+        // every open block has to be continued by the line, starting from the outermost one
+        let mut container = DOCUMENT;
+        while let Some(last) = self.blocks[container].last_child {
+            if !self.blocks[last].open {
+                break;
+            }
+            container = last;
+            self.find_next_nonspace();
+            match self.continue_block(container) {
+                Continuation::Matched => {}
+                Continuation::NotMatched => {
+                    container = self.blocks[container].parent.unwrap();
+                    break;
+                }
+                Continuation::Finished => return,
+            }
+        }
+        self.all_closed = container == self.old_tip;
+        self.last_matched_container = container;
+
+        let mut matched_leaf =
+            !matches!(self.blocks[container].kind, BlockKind::Paragraph) && self.blocks[container].kind.accepts_lines();
+        while !matched_leaf {
+            self.find_next_nonspace();
+            match self.start_block(container) {
+                BlockStart::None => {
+                    self.advance_next_nonspace();
+                    break;
+                }
+                BlockStart::Container => container = self.tip,
+                BlockStart::Leaf => {
+                    container = self.tip;
+                    matched_leaf = true;
+                }
+            }
+        }
+
+        // what is left of the line is text
+        if !self.all_closed && !self.blank && matches!(self.blocks[self.tip].kind, BlockKind::Paragraph) {
+            // lazy continuation of a paragraph
+            self.add_line();
+            return;
+        }
+        self.close_unmatched_blocks();
+        if self.blank {
+            if let Some(last) = self.blocks[container].last_child {
+                self.blocks[last].last_line_blank = true;
+            }
+        }
+        let block = &self.blocks[container];
+        // blank lines in fenced code and right after an empty list item don't make a list loose
+        let last_line_blank = self.blank
+            && !(matches!(
+                block.kind,
+                BlockKind::BlockQuote | BlockKind::CodeBlock { fence: Some(_), .. }
+            ) || (matches!(block.kind, BlockKind::Item(_))
+                && block.first_child.is_none()
+                && block.start_line == self.line_number));
+        let mut parent = Some(container);
+        while let Some(block) = parent {
+            self.blocks[block].last_line_blank = last_line_blank;
+            parent = self.blocks[block].parent;
+        }
+
+        if self.blocks[container].kind.accepts_lines() {
+            self.add_line();
+            if let BlockKind::HtmlBlock(html_kind @ 1..=5) = self.blocks[container].kind {
+                if HTML_BLOCK_CLOSE[html_kind].is_match(&self.line[self.offset..]) {
+                    self.finalize(container);
+                }
+            }
+        } else if self.offset < self.line.len() && !self.blank {
+            self.add_child(BlockKind::Paragraph);
+            self.advance_next_nonspace();
+            self.add_line();
+        }
+    }
+
+    fn find_next_nonspace(&mut self) {
+        let bytes = self.line.as_bytes();
+        let mut i = self.offset;
+        let mut column = self.column;
+        while let Some(&c) = bytes.get(i) {
+            match c {
+                b' ' => column += 1,
+                b'\t' => column += 4 - column % 4,
+                _ => break,
+            }
+            i += 1;
+        }
+        self.blank = i == bytes.len();
+        self.next_nonspace = i;
+        self.next_nonspace_column = column;
+        self.indent = column - self.column;
+        self.indented = self.indent >= CODE_INDENT;
+    }
+
+    fn advance_next_nonspace(&mut self) {
+        self.offset = self.next_nonspace;
+        self.column = self.next_nonspace_column;
+        self.partially_consumed_tab = false;
+    }
+
+    /// Advances by `count` characters, or by `count` columns when `columns` is set, in which case
+    /// a tab can be consumed only partially.
+    fn advance_offset(&mut self, mut count: usize, columns: bool) {
+        let bytes = self.line.as_bytes();
+        while count > 0 && self.offset < bytes.len() {
+            if bytes[self.offset] == b'\t' {
+                let to_tab_stop = 4 - self.column % 4;
+                if columns {
+                    self.partially_consumed_tab = to_tab_stop > count;
+                    let advance = to_tab_stop.min(count);
+                    self.column += advance;
+                    if !self.partially_consumed_tab {
+                        self.offset += 1;
+                    }
+                    count -= advance;
+                } else {
+                    self.partially_consumed_tab = false;
+                    self.column += to_tab_stop;
+                    self.offset += 1;
+                    count -= 1;
+                }
+            } else {
+                self.partially_consumed_tab = false;
+                self.offset += 1;
+                self.column += 1;
+                count -= 1;
+            }
+        }
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.line.as_bytes().get(offset).copied()
+    }
+
+    fn add_line(&mut self) {
+        let tip = &mut self.blocks[self.tip];
+        if matches!(tip.kind, BlockKind::Paragraph) {
+            if self.indent > 0 {
+                tip.indented_lines.push(tip.lines);
+            }
+            tip.lines += 1;
+        }
+        let text = &self.line[self.offset..];
+        let start = self.line_start + self.offset;
+        let follows_in_input = matches!(self.line, Cow::Borrowed(_))
+            && !self.partially_consumed_tab
+            && self.source.as_bytes().get(start + text.len()) == Some(&b'\n');
+        if let Content::Input(range) = &mut tip.content {
+            let empty = range.start == range.end;
+            if follows_in_input && (empty || range.end == start) {
+                *range = if empty { start } else { range.start }..start + text.len() + 1;
+                return;
+            }
+        }
+        let mut content = match std::mem::replace(&mut tip.content, Content::Input(0..0)) {
+            Content::Input(range) => self.source[range].to_string(),
+            Content::Owned(content) => content,
+        };
+        if self.partially_consumed_tab {
+            // the rest of the tab becomes spaces
+            self.offset += 1;
+            let to_tab_stop = 4 - self.column % 4;
+            content.push_str(&" ".repeat(to_tab_stop));
+        }
+        content.push_str(&self.line[self.offset..]);
+        content.push('\n');
+        tip.content = Content::Owned(content);
+    }
+
+    /// Text of the current line between `start` and `end`, as a range of the input if possible.
+    fn line_content(&self, start: usize, end: usize) -> Content {
+        match self.line {
+            Cow::Borrowed(_) => Content::Input(self.line_start + start..self.line_start + end),
+            Cow::Owned(_) => Content::Owned(self.line[start..end].to_string()),
+        }
+    }
+
+    fn add_child(&mut self, kind: BlockKind) -> usize {
+        while !self.blocks[self.tip].kind.can_contain(&kind) {
+            self.finalize(self.tip);
+        }
+        let block = self.blocks.len();
+        let parent = self.tip;
+        self.blocks.push(Block::new(kind, Some(parent), self.line_number));
+        self.blocks[block].prev = self.blocks[parent].last_child;
+        match self.blocks[parent].last_child {
+            Some(last) => self.blocks[last].next = Some(block),
+            None => self.blocks[parent].first_child = Some(block),
+        }
+        self.blocks[parent].last_child = Some(block);
+        self.tip = block;
+        block
+    }
+
+    /// Takes a block out of its parent, which it still points to.
+    fn unlink(&mut self, block: usize) {
+        let Block { parent, prev, next, .. } = self.blocks[block];
+        match prev {
+            Some(prev) => self.blocks[prev].next = next,
+            None => {
+                if let Some(parent) = parent {
+                    self.blocks[parent].first_child = next;
+                }
+            }
+        }
+        match next {
+            Some(next) => self.blocks[next].prev = prev,
+            None => {
+                if let Some(parent) = parent {
+                    self.blocks[parent].last_child = prev;
+                }
+            }
+        }
+    }
+
+    fn close_unmatched_blocks(&mut self) {
+        if self.all_closed {
+            return;
+        }
+        while self.old_tip != self.last_matched_container {
+            let parent = self.blocks[self.old_tip].parent.unwrap();
+            self.finalize(self.old_tip);
+            self.old_tip = parent;
+        }
+        self.all_closed = true;
+    }
+
+    fn continue_block(&mut self, container: usize) -> Continuation {
+        match self.blocks[container].kind {
+            BlockKind::Document | BlockKind::List { .. } => Continuation::Matched,
+            BlockKind::BlockQuote => {
+                if self.indented || self.peek_at(self.next_nonspace) != Some(b'>') {
+                    return Continuation::NotMatched;
+                }
+                self.advance_next_nonspace();
+                self.advance_offset(1, false);
+                if matches!(self.peek_at(self.offset), Some(b' ' | b'\t')) {
+                    self.advance_offset(1, true);
+                }
+                Continuation::Matched
+            }
+            BlockKind::Item(data) => {
+                if self.blank {
+                    if self.blocks[container].first_child.is_none() {
+                        // an item can begin with at most one blank line
+                        return Continuation::NotMatched;
+                    }
+                    self.advance_next_nonspace();
+                } else if self.indent >= data.marker_offset + data.padding {
+                    self.advance_offset(data.marker_offset + data.padding, true);
+                } else {
+                    return Continuation::NotMatched;
+                }
+                Continuation::Matched
+            }
+            BlockKind::Heading(_) | BlockKind::ThematicBreak => Continuation::NotMatched,
+            BlockKind::CodeBlock { fence: None, .. } => {
+                if self.indented {
+                    self.advance_offset(CODE_INDENT, true);
+                } else if self.blank {
+                    self.advance_next_nonspace();
+                } else {
+                    return Continuation::NotMatched;
+                }
+                Continuation::Matched
+            }
+            BlockKind::CodeBlock { fence: Some(fence), .. } => {
+                let closing = closing_code_fence(&self.line[self.next_nonspace..]);
+                if closing.is_some_and(|(character, length)| {
+                    !self.indented && character == fence.character && length >= fence.length
+                }) {
+                    self.finalize(container);
+                    return Continuation::Finished;
+                }
+                // lines of code lose as much of their indentation as the opening fence had
+                let mut offset = fence.offset;
+                while offset > 0 && matches!(self.peek_at(self.offset), Some(b' ' | b'\t')) {
+                    self.advance_offset(1, true);
+                    offset -= 1;
+                }
+                Continuation::Matched
+            }
+            BlockKind::HtmlBlock(html_kind) => {
+                if self.blank && (html_kind == 6 || html_kind == 7) {
+                    Continuation::NotMatched
+                } else {
+                    Continuation::Matched
+                }
+            }
+            BlockKind::Paragraph => {
+                if self.blank {
+                    Continuation::NotMatched
+                } else {
+                    Continuation::Matched
+                }
+            }
+        }
+    }
+
+    fn start_block(&mut self, container: usize) -> BlockStart {
+        // cloning the line is free unless it had to be changed
+        let line = self.line.clone();
+        let rest = &line[self.next_nonspace..];
+        let is_paragraph = matches!(self.blocks[container].kind, BlockKind::Paragraph);
+        if !self.indented && !could_start_block(rest, self.extensions) {
+            return BlockStart::None;
+        }
+
+        if !self.indented && rest.starts_with('>') {
+            self.advance_next_nonspace();
+            self.advance_offset(1, false);
+            if matches!(self.peek_at(self.offset), Some(b' ' | b'\t')) {
+                self.advance_offset(1, true);
+            }
+            self.close_unmatched_blocks();
+            self.add_child(BlockKind::BlockQuote);
+            return BlockStart::Container;
+        }
+
+        if let Some((level, marker_len)) = atx_heading_marker(rest).filter(|_| !self.indented) {
+            self.advance_next_nonspace();
+            self.advance_offset(marker_len, false);
+            self.close_unmatched_blocks();
+            let heading = self.add_child(BlockKind::Heading(level));
+            let len = atx_heading_len(&self.line[self.offset..]);
+            self.blocks[heading].content = self.line_content(self.offset, self.offset + len);
+            self.advance_offset(self.line.len() - self.offset, false);
+            return BlockStart::Leaf;
+        }
+
+        if let Some(length) = code_fence(rest).filter(|_| !self.indented) {
+            let fence = Fence {
+                character: rest.as_bytes()[0],
+                length,
+                offset: self.indent,
+            };
+            // info string of a backtick fence can't contain backticks, it would be inline code
+            if fence.character != b'`' || !rest[fence.length..].contains('`') {
+                self.close_unmatched_blocks();
+                self.add_child(BlockKind::CodeBlock {
+                    info: Content::Input(0..0),
+                    fence: Some(fence),
+                });
+                self.advance_next_nonspace();
+                self.advance_offset(fence.length, false);
+                return BlockStart::Leaf;
+            }
+        }
+
+        if !self.indented && rest.starts_with('<') {
+            let html_kind = (1..=7)
+                .find(|&html_kind| HTML_BLOCK_OPEN[html_kind].is_match(rest) && (html_kind < 7 || !is_paragraph));
+            if let Some(html_kind) = html_kind {
+                self.close_unmatched_blocks();
+                // the indentation is part of the html
+                self.add_child(BlockKind::HtmlBlock(html_kind));
+                return BlockStart::Leaf;
+            }
+        }
+
+        if let Some(level) = setext_heading_level(rest).filter(|_| !self.indented && is_paragraph) {
+            self.close_unmatched_blocks();
+            self.take_link_references(container);
+            if !self.content(container).is_empty() {
+                self.blocks[container].kind = BlockKind::Heading(level);
+                self.advance_offset(self.line.len() - self.offset, false);
+                return BlockStart::Leaf;
+            }
+        }
+
+        if !self.indented && is_thematic_break(rest) {
+            self.close_unmatched_blocks();
+            self.add_child(BlockKind::ThematicBreak);
+            self.advance_offset(self.line.len() - self.offset, false);
+            return BlockStart::Leaf;
+        }
+
+        let is_list = matches!(self.blocks[container].kind, BlockKind::List { .. });
+        if !self.indented || is_list {
+            if let Some(data) = self.parse_list_marker(container) {
+                self.close_unmatched_blocks();
+                let continues_list = match &self.blocks[self.tip].kind {
+                    BlockKind::List { data: list_data, .. } => list_data.kind == data.kind,
+                    _ => false,
+                };
+                if !continues_list {
+                    self.add_child(BlockKind::List { data, tight: true });
+                }
+                self.add_child(BlockKind::Item(data));
+                return BlockStart::Container;
+            }
+        }
+
+        // indented lines can't interrupt a paragraph, they continue it
+        if self.indented && !self.blank && !matches!(self.blocks[self.tip].kind, BlockKind::Paragraph) {
+            self.advance_offset(CODE_INDENT, true);
+            self.close_unmatched_blocks();
+            self.add_child(BlockKind::CodeBlock {
+                info: Content::Input(0..0),
+                fence: None,
+            });
+            return BlockStart::Leaf;
+        }
+
+        BlockStart::None
+    }
+
+    /// Marker of a list item at the start of the line and the spaces following it.
+    fn parse_list_marker(&mut self, container: usize) -> Option<ListData> {
+        if self.indent >= CODE_INDENT {
+            return None;
+        }
+        let rest = &self.line[self.next_nonspace..];
+        let interrupts_paragraph = matches!(self.blocks[container].kind, BlockKind::Paragraph);
+        let bytes = rest.as_bytes();
+        let digits = bytes.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (kind, start, marker_len) = match bytes.first().copied() {
+            Some(bullet @ (b'*' | b'+' | b'-')) => (ListKind::Bullet(bullet), 1, 1),
+            Some(b'0'..=b'9') if digits <= 9 && matches!(bytes.get(digits), Some(b'.' | b')')) => {
+                // only a list starting at 1 can interrupt a paragraph
+                if interrupts_paragraph && &rest[..digits] != "1" {
+                    return None;
+                }
+                (
+                    ListKind::Ordered(bytes[digits]),
+                    rest[..digits].parse().unwrap(),
+                    digits + 1,
+                )
+            }
+            Some(letter @ b'a'..=b'z') if self.extensions.lettered_lists && bytes.get(1) == Some(&b')') => {
+                if interrupts_paragraph && letter != b'a' {
+                    return None;
+                }
+                (ListKind::Lettered, usize::from(letter - b'a') + 1, 2)
+            }
+            _ => return None,
+        };
+        let after_marker = &rest[marker_len..];
+        if !(after_marker.is_empty() || after_marker.starts_with([' ', '\t'])) {
+            return None;
+        }
+        if interrupts_paragraph && after_marker.trim().is_empty() {
+            return None;
+        }
+
+        self.advance_next_nonspace();
+        self.advance_offset(marker_len, true);
+        let spaces_start_column = self.column;
+        let spaces_start_offset = self.offset;
+        loop {
+            self.advance_offset(1, true);
+            let next = self.peek_at(self.offset);
+            if self.column - spaces_start_column >= 5 || !matches!(next, Some(b' ' | b'\t')) {
+                break;
+            }
+        }
+        let blank_item = self.peek_at(self.offset).is_none();
+        let spaces_after_marker = self.column - spaces_start_column;
+        let padding = if !(1..5).contains(&spaces_after_marker) || blank_item {
+            // content starts right after the marker and a single space, the rest is indentation
+            self.column = spaces_start_column;
+            self.offset = spaces_start_offset;
+            if matches!(self.peek_at(self.offset), Some(b' ' | b'\t')) {
+                self.advance_offset(1, true);
+            }
+            marker_len + 1
+        } else {
+            marker_len + spaces_after_marker
+        };
+        Some(ListData {
+            kind,
+            start,
+            marker_offset: self.indent,
+            padding,
+        })
+    }
+
+    fn finalize(&mut self, block: usize) {
+        self.blocks[block].open = false;
+        let parent = self.blocks[block].parent;
+        let mut removed = false;
+        match self.blocks[block].kind {
+            BlockKind::Paragraph => {
+                self.take_link_references(block);
+                if self.extensions.abbreviations {
+                    self.take_abbreviations(block);
+                }
+                if self.content(block).trim().is_empty() {
+                    self.unlink(block);
+                    removed = true;
+                }
+            }
+            BlockKind::CodeBlock { fence: Some(fence), .. } => {
+                let content = self.content(block);
+                let (line, code_start) = match content.find('\n') {
+                    Some(end) => (&content[..end], end + 1),
+                    None => (content, content.len()),
+                };
+                let info_start = line.len() - line.trim_start().len();
+                let info_end = info_start + line.trim().len();
+                let info = match (unescape(line.trim()), &self.blocks[block].content) {
+                    (Cow::Borrowed(_), Content::Input(range)) => {
+                        Content::Input(range.start + info_start..range.start + info_end)
+                    }
+                    (info, _) => Content::Owned(info.into_owned()),
+                };
+                let len = content.len();
+                self.blocks[block].kind = BlockKind::CodeBlock {
+                    info,
+                    fence: Some(fence),
+                };
+                self.blocks[block].content.keep(code_start..len);
+            }
+            BlockKind::CodeBlock { fence: None, .. } => {
+                // trailing blank lines belong to the blocks after the code
+                let mut code_end = 0;
+                let mut line_end = 0;
+                for line in self.content(block).split_inclusive('\n') {
+                    line_end += line.len();
+                    if !line.trim().is_empty() {
+                        code_end = line_end;
+                    }
+                }
+                self.blocks[block].content.keep(0..code_end);
+            }
+            BlockKind::HtmlBlock(_) => {
+                let len = self.content(block).trim_end_matches([' ', '\n']).len();
+                self.blocks[block].content.keep(0..len);
+            }
+            BlockKind::List { data, .. } => {
+                let tight = self.is_tight(block);
+                self.blocks[block].kind = BlockKind::List { data, tight };
+            }
+            _ => {}
+        }
+        self.tip = parent.unwrap_or(DOCUMENT);
+        if parent == Some(DOCUMENT) && !removed {
+            self.finished.push_back(block);
+        }
+    }
+
+    /// Moves link reference definitions from the start of a paragraph to `references`.
+    fn take_link_references(&mut self, paragraph: usize) {
+        let block = &mut self.blocks[paragraph];
+        let content = block.content.text(self.source);
+        let mut taken = 0;
+        while content[taken..].starts_with('[') {
+            let len = parse_link_reference(&content[taken..], &mut self.references);
+            if len == 0 {
+                break;
+            }
+            taken += len;
+        }
+        if taken == 0 {
+            return;
+        }
+        let lines = content[..taken].matches('\n').count();
+        let len = content.len();
+        block.content.keep(taken..len);
+        block.lines = block.lines.saturating_sub(lines);
+        block.indented_lines.retain(|&line| line >= lines);
+        for line in &mut block.indented_lines {
+            *line -= lines;
+        }
+    }
+
+    /// Moves `*[term]: title` lines of a paragraph to `abbreviations`.
+    fn take_abbreviations(&mut self, paragraph: usize) {
+        let block = &mut self.blocks[paragraph];
+        let text = block.content.text(self.source);
+        if !text.contains("*[") || !text.lines().any(|line| line.trim_start().starts_with("*[")) {
+            return;
+        }
+        let mut content = String::new();
+        let mut lines = 0;
+        let mut indented_lines = vec![];
+        for (i, line) in text.lines().enumerate() {
+            match ABBREVIATION_PATTERN.captures(line.trim()) {
+                Some(_) if self.read_ahead => {}
+                Some(capture) => self
+                    .abbreviations
+                    .push((capture[1].to_string(), capture[2].trim().to_string())),
+                None => {
+                    content.push_str(line);
+                    content.push('\n');
+                    if block.indented_lines.contains(&i) {
+                        indented_lines.push(lines);
+                    }
+                    lines += 1;
+                }
+            }
+        }
+        block.content = Content::Owned(content);
+        block.lines = lines;
+        block.indented_lines = indented_lines;
+    }
+
+    /// A list is loose when any of its items are separated by blank lines, or when an item
+    /// directly contains blocks separated by them.
+    fn is_tight(&mut self, list: usize) -> bool {
+        let mut next_item = self.blocks[list].first_child;
+        while let Some(item) = next_item {
+            next_item = self.blocks[item].next;
+            let is_last_item = next_item.is_none();
+            if self.ends_with_blank_line(item) && !is_last_item {
+                return false;
+            }
+            let mut next_child = self.blocks[item].first_child;
+            while let Some(child) = next_child {
+                next_child = self.blocks[child].next;
+                let is_last_child = next_child.is_none();
+                if self.ends_with_blank_line(child) && !(is_last_item && is_last_child) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn ends_with_blank_line(&mut self, mut block: usize) -> bool {
+        loop {
+            if self.blocks[block].last_line_blank {
+                return true;
+            }
+            let checked = self.blocks[block].last_line_checked;
+            self.blocks[block].last_line_checked = true;
+            match (&self.blocks[block].kind, self.blocks[block].last_child) {
+                (BlockKind::List { .. } | BlockKind::Item(_), Some(last)) if !checked => block = last,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Whether a paragraph is a term followed by definitions starting with `: `.
+    pub(crate) fn is_definition_list(&self, paragraph: usize) -> bool {
+        if !matches!(self.blocks[paragraph].kind, BlockKind::Paragraph) {
+            return false;
+        }
+        let content = self.content(paragraph);
+        !content.starts_with(':')
+            && content
+                .find('\n')
+                .is_some_and(|end| content[end + 1..].starts_with(": "))
+    }
+
+    /// Takes the terms and definitions out of a paragraph that is a definition list.
+    pub(crate) fn take_definition_list(&mut self, paragraph: usize) -> DefinitionList<'a> {
+        DefinitionList {
+            indented_lines: std::mem::take(&mut self.blocks[paragraph].indented_lines),
+            content: self.take_content(paragraph),
+            next_line: 0,
+            line: 0,
+        }
+    }
+}
+
+/// Terms and definitions of a paragraph that is a definition list, one at a time. Indented lines
+/// continue the definition before them, other lines are new terms.
+pub(crate) struct DefinitionList<'a> {
+    content: Cow<'a, str>,
+    indented_lines: Vec<usize>,
+    /// Start of the next line of the content, and its number.
+    next_line: usize,
+    line: usize,
+}
+
+impl DefinitionList<'_> {
+    /// End of the line starting at `next_line`, which moves to the line after it.
+    fn read_line(&mut self) -> usize {
+        let start = self.next_line;
+        let end = self.content[start..]
+            .find('\n')
+            .map_or(self.content.len(), |end| start + end);
+        self.next_line = end + 1;
+        self.line += 1;
+        end
+    }
+}
+
+impl<'a> Iterator for DefinitionList<'a> {
+    /// Whether the text is a definition rather than a term, and the text.
+    type Item = (bool, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<(bool, Cow<'a, str>)> {
+        if self.next_line >= self.content.len() {
+            return None;
+        }
+        let mut start = self.next_line;
+        let mut end = self.read_line();
+        let is_definition = self.content[start..end].starts_with(": ");
+        if is_definition {
+            start += 2;
+            while self.next_line < self.content.len()
+                && self.indented_lines.contains(&self.line)
+                && !self.content[self.next_line..].starts_with(": ")
+            {
+                end = self.read_line();
+            }
+        }
+        let text = match &self.content {
+            Cow::Borrowed(content) => Cow::Borrowed(&content[start..end]),
+            Cow::Owned(content) => Cow::Owned(content[start..end].to_string()),
+        };
+        Some((is_definition, text))
+    }
+}
+
+/// Length of the text of an ATX heading after the opening `#`s, without the optional closing ones.
+fn atx_heading_len(rest: &str) -> usize {
+    let text = rest.trim_end_matches([' ', '\t']);
+    let without_closing = text.trim_end_matches('#');
+    if without_closing.trim_start_matches([' ', '\t']).is_empty() {
+        0
+    } else if without_closing.ends_with([' ', '\t']) {
+        without_closing.trim_end_matches([' ', '\t']).len()
+    } else {
+        text.len()
+    }
+}
+
+/// Whether a line could start a block other than a paragraph, checked before the patterns of
+/// all the blocks since most lines are text.
+fn could_start_block(rest: &str, extensions: Extensions) -> bool {
+    let bytes = rest.as_bytes();
+    match bytes.first() {
+        Some(b'>' | b'#' | b'`' | b'~' | b'<' | b'=' | b'-' | b'*' | b'_' | b'+' | b'0'..=b'9') => true,
+        Some(b'a'..=b'z') => extensions.lettered_lists && bytes.get(1) == Some(&b')'),
+        _ => false,
+    }
+}
+
+/// Level of the heading started by the `#`s at the start of the line, and their length with the
+/// spaces after them.
+fn atx_heading_marker(rest: &str) -> Option<(usize, usize)> {
+    let level = rest.bytes().take_while(|&c| c == b'#').count();
+    let spaces = rest[level..].bytes().take_while(|&c| matches!(c, b' ' | b'\t')).count();
+    ((1..=6).contains(&level) && (spaces > 0 || level == rest.len())).then_some((level, level + spaces))
+}
+
+/// Level of the heading underlined by a line of `=` or `-`.
+fn setext_heading_level(rest: &str) -> Option<usize> {
+    let underline = rest.trim_end_matches([' ', '\t']);
+    match underline.as_bytes().first() {
+        Some(&c @ (b'=' | b'-')) if underline.bytes().all(|other| other == c) => Some(if c == b'=' { 1 } else { 2 }),
+        _ => None,
+    }
+}
+
+/// Three or more `*`, `_` or `-`, possibly with spaces between them.
+fn is_thematic_break(rest: &str) -> bool {
+    let Some(&c @ (b'*' | b'_' | b'-')) = rest.as_bytes().first() else {
+        return false;
+    };
+    rest.bytes().all(|other| matches!(other, b' ' | b'\t') || other == c)
+        && rest.bytes().filter(|&other| other == c).count() >= 3
+}
+
+/// Length of the three or more backticks or tildes opening a fenced code block.
+fn code_fence(rest: &str) -> Option<usize> {
+    let c = *rest.as_bytes().first().filter(|&&c| c == b'`' || c == b'~')?;
+    let length = rest.bytes().take_while(|&other| other == c).count();
+    (length >= 3).then_some(length)
+}
+
+/// Character and length of a fence closing a code block, nothing but spaces can follow it.
+fn closing_code_fence(rest: &str) -> Option<(u8, usize)> {
+    let length = code_fence(rest)?;
+    rest[length..]
+        .bytes()
+        .all(|c| c == b' ')
+        .then(|| (rest.as_bytes()[0], length))
+}
@@ -0,0 +1,111 @@
+//! Runs the examples of the CommonMark spec (`spec/commonmark-0.28.txt`, CC-BY-SA 4.0) against
+//! the parser with the site extensions turned off. Run with `--nocapture` to see the pass rate
+//! of every section.
+
+use crate::block::Extensions;
+use crate::events::parse_document;
+use crate::html::render_html;
+use crate::markdown::Document;
+
+const SPEC: &str = include_str!("../spec/commonmark-0.28.txt");
+const EXAMPLE_FENCE: &str = "```````````````````````````````` example";
+const END_FENCE: &str = "````````````````````````````````";
+
+/// Number of examples known to pass, raise it when the parser gets better.
+const PASSING_EXAMPLES: usize = 633;
+
+/// Sections that have to pass as a whole.
+const PASSING_SECTIONS: &[&str] = &[
+    "Tabs",
+    "Precedence",
+    "Indented code blocks",
+    "Fenced code blocks",
+    "Blank lines",
+    "Inlines",
+    "Emphasis and strong emphasis",
+    "Links",
+    "Images",
+    "Autolinks",
+    "Raw HTML",
+    "Hard line breaks",
+    "Soft line breaks",
+    "Textual content",
+];
+
+struct Example {
+    number: usize,
+    section: String,
+    markdown: String,
+    html: String,
+}
+
+fn examples() -> Vec<Example> {
+    let mut examples = Vec::new();
+    let mut section = String::new();
+    let mut lines = SPEC.lines();
+    while let Some(line) = lines.next() {
+        if let Some(heading) = line.strip_prefix('#') {
+            section = heading.trim_start_matches('#').trim().to_string();
+        }
+        if line != EXAMPLE_FENCE {
+            continue;
+        }
+        let mut markdown = String::new();
+        for line in lines.by_ref().take_while(|line| *line != ".") {
+            markdown.push_str(line);
+            markdown.push('\n');
+        }
+        let mut html = String::new();
+        for line in lines.by_ref().take_while(|line| *line != END_FENCE) {
+            html.push_str(line);
+            html.push('\n');
+        }
+        examples.push(Example {
+            number: examples.len() + 1,
+            section: section.clone(),
+            markdown: markdown.replace('→', "\t"),
+            html: html.replace('→', "\t"),
+        });
+    }
+    examples
+}
+
+#[test]
+fn test_commonmark_spec() {
+    let examples = examples();
+    assert_eq!(633, examples.len());
+    let mut passed = 0;
+    let mut sections: Vec<(String, usize, Vec<usize>)> = Vec::new();
+    for example in &examples {
+        let document = Document {
+            elements: parse_document(&example.markdown, Extensions::NONE),
+        };
+        let html = render_html(&document);
+        if sections
+            .last()
+            .is_none_or(|(section, _, _)| *section != example.section)
+        {
+            sections.push((example.section.clone(), 0, Vec::new()));
+        }
+        let (_, section_passed, failed) = sections.last_mut().unwrap();
+        if html == example.html {
+            passed += 1;
+            *section_passed += 1;
+        } else {
+            failed.push(example.number);
+        }
+    }
+    for (section, section_passed, failed) in &sections {
+        println!(
+            "{section}: {section_passed}/{}, failed: {failed:?}",
+            section_passed + failed.len()
+        );
+    }
+    println!("passed {passed} out of {} examples", examples.len());
+    for (section, _, failed) in &sections {
+        if PASSING_SECTIONS.contains(&section.as_str()) {
+            assert!(failed.is_empty(), "examples {failed:?} of {section} failed");
+        }
+    }
+    assert!(passed >= PASSING_EXAMPLES, "only {passed} examples passed");
+}
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+/// Site wide settings used when loading markdown pages.
+#[derive(Debug)]
+pub struct Config {
+    /// Replacements applied to the text of every page after emoji shortcodes, e.g. `(c)` → `©` or
+    /// `:ferris:` → `<img src="ferris.svg">`. Replacements are inserted as they are, so they can
+    /// contain html.
+    pub substitutions: HashMap<String, String>,
+    /// Directory of element templates used by pages that don't set `element_templates` in their
+    /// metadata, e.g. the one of a theme. Elements without a template there use the ones from
+    /// `templates/elements/`, or the built-in ones.
+    pub element_templates: Option<String>,
+    /// Reading speed used for the `time_to_read` of pages.
+    pub words_per_minute: usize,
+    /// Reading speed of code blocks and inline code, usually slower than of text.
+    pub code_words_per_minute: usize,
+    /// Adds a link to itself next to every header, rendered with the `permalink` element template
+    /// wherever the `header` template puts `{{ $permalink$ }}`. Pages can opt out with
+    /// `permalinks: false`.
+    pub permalinks: bool,
+    /// Language of pages that don't set `lang` in their metadata, e.g. `pl`. Templates get it as
+    /// `lang`, together with `dir` of `ltr` or `rtl` derived from it.
+    pub lang: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            substitutions: HashMap::new(),
+            element_templates: None,
+            words_per_minute: 200,
+            code_words_per_minute: 100,
+            permalinks: false,
+            lang: "en".to_string(),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::Path;
+use webdotx::Template;
+
+/// Directory of the site's element templates, used when a page doesn't choose its own.
+const SITE_DIRECTORY: &str = "templates/elements";
+
+/// Templates embedded in the crate, used for elements that have no template on disk.
+static BUILTIN_TEMPLATES: Lazy<HashMap<&'static str, Template>> = Lazy::new(|| {
+    [
+        ("abbreviation", include_str!("../templates/elements/abbreviation.html")),
+        ("blockquote", include_str!("../templates/elements/blockquote.html")),
+        ("code", include_str!("../templates/elements/code.html")),
+        ("definition", include_str!("../templates/elements/definition.html")),
+        (
+            "definition_list",
+            include_str!("../templates/elements/definition_list.html"),
+        ),
+        (
+            "definition_term",
+            include_str!("../templates/elements/definition_term.html"),
+        ),
+        ("emphasis", include_str!("../templates/elements/emphasis.html")),
+        ("header", include_str!("../templates/elements/header.html")),
+        ("image", include_str!("../templates/elements/image.html")),
+        ("inline_code", include_str!("../templates/elements/inline_code.html")),
+        ("link", include_str!("../templates/elements/link.html")),
+        ("list_item", include_str!("../templates/elements/list_item.html")),
+        ("ordered_list", include_str!("../templates/elements/ordered_list.html")),
+        ("paragraph", include_str!("../templates/elements/paragraph.html")),
+        ("permalink", include_str!("../templates/elements/permalink.html")),
+        ("span", include_str!("../templates/elements/span.html")),
+        ("strong", include_str!("../templates/elements/strong.html")),
+        (
+            "thematic_break",
+            include_str!("../templates/elements/thematic_break.html"),
+        ),
+        (
+            "unordered_list",
+            include_str!("../templates/elements/unordered_list.html"),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, content)| (name, Template::new(content)))
+    .collect()
+});
+
+/// Finds the template of an element, e.g. `header` or `code`. Templates are looked up in the
+/// directory chosen by the page with `element_templates: templates/elements/slides/`, then in
+/// `templates/elements/` and at last among the built-in ones.
+pub(crate) struct ElementTemplates<'a> {
+    templates: &'a HashMap<String, Template>,
+    directory: Option<&'a str>,
+}
+
+impl<'a> ElementTemplates<'a> {
+    pub(crate) fn new(templates: &'a HashMap<String, Template>, directory: Option<&'a str>) -> ElementTemplates<'a> {
+        ElementTemplates { templates, directory }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> &'a Template {
+        let file_name = format!("{name}.html");
+        self.directory
+            .into_iter()
+            .chain([SITE_DIRECTORY])
+            .find_map(|directory| {
+                let path = Path::new(directory).join(&file_name);
+                self.templates.get(path.to_str()?)
+            })
+            .unwrap_or_else(|| {
+                BUILTIN_TEMPLATES
+                    .get(name)
+                    .unwrap_or_else(|| panic!("{name} is not an element template"))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ElementTemplates;
+    use std::collections::HashMap;
+    use webdotx::{Registry, Template};
+
+    fn fill(templates: &ElementTemplates, name: &str) -> String {
+        let placeholders = HashMap::from([("content".to_string(), "text".to_string())]);
+        templates
+            .get(name)
+            .fill_template(placeholders, &Registry::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_element_template_lookup() {
+        let templates = HashMap::from([
+            (
+                "templates/elements/paragraph.html".to_string(),
+                Template::new(r#"<p class="site">{{ $content$ }}</p>"#),
+            ),
+            (
+                "templates/elements/slides/paragraph.html".to_string(),
+                Template::new(r#"<p class="slide">{{ $content$ }}</p>"#),
+            ),
+        ]);
+
+        let site = ElementTemplates::new(&templates, None);
+        assert_eq!(r#"<p class="site">text</p>"#, fill(&site, "paragraph"));
+        assert_eq!("<strong>text</strong>\n", fill(&site, "strong"));
+
+        let slides = ElementTemplates::new(&templates, Some("templates/elements/slides/"));
+        assert_eq!(r#"<p class="slide">text</p>"#, fill(&slides, "paragraph"));
+        assert_eq!("<strong>text</strong>\n", fill(&slides, "strong"));
+
+        let missing = ElementTemplates::new(&templates, Some("templates/elements/missing"));
+        assert_eq!(r#"<p class="site">text</p>"#, fill(&missing, "paragraph"));
+    }
+}
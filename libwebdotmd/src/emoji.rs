@@ -0,0 +1,249 @@
+/// Shortcodes (without the surrounding colons) of emoji commonly used on GitHub and Slack, sorted
+/// by shortcode so they can be binary searched.
+pub(crate) const EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("airplane", "✈️"),
+    ("alarm_clock", "⏰"),
+    ("alien", "👽"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("art", "🎨"),
+    ("baby", "👶"),
+    ("balloon", "🎈"),
+    ("bangbang", "‼️"),
+    ("bar_chart", "📊"),
+    ("battery", "🔋"),
+    ("beer", "🍺"),
+    ("beers", "🍻"),
+    ("bell", "🔔"),
+    ("bike", "🚲"),
+    ("bird", "🐦"),
+    ("birthday", "🎂"),
+    ("blush", "😊"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bow", "🙇"),
+    ("brain", "🧠"),
+    ("bread", "🍞"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("bus", "🚌"),
+    ("cake", "🍰"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("car", "🚗"),
+    ("cat", "🐱"),
+    ("chart_with_downwards_trend", "📉"),
+    ("chart_with_upwards_trend", "📈"),
+    ("check", "✔️"),
+    ("cherries", "🍒"),
+    ("chicken", "🐔"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("clock", "🕐"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cool", "🆒"),
+    ("cow", "🐮"),
+    ("crab", "🦀"),
+    ("crossed_fingers", "🤞"),
+    ("cry", "😢"),
+    ("crystal_ball", "🔮"),
+    ("dart", "🎯"),
+    ("dash", "💨"),
+    ("desktop_computer", "🖥️"),
+    ("disappointed", "😞"),
+    ("dizzy", "💫"),
+    ("dog", "🐶"),
+    ("dollar", "💵"),
+    ("door", "🚪"),
+    ("dragon", "🐉"),
+    ("droplet", "💧"),
+    ("earth_africa", "🌍"),
+    ("earth_americas", "🌎"),
+    ("earth_asia", "🌏"),
+    ("email", "📧"),
+    ("exclamation", "❗"),
+    ("expressionless", "😑"),
+    ("eyes", "👀"),
+    ("facepalm", "🤦"),
+    ("fearful", "😨"),
+    ("file_folder", "📁"),
+    ("fire", "🔥"),
+    ("fish", "🐟"),
+    ("fist", "✊"),
+    ("flag_pl", "🇵🇱"),
+    ("floppy_disk", "💾"),
+    ("flushed", "😳"),
+    ("frog", "🐸"),
+    ("frowning", "😦"),
+    ("gear", "⚙️"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("grimacing", "😬"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠️"),
+    ("hand", "✋"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("heavy_check_mark", "✔️"),
+    ("heavy_minus_sign", "➖"),
+    ("heavy_plus_sign", "➕"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("hugs", "🤗"),
+    ("hushed", "😯"),
+    ("information_source", "ℹ️"),
+    ("innocent", "😇"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("keyboard", "⌨️"),
+    ("kiss", "💋"),
+    ("laughing", "😆"),
+    ("leaves", "🍃"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("mailbox", "📫"),
+    ("memo", "📝"),
+    ("microscope", "🔬"),
+    ("money_with_wings", "💸"),
+    ("monkey", "🐒"),
+    ("moon", "🌙"),
+    ("mortar_board", "🎓"),
+    ("mouse", "🐭"),
+    ("muscle", "💪"),
+    ("mushroom", "🍄"),
+    ("musical_note", "🎵"),
+    ("nerd_face", "🤓"),
+    ("neutral_face", "😐"),
+    ("new", "🆕"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("open_mouth", "😮"),
+    ("package", "📦"),
+    ("page_facing_up", "📄"),
+    ("paperclip", "📎"),
+    ("party_popper", "🎉"),
+    ("partying_face", "🥳"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("penguin", "🐧"),
+    ("phone", "☎️"),
+    ("pig", "🐷"),
+    ("pizza", "🍕"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("poop", "💩"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rabbit", "🐰"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("red_circle", "🔴"),
+    ("relaxed", "☺️"),
+    ("relieved", "😌"),
+    ("repeat", "🔁"),
+    ("robot", "🤖"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("rose", "🌹"),
+    ("rotating_light", "🚨"),
+    ("sad", "😞"),
+    ("satellite", "📡"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("seedling", "🌱"),
+    ("shield", "🛡️"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snail", "🐌"),
+    ("snake", "🐍"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("soccer", "⚽"),
+    ("sparkles", "✨"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("stopwatch", "⏱️"),
+    ("sun_with_face", "🌞"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("tea", "🍵"),
+    ("telescope", "🔭"),
+    ("tent", "⛺"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tired_face", "😫"),
+    ("tomato", "🍅"),
+    ("tongue", "👅"),
+    ("tools", "🛠️"),
+    ("trophy", "🏆"),
+    ("turtle", "🐢"),
+    ("tv", "📺"),
+    ("unamused", "😒"),
+    ("unicorn", "🦄"),
+    ("unlock", "🔓"),
+    ("upside_down_face", "🙃"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// Emoji for a shortcode such as `rocket`.
+pub(crate) fn emoji_for(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by_key(&shortcode, |(shortcode, _)| shortcode)
+        .ok()
+        .map(|idx| EMOJI[idx].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emoji_for, EMOJI};
+
+    #[test]
+    fn test_emoji_table_is_sorted() {
+        assert!(EMOJI.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_emoji_for() {
+        assert_eq!(Some("🚀"), emoji_for("rocket"));
+        assert_eq!(Some("👍"), emoji_for("+1"));
+        assert_eq!(None, emoji_for("not_an_emoji"));
+    }
+}
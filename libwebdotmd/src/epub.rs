@@ -0,0 +1,389 @@
+//! EPUB 3 books made of article pages. Pages join a book with `series: <book title>` and
+//! `chapter: <number>`, the chapter numbers give the reading order.
+
+use crate::html::{escape_html, HtmlRenderer};
+use crate::markdown::{plain_text, Element, MarkdownPage};
+use crate::renderer::Renderer;
+use crate::typography::text_direction;
+use crate::zip::ZipWriter;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+/// Builds an EPUB of every series into `output_path`, named after the slug of its title. Images
+/// of the chapters are read from `assets_path`.
+pub fn write_epubs(
+    md_pages: &HashMap<String, MarkdownPage>,
+    assets_path: &Path,
+    output_path: &Path,
+) -> Result<(), std::io::Error> {
+    let mut series: BTreeMap<&str, Vec<(usize, &MarkdownPage)>> = BTreeMap::new();
+    for (path, page) in md_pages {
+        let Some(title) = page.get_metadata("series") else {
+            continue;
+        };
+        let chapter = page
+            .get_metadata("chapter")
+            .and_then(|chapter| chapter.parse().ok())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{path}: pages of a series need a `chapter: <number>`"),
+                )
+            })?;
+        series.entry(title).or_default().push((chapter, page));
+    }
+    for (title, mut chapters) in series {
+        chapters.sort_by_key(|(chapter, _)| *chapter);
+        let chapters = chapters.into_iter().map(|(_, page)| page).collect::<Vec<_>>();
+        let epub = build_epub(title, &chapters, assets_path)?;
+        std::fs::write(output_path.join(format!("{}.epub", slug(title))), epub)?;
+    }
+    Ok(())
+}
+
+/// Header of a chapter, `id` is what the nav document links to.
+struct Heading {
+    level: usize,
+    id: String,
+    text: String,
+}
+
+pub(crate) fn build_epub(title: &str, chapters: &[&MarkdownPage], assets_path: &Path) -> Result<Vec<u8>, Error> {
+    let first = chapters.first().copied();
+    let metadata = |name: &str| first.and_then(|page| page.get_metadata(name)).map(String::as_str);
+    let author = metadata("author");
+    let language = metadata("lang").unwrap_or("en");
+    // the book is as recent as its latest chapter
+    let date = chapters.iter().filter_map(|page| page.get_metadata("date")).max();
+
+    let mut zip = ZipWriter::default();
+    zip.add("mimetype", b"application/epub+zip");
+    zip.add("META-INF/container.xml", CONTAINER.as_bytes());
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut nav = String::new();
+    let mut images: Vec<String> = vec![];
+    for (i, page) in chapters.iter().enumerate() {
+        let file_name = format!("chapter-{}.xhtml", i + 1);
+        let chapter_title = page.get_metadata("title").map_or(title, String::as_str);
+        let (body, headings) = chapter_body(page.document().elements());
+        zip.add(
+            &format!("OEBPS/{file_name}"),
+            xhtml(chapter_title, language, &body).as_bytes(),
+        );
+        manifest.push_str(&format!(
+            "<item id=\"chapter-{}\" href=\"{file_name}\" media-type=\"application/xhtml+xml\"/>\n",
+            i + 1
+        ));
+        spine.push_str(&format!("<itemref idref=\"chapter-{}\"/>\n", i + 1));
+        nav.push_str(&format!(
+            "<li><a href=\"{file_name}\">{}</a>\n{}</li>\n",
+            escape_html(chapter_title),
+            outline(&file_name, &headings)
+        ));
+        for link in image_links(page.document().elements()) {
+            if !images.contains(&link) {
+                images.push(link);
+            }
+        }
+    }
+    for (i, link) in images.iter().enumerate() {
+        let media_type = image_media_type(link).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{link} is not an image an EPUB can hold"),
+            )
+        })?;
+        let contents = std::fs::read(assets_path.join(link))
+            .map_err(|error| Error::new(error.kind(), format!("image {link} of {title}: {error}")))?;
+        zip.add(&format!("OEBPS/{link}"), &contents);
+        manifest.push_str(&format!(
+            "<item id=\"image-{}\" href=\"{}\" media-type=\"{media_type}\"/>\n",
+            i + 1,
+            escape_html(link)
+        ));
+    }
+
+    let nav = format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{nav}</ol>\n</nav>\n",
+        escape_html(title)
+    );
+    zip.add("OEBPS/nav.xhtml", xhtml(title, language, &nav).as_bytes());
+
+    let mut dc = format!(
+        "<dc:identifier id=\"book-id\">urn:webdotmd:{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n",
+        slug(title),
+        escape_html(title),
+        escape_html(language)
+    );
+    if let Some(author) = author {
+        dc.push_str(&format!("<dc:creator>{}</dc:creator>\n", escape_html(author)));
+    }
+    if let Some(date) = date {
+        dc.push_str(&format!("<dc:date>{}</dc:date>\n", escape_html(date)));
+    }
+    let modified = date.map_or("1980-01-01", String::as_str);
+    let package = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{dc}<meta property="dcterms:modified">{}T00:00:00Z</meta>
+</metadata>
+<manifest>
+<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}</manifest>
+<spine>
+{spine}</spine>
+</package>
+"#,
+        escape_html(modified)
+    );
+    zip.add("OEBPS/content.opf", package.as_bytes());
+    Ok(zip.finish())
+}
+
+fn xhtml(title: &str, language: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{language}" xml:lang="{language}" dir="{dir}">
+<head>
+<title>{}</title>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+        escape_html(title),
+        language = escape_html(language),
+        dir = text_direction(language)
+    )
+}
+
+/// Html of a chapter with ids on its headers. Raw html is left out, it often isn't valid XHTML,
+/// substitutions are kept as their text for the same reason.
+fn chapter_body(elements: &[Element]) -> (String, Vec<Heading>) {
+    let mut elements = elements.to_vec();
+    remove_raw_html(&mut elements);
+    let mut body = String::new();
+    let mut headings = vec![];
+    for el in &elements {
+        match el {
+            Element::Header { level, elements } => {
+                let id = format!("heading-{}", headings.len() + 1);
+                let content: String = elements.iter().map(|el| HtmlRenderer.render_element(el)).collect();
+                body.push_str(&format!("<h{level} id=\"{id}\">{content}</h{level}>\n"));
+                headings.push(Heading {
+                    level: *level,
+                    id,
+                    text: plain_text(elements),
+                });
+            }
+            el => body.push_str(&HtmlRenderer.render_element(el)),
+        }
+    }
+    (body, headings)
+}
+
+fn remove_raw_html(elements: &mut Vec<Element>) {
+    elements.retain(|el| !matches!(el, Element::InlineHtml(_) | Element::HtmlBlock(_)));
+    for el in elements {
+        if let Element::Substitution { text, html: _ } = el {
+            *el = Element::Text(std::mem::take(text));
+        }
+        for children in el.children_mut() {
+            remove_raw_html(children);
+        }
+    }
+}
+
+/// Nested list of the headings, deeper headings are put under the heading before them.
+fn outline(file_name: &str, headings: &[Heading]) -> String {
+    let Some(level) = headings.iter().map(|heading| heading.level).min() else {
+        return String::new();
+    };
+    let mut html = "<ol>\n".to_string();
+    let mut i = 0;
+    while i < headings.len() {
+        let end = headings[i + 1..]
+            .iter()
+            .position(|heading| heading.level <= level)
+            .map_or(headings.len(), |position| i + 1 + position);
+        html.push_str(&format!(
+            "<li><a href=\"{file_name}#{}\">{}</a>\n{}</li>\n",
+            headings[i].id,
+            escape_html(&headings[i].text),
+            outline(file_name, &headings[i + 1..end])
+        ));
+        i = end;
+    }
+    html.push_str("</ol>\n");
+    html
+}
+
+/// Links of the images of a chapter that are stored with the site, remote ones are left as they are.
+fn image_links(elements: &[Element]) -> Vec<String> {
+    let mut links = vec![];
+    for el in elements {
+        match el {
+            Element::Image { link, .. } if !link.contains(':') => {
+                links.push(link.trim_start_matches("./").trim_start_matches('/').to_string())
+            }
+            Element::List { list_type: _, items } => {
+                for item in items {
+                    links.extend(image_links(item));
+                }
+            }
+            Element::DefinitionList { items } => {
+                for item in items {
+                    links.extend(image_links(&item.term));
+                    for definition in &item.definitions {
+                        links.extend(image_links(definition));
+                    }
+                }
+            }
+            Element::Paragraph(elements)
+            | Element::Header { level: _, elements }
+            | Element::BlockQuote(elements)
+            | Element::Emphasis(elements)
+            | Element::Strong(elements)
+            | Element::Link { elements, .. }
+            | Element::Span { elements, .. } => links.extend(image_links(elements)),
+            _ => {}
+        }
+    }
+    links
+}
+
+fn image_media_type(link: &str) -> Option<&'static str> {
+    let extension = Path::new(link).extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// File name of a book, e.g. `rust-in-practice` for "Rust in Practice".
+fn slug(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_epub, slug};
+    use crate::markdown::{parse_content, parse_metadata, MarkdownPage};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn page(content: &str) -> MarkdownPage {
+        MarkdownPage {
+            metadata: parse_metadata(content),
+            content: parse_content(content),
+        }
+    }
+
+    /// Reads the files of the archive from their local headers, they are stored uncompressed.
+    fn stored_files(epub: &[u8]) -> HashMap<String, String> {
+        let mut files = HashMap::new();
+        let mut offset = 0;
+        while epub[offset..].starts_with(b"PK\x03\x04") {
+            let header = &epub[offset..offset + 30];
+            let size = u32::from_le_bytes([header[18], header[19], header[20], header[21]]) as usize;
+            let name_length = u16::from_le_bytes([header[26], header[27]]) as usize;
+            let name_start = offset + 30;
+            let data_start = name_start + name_length;
+            files.insert(
+                String::from_utf8_lossy(&epub[name_start..data_start]).to_string(),
+                String::from_utf8_lossy(&epub[data_start..data_start + size]).to_string(),
+            );
+            offset = data_start + size;
+        }
+        files
+    }
+
+    #[test]
+    fn test_build_epub() {
+        let first = page(
+            "template: t.html
+title: Getting started
+author: Jane Doe
+date: 2024-01-02
+series: Rust in Practice
+chapter: 1
+------
+# Setup
+
+## Installing
+
+<div>raw</div>
+
+# Hello",
+        );
+        let second = page(
+            "template: t.html
+title: Ownership & borrowing
+date: 2024-02-03
+series: Rust in Practice
+chapter: 2
+------
+Text with a ![remote image](https://example.com/a.png).",
+        );
+        let epub = build_epub("Rust in Practice", &[&first, &second], Path::new("assets")).unwrap();
+        let files = stored_files(&epub);
+        // readers expect the mimetype to be the first file
+        assert_eq!(b"mimetype", &epub[30..38]);
+        assert_eq!("application/epub+zip", files["mimetype"]);
+
+        let package = &files["OEBPS/content.opf"];
+        assert!(package.contains("<dc:title>Rust in Practice</dc:title>"));
+        assert!(package.contains("<dc:creator>Jane Doe</dc:creator>"));
+        assert!(package.contains("<dc:date>2024-02-03</dc:date>"));
+        assert!(package.contains("<itemref idref=\"chapter-1\"/>\n<itemref idref=\"chapter-2\"/>"));
+
+        let chapter = &files["OEBPS/chapter-1.xhtml"];
+        assert!(chapter.contains("<h2 id=\"heading-2\">Installing</h2>"));
+        assert!(!chapter.contains("raw"));
+
+        let nav = &files["OEBPS/nav.xhtml"];
+        let expected = "<li><a href=\"chapter-1.xhtml\">Getting started</a>
+<ol>
+<li><a href=\"chapter-1.xhtml#heading-1\">Setup</a>
+<ol>
+<li><a href=\"chapter-1.xhtml#heading-2\">Installing</a>
+</li>
+</ol>
+</li>
+<li><a href=\"chapter-1.xhtml#heading-3\">Hello</a>
+</li>
+</ol>
+</li>
+<li><a href=\"chapter-2.xhtml\">Ownership &amp; borrowing</a>
+</li>";
+        assert!(nav.contains(expected), "{nav}");
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!("rust-in-practice", slug("Rust in Practice"));
+        assert_eq!("c-tips", slug("C++ -- tips!"));
+    }
+}
@@ -0,0 +1,632 @@
+//! Pull parser over a markdown document. Lines are read until the next block at the top of the
+//! document is finished, its events are returned, then the parser moves on to the next one. Text
+//! borrows from the input unless it had to change, e.g. lines of a quote without their `>`.
+
+use crate::block::{BlockKind, BlockParser, DefinitionList, Extensions, ListKind};
+use crate::inline::Inlines;
+use crate::markdown::{DefinitionItem, Element, ListType};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+/// Piece of a markdown document, containers are a `Start` and an `End` around their contents.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Event<'a> {
+    Start(Tag<'a>),
+    End(TagEnd),
+    /// Text with soft line breaks kept as `\n`.
+    Text(Cow<'a, str>),
+    InlineCode(Cow<'a, str>),
+    InlineHtml(Cow<'a, str>),
+    HardBreak,
+    Image {
+        alt: Cow<'a, str>,
+        link: Cow<'a, str>,
+        title: Cow<'a, str>,
+    },
+    WikiLink {
+        target: Cow<'a, str>,
+        heading: Option<Cow<'a, str>>,
+        text: Cow<'a, str>,
+    },
+    Abbreviation {
+        text: Cow<'a, str>,
+        title: Cow<'a, str>,
+    },
+    ThematicBreak,
+    Code {
+        lang: Cow<'a, str>,
+        code: Cow<'a, str>,
+    },
+    HtmlBlock(Cow<'a, str>),
+}
+
+/// Container started by `Event::Start`. Paragraphs of tight lists have no tags, their contents
+/// are directly in the item.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Tag<'a> {
+    Paragraph,
+    Header { level: usize },
+    BlockQuote,
+    List { list_type: ListType },
+    Item,
+    DefinitionList,
+    DefinitionTerm,
+    Definition,
+    Emphasis,
+    Strong,
+    Link { link: Cow<'a, str>, title: Cow<'a, str> },
+    Span { lang: Cow<'a, str> },
+}
+
+/// Container ended by `Event::End`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TagEnd {
+    Paragraph,
+    Header,
+    BlockQuote,
+    List,
+    Item,
+    DefinitionList,
+    DefinitionTerm,
+    Definition,
+    Emphasis,
+    Strong,
+    Link,
+    Span,
+}
+
+impl Event<'_> {
+    /// Copies borrowed text, so the event can outlive the input.
+    pub fn into_owned(self) -> Event<'static> {
+        let owned = |text: Cow<'_, str>| Cow::Owned(text.into_owned());
+        match self {
+            Event::Start(Tag::Link { link, title }) => Event::Start(Tag::Link {
+                link: owned(link),
+                title: owned(title),
+            }),
+            Event::Start(Tag::Span { lang }) => Event::Start(Tag::Span { lang: owned(lang) }),
+            Event::Start(Tag::Paragraph) => Event::Start(Tag::Paragraph),
+            Event::Start(Tag::Header { level }) => Event::Start(Tag::Header { level }),
+            Event::Start(Tag::BlockQuote) => Event::Start(Tag::BlockQuote),
+            Event::Start(Tag::List { list_type }) => Event::Start(Tag::List { list_type }),
+            Event::Start(Tag::Item) => Event::Start(Tag::Item),
+            Event::Start(Tag::DefinitionList) => Event::Start(Tag::DefinitionList),
+            Event::Start(Tag::DefinitionTerm) => Event::Start(Tag::DefinitionTerm),
+            Event::Start(Tag::Definition) => Event::Start(Tag::Definition),
+            Event::Start(Tag::Emphasis) => Event::Start(Tag::Emphasis),
+            Event::Start(Tag::Strong) => Event::Start(Tag::Strong),
+            Event::End(end) => Event::End(end),
+            Event::Text(text) => Event::Text(owned(text)),
+            Event::InlineCode(code) => Event::InlineCode(owned(code)),
+            Event::InlineHtml(html) => Event::InlineHtml(owned(html)),
+            Event::HardBreak => Event::HardBreak,
+            Event::Image { alt, link, title } => Event::Image {
+                alt: owned(alt),
+                link: owned(link),
+                title: owned(title),
+            },
+            Event::WikiLink { target, heading, text } => Event::WikiLink {
+                target: owned(target),
+                heading: heading.map(owned),
+                text: owned(text),
+            },
+            Event::Abbreviation { text, title } => Event::Abbreviation {
+                text: owned(text),
+                title: owned(title),
+            },
+            Event::ThematicBreak => Event::ThematicBreak,
+            Event::Code { lang, code } => Event::Code {
+                lang: owned(lang),
+                code: owned(code),
+            },
+            Event::HtmlBlock(html) => Event::HtmlBlock(owned(html)),
+        }
+    }
+}
+
+/// Terms defined with `*[term]: title`, matched as a single pattern.
+struct Abbreviations {
+    pattern: Regex,
+    titles: HashMap<String, String>,
+}
+
+impl Abbreviations {
+    fn new(abbreviations: &[(String, String)]) -> Option<Abbreviations> {
+        if abbreviations.is_empty() {
+            return None;
+        }
+        let mut terms = abbreviations.iter().map(|(term, _)| term.as_str()).collect::<Vec<_>>();
+        // longer terms first, so that `HTML5` wins over `HTML`
+        terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+        let pattern = terms
+            .iter()
+            .map(|term| regex::escape(term))
+            .collect::<Vec<_>>()
+            .join("|");
+        Some(Abbreviations {
+            pattern: Regex::new(&pattern).expect("Escaped abbreviations to form a valid pattern"),
+            titles: abbreviations.iter().cloned().collect(),
+        })
+    }
+
+    /// Splits text so that every whole word occurrence of a term becomes an `Abbreviation` event.
+    fn split<'a>(&self, text: Cow<'a, str>, events: &mut VecDeque<Event<'a>>) {
+        let slice = |range: std::ops::Range<usize>| match &text {
+            Cow::Borrowed(text) => Cow::Borrowed(&text[range]),
+            Cow::Owned(text) => Cow::Owned(text[range].to_string()),
+        };
+        let is_word_char = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+        let mut last_end = 0;
+        for found in self.pattern.find_iter(&text) {
+            let before = text[..found.start()].chars().next_back();
+            let after = text[found.end()..].chars().next();
+            if is_word_char(before) || is_word_char(after) {
+                continue;
+            }
+            if found.start() > last_end {
+                events.push_back(Event::Text(slice(last_end..found.start())));
+            }
+            events.push_back(Event::Abbreviation {
+                text: slice(found.range()),
+                title: Cow::Owned(self.titles[found.as_str()].to_string()),
+            });
+            last_end = found.end();
+        }
+        if last_end == 0 {
+            events.push_back(Event::Text(text));
+        } else if last_end < text.len() {
+            let rest = slice(last_end..text.len());
+            events.push_back(Event::Text(rest));
+        }
+    }
+}
+
+/// Iterator over the events of a markdown document.
+pub struct Parser<'a> {
+    blocks: BlockParser<'a>,
+    abbreviations: Option<Abbreviations>,
+    /// Number of abbreviations `abbreviations` was built from.
+    abbreviation_count: usize,
+    /// Containers being walked through and the next child to visit in each.
+    stack: Vec<(usize, Option<usize>)>,
+    /// Events to return before the inline events.
+    events: VecDeque<Event<'a>>,
+    /// Text of the paragraph, header or part of a definition list being parsed into `inlines`.
+    text: Cow<'a, str>,
+    inlines: Inlines,
+    /// Some abbreviations occur in the text, so its text events are split at them.
+    has_abbreviations: bool,
+    /// Event following the inline events of the text.
+    text_end: Option<TagEnd>,
+    /// Terms and definitions of the definition list being walked through.
+    definition_list: Option<DefinitionList<'a>>,
+    /// Glossary entries separated by blank lines end up in a single definition list.
+    in_definition_list: bool,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses CommonMark with the extensions of the site: wiki links, definition lists,
+    /// abbreviations and `a)` lists.
+    pub fn new(markdown: &'a str) -> Parser<'a> {
+        Parser::with_extensions(markdown, Extensions::ALL)
+    }
+
+    pub(crate) fn with_extensions(markdown: &'a str, extensions: Extensions) -> Parser<'a> {
+        Parser {
+            blocks: BlockParser::new(markdown, extensions),
+            abbreviations: None,
+            abbreviation_count: 0,
+            stack: vec![],
+            events: VecDeque::new(),
+            text: Cow::Borrowed(""),
+            inlines: Inlines::default(),
+            has_abbreviations: false,
+            text_end: None,
+            definition_list: None,
+            in_definition_list: false,
+        }
+    }
+
+    /// Event starting a block, its text is parsed so that its inline events follow.
+    fn enter(&mut self, block: usize) -> Option<Event<'a>> {
+        let is_definition_list = self.blocks.extensions.definition_lists && self.blocks.is_definition_list(block);
+        let event = match &self.blocks.blocks[block].kind {
+            BlockKind::BlockQuote => {
+                self.stack.push((block, self.blocks.blocks[block].first_child));
+                Event::Start(Tag::BlockQuote)
+            }
+            BlockKind::List { data, tight: _ } => {
+                let list_type = match data.kind {
+                    ListKind::Bullet(bullet) => ListType::Unordered {
+                        symbol: (bullet as char).to_string(),
+                    },
+                    ListKind::Ordered(delimiter) => ListType::Ordered {
+                        symbol: format!("1{}", delimiter as char),
+                        start: data.start,
+                    },
+                    ListKind::Lettered => ListType::Ordered {
+                        symbol: "a)".to_string(),
+                        start: data.start,
+                    },
+                };
+                self.stack.push((block, self.blocks.blocks[block].first_child));
+                Event::Start(Tag::List { list_type })
+            }
+            BlockKind::Item(_) => {
+                self.stack.push((block, self.blocks.blocks[block].first_child));
+                Event::Start(Tag::Item)
+            }
+            BlockKind::Paragraph if is_definition_list => {
+                self.definition_list = Some(self.blocks.take_definition_list(block));
+                if self.in_definition_list {
+                    return None;
+                }
+                self.in_definition_list = true;
+                return Some(Event::Start(Tag::DefinitionList));
+            }
+            BlockKind::Paragraph => {
+                // paragraphs of tight lists are rendered without `<p>`
+                let tight = self.blocks.blocks[block]
+                    .parent
+                    .filter(|&item| matches!(self.blocks.blocks[item].kind, BlockKind::Item(_)))
+                    .and_then(|item| self.blocks.blocks[item].parent)
+                    .is_some_and(|list| matches!(self.blocks.blocks[list].kind, BlockKind::List { tight: true, .. }));
+                let text = self.blocks.take_content(block);
+                if tight {
+                    self.parse_inlines(text, None);
+                    return self.end_definition_list(None);
+                }
+                self.parse_inlines(text, Some(TagEnd::Paragraph));
+                Event::Start(Tag::Paragraph)
+            }
+            BlockKind::Heading(level) => {
+                let level = *level;
+                let text = self.blocks.take_content(block);
+                self.parse_inlines(text, Some(TagEnd::Header));
+                Event::Start(Tag::Header { level })
+            }
+            BlockKind::ThematicBreak => Event::ThematicBreak,
+            BlockKind::CodeBlock { .. } => {
+                let lang = match self.blocks.take_info(block) {
+                    Cow::Borrowed(info) => Cow::Borrowed(info.split_whitespace().next().unwrap_or_default()),
+                    Cow::Owned(info) => Cow::Owned(info.split_whitespace().next().unwrap_or_default().to_string()),
+                };
+                let code = self.blocks.take_content(block);
+                Event::Code { lang, code }
+            }
+            BlockKind::HtmlBlock(_) => Event::HtmlBlock(self.blocks.take_content(block)),
+            BlockKind::Document => unreachable!("Only found at the top"),
+        };
+        self.end_definition_list(Some(event))
+    }
+
+    /// Event ending a container once all its children were walked through.
+    fn leave(&mut self, block: usize) -> Option<Event<'a>> {
+        let end = match self.blocks.blocks[block].kind {
+            BlockKind::BlockQuote => TagEnd::BlockQuote,
+            BlockKind::List { .. } => TagEnd::List,
+            BlockKind::Item(_) => TagEnd::Item,
+            _ => unreachable!("Only containers are left"),
+        };
+        self.end_definition_list(Some(Event::End(end)))
+    }
+
+    /// Returns `event`, unless a definition list ends before it, it then follows the end of the
+    /// list. Definition lists end before a block that isn't one, or at the end of its container.
+    fn end_definition_list(&mut self, event: Option<Event<'a>>) -> Option<Event<'a>> {
+        if !self.in_definition_list {
+            return event;
+        }
+        self.in_definition_list = false;
+        self.events.extend(event);
+        Some(Event::End(TagEnd::DefinitionList))
+    }
+
+    /// Parses the text of a block, its inline events are returned next, followed by `end`.
+    fn parse_inlines(&mut self, text: Cow<'a, str>, end: Option<TagEnd>) {
+        let whitespace = [' ', '\t', '\n', '\r'];
+        self.text = match text {
+            Cow::Borrowed(text) => Cow::Borrowed(text.trim_matches(whitespace)),
+            Cow::Owned(mut text) => {
+                text.truncate(text.trim_end_matches(whitespace).len());
+                text.drain(..text.len() - text.trim_start_matches(whitespace).len());
+                Cow::Owned(text)
+            }
+        };
+        self.blocks.read_definitions();
+        if self.blocks.abbreviations.len() != self.abbreviation_count {
+            self.abbreviations = Abbreviations::new(&self.blocks.abbreviations);
+            self.abbreviation_count = self.blocks.abbreviations.len();
+        }
+        self.has_abbreviations = self
+            .abbreviations
+            .as_ref()
+            .is_some_and(|abbreviations| abbreviations.pattern.is_match(&self.text));
+        self.inlines
+            .parse(&self.text, &self.blocks.references, self.blocks.extensions);
+        self.text_end = end;
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(event);
+            }
+            if let Some(event) = self.inlines.next_event(&self.text) {
+                match (event, &self.abbreviations) {
+                    (Event::Text(text), Some(abbreviations)) if self.has_abbreviations => {
+                        abbreviations.split(text, &mut self.events)
+                    }
+                    (event, _) => return Some(event),
+                }
+                continue;
+            }
+            if let Some(end) = self.text_end.take() {
+                return Some(Event::End(end));
+            }
+            if let Some(definition_list) = &mut self.definition_list {
+                match definition_list.next() {
+                    Some((true, definition)) => {
+                        self.parse_inlines(definition, Some(TagEnd::Definition));
+                        return Some(Event::Start(Tag::Definition));
+                    }
+                    Some((false, term)) => {
+                        self.parse_inlines(term, Some(TagEnd::DefinitionTerm));
+                        return Some(Event::Start(Tag::DefinitionTerm));
+                    }
+                    None => self.definition_list = None,
+                }
+            }
+            match self.stack.last_mut() {
+                Some((block, next_child)) => {
+                    let block = *block;
+                    match *next_child {
+                        Some(child) => {
+                            *next_child = self.blocks.blocks[child].next;
+                            if let Some(event) = self.enter(child) {
+                                return Some(event);
+                            }
+                        }
+                        None => {
+                            self.stack.pop();
+                            if let Some(event) = self.leave(block) {
+                                return Some(event);
+                            }
+                        }
+                    }
+                }
+                None => match self.blocks.next_block() {
+                    Some(block) => {
+                        if let Some(event) = self.enter(block) {
+                            return Some(event);
+                        }
+                    }
+                    None => return self.end_definition_list(None),
+                },
+            }
+        }
+    }
+}
+
+/// Builds the element tree of a document from its events.
+pub(crate) fn elements_from<'a>(events: &mut impl Iterator<Item = Event<'a>>) -> Vec<Element> {
+    children(events, &mut vec![])
+}
+
+/// Elements up to the end of the current container. They are gathered at the end of `stack`
+/// first, so that they are moved once into a vector of the right size.
+fn children<'a>(events: &mut impl Iterator<Item = Event<'a>>, stack: &mut Vec<Element>) -> Vec<Element> {
+    let start = stack.len();
+    while let Some(event) = events.next() {
+        let el = match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => Element::Paragraph(children(events, stack)),
+                Tag::Header { level } => Element::Header {
+                    level,
+                    elements: children(events, stack),
+                },
+                Tag::BlockQuote => Element::BlockQuote(children(events, stack)),
+                Tag::List { list_type } => {
+                    let mut items = vec![];
+                    while let Some(Event::Start(Tag::Item)) = events.next() {
+                        items.push(children(events, stack));
+                    }
+                    Element::List { list_type, items }
+                }
+                Tag::DefinitionList => {
+                    let mut items: Vec<DefinitionItem> = vec![];
+                    while let Some(Event::Start(tag)) = events.next() {
+                        let elements = children(events, stack);
+                        match (tag, items.last_mut()) {
+                            (Tag::Definition, Some(item)) => item.definitions.push(elements),
+                            _ => items.push(DefinitionItem {
+                                term: elements,
+                                definitions: vec![],
+                            }),
+                        }
+                    }
+                    Element::DefinitionList { items }
+                }
+                Tag::Emphasis => Element::Emphasis(children(events, stack)),
+                Tag::Strong => Element::Strong(children(events, stack)),
+                Tag::Link { link, title } => Element::Link {
+                    elements: children(events, stack),
+                    link: link.into_owned(),
+                    title: title.into_owned(),
+                },
+                Tag::Span { lang } => Element::Span {
+                    elements: children(events, stack),
+                    lang: lang.into_owned(),
+                },
+                Tag::Item | Tag::DefinitionTerm | Tag::Definition => unreachable!("Only found inside of lists"),
+            },
+            Event::End(_) => break,
+            Event::Text(text) => Element::Text(text.into_owned()),
+            Event::InlineCode(code) => Element::InlineCode(code.into_owned()),
+            Event::InlineHtml(html) => Element::InlineHtml(html.into_owned()),
+            Event::HardBreak => Element::HardBreak,
+            Event::Image { alt, link, title } => Element::Image {
+                alt: alt.into_owned(),
+                link: link.into_owned(),
+                title: title.into_owned(),
+            },
+            Event::WikiLink { target, heading, text } => Element::WikiLink {
+                target: target.into_owned(),
+                heading: heading.map(Cow::into_owned),
+                text: text.into_owned(),
+            },
+            Event::Abbreviation { text, title } => Element::Abbreviation {
+                text: text.into_owned(),
+                title: title.into_owned(),
+            },
+            Event::ThematicBreak => Element::ThematicBreak,
+            Event::Code { lang, code } => Element::Code {
+                lang: lang.into_owned(),
+                code: code.into_owned(),
+            },
+            Event::HtmlBlock(html) => Element::HtmlBlock(html.into_owned()),
+        };
+        stack.push(el);
+    }
+    if start == 0 {
+        // elements at the top of the document are all of them, they are kept as they are
+        return std::mem::take(stack);
+    }
+    stack.drain(start..).collect()
+}
+
+/// Parses a markdown document into elements.
+pub(crate) fn parse_document(text: &str, extensions: Extensions) -> Vec<Element> {
+    elements_from(&mut Parser::with_extensions(text, extensions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Parser, Tag, TagEnd};
+    use crate::markdown::ListType;
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_events() {
+        let markdown = "# A *title*
+
+- one
+- [two](two.html)
+
+> quoted
+> text
+
+*[HTML]: Hyper Text Markup Language
+Plain HTML.
+";
+        let expected = vec![
+            Event::Start(Tag::Header { level: 1 }),
+            Event::Text(Cow::Borrowed("A ")),
+            Event::Start(Tag::Emphasis),
+            Event::Text(Cow::Borrowed("title")),
+            Event::End(TagEnd::Emphasis),
+            Event::End(TagEnd::Header),
+            Event::Start(Tag::List {
+                list_type: ListType::Unordered {
+                    symbol: "-".to_string(),
+                },
+            }),
+            Event::Start(Tag::Item),
+            Event::Text(Cow::Borrowed("one")),
+            Event::End(TagEnd::Item),
+            Event::Start(Tag::Item),
+            Event::Start(Tag::Link {
+                link: Cow::Borrowed("two.html"),
+                title: Cow::Borrowed(""),
+            }),
+            Event::Text(Cow::Borrowed("two")),
+            Event::End(TagEnd::Link),
+            Event::End(TagEnd::Item),
+            Event::End(TagEnd::List),
+            Event::Start(Tag::BlockQuote),
+            Event::Start(Tag::Paragraph),
+            Event::Text(Cow::Borrowed("quoted\ntext")),
+            Event::End(TagEnd::Paragraph),
+            Event::End(TagEnd::BlockQuote),
+            Event::Start(Tag::Paragraph),
+            Event::Text(Cow::Borrowed("Plain ")),
+            Event::Abbreviation {
+                text: Cow::Borrowed("HTML"),
+                title: Cow::Borrowed("Hyper Text Markup Language"),
+            },
+            Event::Text(Cow::Borrowed(".")),
+            Event::End(TagEnd::Paragraph),
+        ];
+        assert_eq!(expected, Parser::new(markdown).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_borrowed_text() {
+        let markdown = "Some *text*
+over lines
+
+> - a quoted
+>   list
+
+```
+code
+```
+
+Escaped \\*star\\* &amp; entity
+";
+        let borrowed = |event: &Event| match event {
+            Event::Text(text) | Event::Code { code: text, .. } => Some(matches!(text, Cow::Borrowed(_))),
+            _ => None,
+        };
+        let texts = Parser::new(markdown)
+            .filter_map(|event| Some((borrowed(&event)?, event)))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (true, Event::Text(Cow::Borrowed("Some "))),
+            (true, Event::Text(Cow::Borrowed("text"))),
+            (true, Event::Text(Cow::Borrowed("\nover lines"))),
+            // the lines of the item aren't next to each other in the input
+            (false, Event::Text(Cow::Borrowed("a quoted\nlist"))),
+            (
+                true,
+                Event::Code {
+                    lang: Cow::Borrowed(""),
+                    code: Cow::Borrowed("code\n"),
+                },
+            ),
+            // escapes are skipped, entities decoded
+            (false, Event::Text(Cow::Borrowed("Escaped *star* & entity"))),
+        ];
+        assert_eq!(expected, texts);
+    }
+
+    #[test]
+    fn test_definition_lists_are_merged() {
+        let events = Parser::new(
+            "Term
+: Definition
+
+Other term
+: Other definition",
+        )
+        .collect::<Vec<_>>();
+        let starts = events
+            .iter()
+            .filter(|event| matches!(event, Event::Start(Tag::DefinitionList)))
+            .count();
+        assert_eq!(1, starts);
+        assert_eq!(Some(&Event::End(TagEnd::DefinitionList)), events.last());
+        assert_eq!(
+            4,
+            events
+                .iter()
+                .filter(|event| matches!(event, Event::Start(Tag::DefinitionTerm | Tag::Definition)))
+                .count()
+        );
+    }
+}
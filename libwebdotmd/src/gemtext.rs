@@ -0,0 +1,229 @@
+use crate::markdown::{Document, Element, ListType};
+use crate::plain_text::{indent, is_block};
+use crate::renderer::Renderer;
+use std::fmt::Write;
+
+/// Renders documents into gemtext for Gemini capsules. Gemtext has no inline markup, so each
+/// paragraph becomes a single line with its links listed as `=>` lines after it.
+pub struct GemtextRenderer;
+
+impl Renderer for GemtextRenderer {
+    fn render_element(&self, element: &Element) -> String {
+        use Element::*;
+        match element {
+            Paragraph(elements) => self.text_block("", elements),
+            Header { level, elements } => {
+                let marker = "#".repeat((*level).min(3));
+                format!("{marker} {}\n", one_line(&inline_text(elements)))
+            }
+            ThematicBreak => "---\n".to_string(),
+            Code { lang, code } => format!("```{lang}\n{code}```\n"),
+            BlockQuote(elements) => {
+                let mut rendered = String::new();
+                for el in elements {
+                    let block = match el {
+                        Paragraph(elements) => self.text_block("> ", elements),
+                        el => self.render_element(el),
+                    };
+                    rendered.push_str(&block);
+                }
+                rendered
+            }
+            List { list_type, items } => {
+                let mut rendered = String::new();
+                let mut links = vec![];
+                for (i, item) in items.iter().enumerate() {
+                    let mut inlines = vec![];
+                    let mut blocks = String::new();
+                    for el in item {
+                        match el {
+                            Paragraph(elements) => inlines.extend(elements),
+                            el if is_block(el) => blocks.push_str(&self.render_element(el)),
+                            el => inlines.push(el),
+                        }
+                    }
+                    let number = match list_type {
+                        ListType::Ordered { start, .. } => format!("{}. ", start + i),
+                        ListType::Unordered { .. } => String::new(),
+                    };
+                    rendered.push_str(&format!(
+                        "* {number}{}\n",
+                        one_line(&inline_text(inlines.iter().copied()))
+                    ));
+                    links.extend(links_of(inlines));
+                    rendered.push_str(&blocks);
+                }
+                rendered + &link_lines(&links)
+            }
+            DefinitionList { items } => {
+                let mut rendered = String::new();
+                for item in items {
+                    rendered.push_str(&format!("{}\n", one_line(&inline_text(&item.term))));
+                    for definition in &item.definitions {
+                        rendered.push_str(&self.text_block("> ", definition));
+                    }
+                }
+                rendered
+            }
+            HtmlBlock(_) => String::new(),
+            inline => inline_text(std::slice::from_ref(inline)),
+        }
+    }
+
+    fn render(&self, document: &Document) -> String {
+        document
+            .elements()
+            .iter()
+            .map(|el| self.render_element(el))
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl GemtextRenderer {
+    /// A line of text prefixed with `prefix`, followed by a line for each of its links. Images
+    /// standing alone in a paragraph become just a link line.
+    fn text_block(&self, prefix: &str, elements: &[Element]) -> String {
+        if let [Element::Image { alt, link, .. }] = elements {
+            return link_lines(&[(link.to_string(), alt.to_string())]);
+        }
+        let text = one_line(&inline_text(elements));
+        let text = if text.is_empty() {
+            String::new()
+        } else {
+            indent(&text, prefix, prefix)
+        };
+        text + &link_lines(&links_of(elements))
+    }
+}
+
+fn inline_text<'a>(elements: impl IntoIterator<Item = &'a Element>) -> String {
+    use Element::*;
+    let mut text = String::new();
+    for el in elements {
+        match el {
+            Text(t)
+            | InlineCode(t)
+            | WikiLink { text: t, .. }
+            | Abbreviation { text: t, title: _ }
+            | Substitution { text: t, html: _ } => text.push_str(t),
+            Image { alt, .. } => text.push_str(alt),
+            HardBreak => text.push(' '),
+            Emphasis(elements) | Strong(elements) | Link { elements, .. } | Span { elements, .. } => {
+                text.push_str(&inline_text(elements))
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Gemtext lines are wrapped by the client, so line breaks inside of a paragraph are dropped.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn links_of<'a>(elements: impl IntoIterator<Item = &'a Element>) -> Vec<(String, String)> {
+    let mut links = vec![];
+    for el in elements {
+        match el {
+            Element::Link { elements, link, .. } => {
+                links.push((link.to_string(), one_line(&inline_text(elements))));
+                links.extend(links_of(elements));
+            }
+            Element::Image { alt, link, .. } => links.push((link.to_string(), alt.to_string())),
+            Element::Emphasis(elements) | Element::Strong(elements) | Element::Span { elements, .. } => {
+                links.extend(links_of(elements))
+            }
+            _ => {}
+        }
+    }
+    links
+}
+
+fn link_lines(links: &[(String, String)]) -> String {
+    links
+        .iter()
+        .map(|(link, text)| match (encode_link(link), text.as_str()) {
+            (link, "") => format!("=> {link}\n"),
+            (link, text) => format!("=> {link} {text}\n"),
+        })
+        .collect()
+}
+
+/// Percent-encodes whitespace and everything outside ASCII in a link target, e.g. in a heading
+/// anchor, so that the link line doesn't end at a space and clients can parse the URL.
+fn encode_link(link: &str) -> String {
+    let mut encoded = String::with_capacity(link.len());
+    for char in link.chars() {
+        if char.is_ascii_graphic() {
+            encoded.push(char);
+        } else {
+            for byte in char.encode_utf8(&mut [0; 4]).bytes() {
+                write!(encoded, "%{byte:02X}").unwrap();
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GemtextRenderer;
+    use crate::markdown::Document;
+    use crate::renderer::Renderer;
+
+    #[test]
+    fn test_render_gemtext() {
+        let document = Document::parse(
+            "# Title
+
+#### Deep header
+
+Some *text* with a [link](https://example.com)
+over two lines.
+
+![A picture](picture.png)
+
+> quoted
+
+- first
+- [second](second.html)
+
+```rust
+let x = 1;
+```",
+        );
+        let expected = "# Title
+
+### Deep header
+
+Some text with a link over two lines.
+=> https://example.com link
+
+=> picture.png A picture
+
+> quoted
+
+* first
+* second
+=> second.html second
+
+```rust
+let x = 1;
+```
+";
+        assert_eq!(expected, GemtextRenderer.render(&document));
+    }
+
+    #[test]
+    fn test_link_targets_are_encoded() {
+        let document = Document::parse("See [the start](<intro.gmi#Getting started>) and [notes](żółw.gmi).");
+        let expected = "See the start and notes.
+=> intro.gmi#Getting%20started the start
+=> %C5%BC%C3%B3%C5%82w.gmi notes
+";
+        assert_eq!(expected, GemtextRenderer.render(&document));
+    }
+}
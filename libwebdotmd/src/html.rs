@@ -0,0 +1,237 @@
+//! Built-in html output for using the library without templates. It follows the CommonMark
+//! reference implementation, which is what the examples of the spec expect.
+
+use crate::markdown::{Document, Element, ListType};
+use crate::renderer::Renderer;
+use crate::typography::text_direction;
+
+/// Renders a document into plain html, without any templates.
+pub fn render_html(document: &Document) -> String {
+    HtmlRenderer.render(document)
+}
+
+/// Renders elements into html like the CommonMark reference implementation, site extensions use
+/// `<dl>` and `<abbr>` and unresolved wiki links are rendered as text.
+pub struct HtmlRenderer;
+
+impl Renderer for HtmlRenderer {
+    fn render_element(&self, element: &Element) -> String {
+        let mut html = HtmlWriter { html: String::new() };
+        html.element(element);
+        html.html
+    }
+}
+
+/// Escapes text for use in html content and attribute values.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct HtmlWriter {
+    html: String,
+}
+
+impl HtmlWriter {
+    /// Blocks start on a new line.
+    fn cr(&mut self) {
+        if !self.html.is_empty() && !self.html.ends_with('\n') {
+            self.html.push('\n');
+        }
+    }
+
+    fn push(&mut self, html: &str) {
+        self.html.push_str(html);
+    }
+
+    fn elements(&mut self, elements: &[Element]) {
+        for el in elements {
+            self.element(el);
+        }
+    }
+
+    fn title(&mut self, title: &str) {
+        if !title.is_empty() {
+            self.push(&format!(" title=\"{}\"", escape_html(title)));
+        }
+    }
+
+    fn element(&mut self, el: &Element) {
+        use Element::*;
+        match el {
+            Text(text) => self.push(&escape_html(text)),
+            HardBreak => self.push("<br />\n"),
+            InlineCode(code) => self.push(&format!("<code>{}</code>", escape_html(code))),
+            Emphasis(elements) => {
+                self.push("<em>");
+                self.elements(elements);
+                self.push("</em>");
+            }
+            Strong(elements) => {
+                self.push("<strong>");
+                self.elements(elements);
+                self.push("</strong>");
+            }
+            Link { elements, link, title } => {
+                self.push(&format!("<a href=\"{}\"", escape_html(link)));
+                self.title(title);
+                self.push(">");
+                self.elements(elements);
+                self.push("</a>");
+            }
+            Image { alt, link, title } => {
+                self.push(&format!(
+                    "<img src=\"{}\" alt=\"{}\"",
+                    escape_html(link),
+                    escape_html(alt)
+                ));
+                self.title(title);
+                self.push(" />");
+            }
+            InlineHtml(html) | Substitution { text: _, html } => self.push(html),
+            WikiLink { text, .. } => self.push(&escape_html(text)),
+            Abbreviation { text, title } => self.push(&format!(
+                "<abbr title=\"{}\">{}</abbr>",
+                escape_html(title),
+                escape_html(text)
+            )),
+            Span { elements, lang } => {
+                self.push(&format!(
+                    "<span lang=\"{}\" dir=\"{}\">",
+                    escape_html(lang),
+                    text_direction(lang)
+                ));
+                self.elements(elements);
+                self.push("</span>");
+            }
+            Paragraph(elements) => {
+                self.cr();
+                self.push("<p>");
+                self.elements(elements);
+                self.push("</p>");
+                self.cr();
+            }
+            Header { level, elements } => {
+                self.cr();
+                self.push(&format!("<h{level}>"));
+                self.elements(elements);
+                self.push(&format!("</h{level}>"));
+                self.cr();
+            }
+            ThematicBreak => {
+                self.cr();
+                self.push("<hr />");
+                self.cr();
+            }
+            Code { lang, code } => {
+                self.cr();
+                self.push("<pre><code");
+                if !lang.is_empty() {
+                    self.push(&format!(" class=\"language-{}\"", escape_html(lang)));
+                }
+                self.push(">");
+                self.push(&escape_html(code));
+                self.push("</code></pre>");
+                self.cr();
+            }
+            HtmlBlock(html) => {
+                self.cr();
+                self.push(html);
+                self.cr();
+            }
+            BlockQuote(elements) => {
+                self.cr();
+                self.push("<blockquote>");
+                self.cr();
+                self.elements(elements);
+                self.cr();
+                self.push("</blockquote>");
+                self.cr();
+            }
+            List { list_type, items } => {
+                let tag = match list_type {
+                    ListType::Unordered { .. } => "ul",
+                    ListType::Ordered { .. } => "ol",
+                };
+                self.cr();
+                match list_type {
+                    ListType::Ordered { symbol: _, start } if *start != 1 => {
+                        self.push(&format!("<ol start=\"{start}\">"))
+                    }
+                    _ => self.push(&format!("<{tag}>")),
+                }
+                self.cr();
+                for item in items {
+                    self.cr();
+                    self.push("<li>");
+                    self.elements(item);
+                    self.push("</li>");
+                    self.cr();
+                }
+                self.cr();
+                self.push(&format!("</{tag}>"));
+                self.cr();
+            }
+            DefinitionList { items } => {
+                self.cr();
+                self.push("<dl>");
+                self.cr();
+                for item in items {
+                    self.push("<dt>");
+                    self.elements(&item.term);
+                    self.push("</dt>");
+                    self.cr();
+                    for definition in &item.definitions {
+                        self.push("<dd>");
+                        self.elements(definition);
+                        self.push("</dd>");
+                        self.cr();
+                    }
+                }
+                self.push("</dl>");
+                self.cr();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_html;
+    use crate::markdown::Document;
+
+    #[test]
+    fn test_render_html() {
+        let document = Document::parse(
+            "# HTML & [[notes]]
+
+Term
+: *definition* of HTML
+
+- a
+- b
+
+*[HTML]: Hyper Text Markup Language",
+        );
+        let expected = r#"<h1><abbr title="Hyper Text Markup Language">HTML</abbr> &amp; notes</h1>
+<dl>
+<dt>Term</dt>
+<dd><em>definition</em> of <abbr title="Hyper Text Markup Language">HTML</abbr></dd>
+</dl>
+<ul>
+<li>a</li>
+<li>b</li>
+</ul>
+"#;
+        assert_eq!(expected, render_html(&document));
+    }
+}
@@ -0,0 +1,1203 @@
+//! Second phase of parsing, turns the text of paragraphs and headers into inline events: code
+//! spans, emphasis, links, images, autolinks, raw html and line breaks, following CommonMark.
+//! Emphasis is only known once the whole text is read, so the text is parsed into a tree of
+//! ranges of it first, then walked through one event at a time.
+
+use crate::block::Extensions;
+use crate::entities::entity_for;
+use crate::events::{Event, Tag, TagEnd};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Target of a `[label]: link "title"` definition, used by reference links.
+#[derive(Debug)]
+pub(crate) struct LinkReference {
+    link: String,
+    title: String,
+}
+
+/// Link reference definitions of a document, by normalized label.
+pub(crate) type References = HashMap<String, LinkReference>;
+
+pub(crate) const OPEN_TAG: &str = r#"<[A-Za-z][A-Za-z0-9-]*(?:\s+[a-zA-Z_:][a-zA-Z0-9:._-]*(?:\s*=\s*(?:[^"'=<>`\x00-\x20]+|'[^']*'|"[^"]*"))?)*\s*/?>"#;
+pub(crate) const CLOSE_TAG: &str = r"</[A-Za-z][A-Za-z0-9-]*\s*>";
+
+static HTML_TAG_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    let comment = r"<!---->|<!--(?:-?[^>-])(?:-?[^-])*-->";
+    let processing_instruction = r"<[?].*?[?]>";
+    let declaration = r"<![A-Z]+\s+[^>]*>";
+    let cdata = r"<!\[CDATA\[[\s\S]*?\]\]>";
+    Regex::new(&format!(
+        "(?i)^(?:{OPEN_TAG}|{CLOSE_TAG}|{comment}|{processing_instruction}|{declaration}|{cdata})"
+    ))
+    .expect("Failed to compile html tag pattern")
+});
+static AUTOLINK_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^<([A-Za-z][A-Za-z0-9.+-]{1,31}:[^<>\x00-\x20]*)>").expect("Failed to compile autolink pattern")
+});
+static EMAIL_AUTOLINK_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^<([a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*)>",
+    )
+    .expect("Failed to compile email autolink pattern")
+});
+/// `{lang=pl}` right after the `]` of a span.
+static SPAN_ATTRIBUTES_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\{[ \t]*lang=(?:"([A-Za-z0-9-]+)"|([A-Za-z0-9-]+))[ \t]*\}"#)
+        .expect("Failed to compile span pattern")
+});
+
+/// Inline tree of the last text parsed, walked one event at a time. Its buffers are reused from
+/// one text to the next.
+#[derive(Default)]
+pub(crate) struct Inlines {
+    nodes: Vec<Node>,
+    delimiters: Vec<Delimiter>,
+    brackets: Vec<Bracket>,
+    cursor: Cursor,
+}
+
+/// Step of the walk through the inline tree.
+#[derive(Debug, Clone, Copy, Default)]
+enum Cursor {
+    Enter(usize),
+    Leave(usize),
+    /// The whole text is a single text event, it has no markup.
+    Plain,
+    #[default]
+    Done,
+}
+
+impl Inlines {
+    /// Parses the text of a paragraph or a header. `references` are the link reference
+    /// definitions found in the whole document.
+    pub(crate) fn parse(&mut self, subject: &str, references: &References, extensions: Extensions) {
+        if !subject.bytes().any(is_special) {
+            self.cursor = if subject.is_empty() {
+                Cursor::Done
+            } else {
+                Cursor::Plain
+            };
+            return;
+        }
+        let mut parser = InlineParser {
+            subject,
+            pos: 0,
+            nodes: std::mem::take(&mut self.nodes),
+            delimiters: std::mem::take(&mut self.delimiters),
+            brackets: std::mem::take(&mut self.brackets),
+            mergeable: ROOT + 1,
+            references,
+            extensions,
+        };
+        parser.nodes.clear();
+        parser.nodes.push(Node::new(Inline::Root));
+        parser.delimiters.clear();
+        parser.brackets.clear();
+        parser.parse();
+        self.nodes = parser.nodes;
+        self.delimiters = parser.delimiters;
+        self.brackets = parser.brackets;
+        self.cursor = self.nodes[ROOT].first_child.map_or(Cursor::Done, Cursor::Enter);
+    }
+
+    /// Next event of the parsed text, which is `subject`. Text borrows from it wherever it can.
+    pub(crate) fn next_event<'a>(&mut self, subject: &Cow<'a, str>) -> Option<Event<'a>> {
+        loop {
+            let node = match self.cursor {
+                Cursor::Done => return None,
+                Cursor::Plain => {
+                    self.cursor = Cursor::Done;
+                    return Some(Event::Text(Text::Subject(0..subject.len()).into_cow(subject)));
+                }
+                Cursor::Enter(node) => node,
+                Cursor::Leave(node) => {
+                    self.cursor = self.after(node);
+                    let end = match self.nodes[node].inline {
+                        Inline::Emphasis => TagEnd::Emphasis,
+                        Inline::Strong => TagEnd::Strong,
+                        Inline::Link { .. } => TagEnd::Link,
+                        Inline::Span { .. } => TagEnd::Span,
+                        _ => unreachable!("Only containers are left"),
+                    };
+                    return Some(Event::End(end));
+                }
+            };
+            let tag = match &mut self.nodes[node].inline {
+                Inline::Emphasis => Tag::Emphasis,
+                Inline::Strong => Tag::Strong,
+                Inline::Link { link, title } => Tag::Link {
+                    link: link.take().into_cow(subject),
+                    title: title.take().into_cow(subject),
+                },
+                Inline::Span { lang } => Tag::Span {
+                    lang: Text::Subject(lang.clone()).into_cow(subject),
+                },
+                _ => match self.leaf(node, subject) {
+                    Some(event) => return Some(event),
+                    None => continue,
+                },
+            };
+            self.cursor = self.nodes[node].first_child.map_or(Cursor::Leave(node), Cursor::Enter);
+            return Some(Event::Start(tag));
+        }
+    }
+
+    /// Event of a node without children, text takes in the text following it too.
+    fn leaf<'a>(&mut self, node: usize, subject: &Cow<'a, str>) -> Option<Event<'a>> {
+        let mut last = node;
+        let event = match std::mem::replace(&mut self.nodes[node].inline, Inline::Root) {
+            Inline::Text(mut text) => {
+                while let Some(next) = self.nodes[last].next {
+                    let Inline::Text(next_text) = &mut self.nodes[next].inline else {
+                        break;
+                    };
+                    text.append(next_text.take(), subject);
+                    last = next;
+                }
+                (!text.is_empty()).then(|| Event::Text(text.into_cow(subject)))
+            }
+            Inline::Code(code) => Some(Event::InlineCode(code.into_cow(subject))),
+            Inline::Html(html) => Some(Event::InlineHtml(Text::Subject(html).into_cow(subject))),
+            Inline::HardBreak => Some(Event::HardBreak),
+            Inline::WikiLink { target, heading, text } => Some(Event::WikiLink {
+                target: Text::Subject(target).into_cow(subject),
+                heading: heading.map(|heading| Text::Subject(heading).into_cow(subject)),
+                text: Text::Subject(text).into_cow(subject),
+            }),
+            Inline::Image { link, title } => {
+                let mut alt = Text::Owned(String::new());
+                self.alt_text(node, subject, &mut alt);
+                Some(Event::Image {
+                    alt: alt.into_cow(subject),
+                    link: link.into_cow(subject),
+                    title: title.into_cow(subject),
+                })
+            }
+            _ => unreachable!("Containers have their own events"),
+        };
+        self.cursor = self.after(last);
+        event
+    }
+
+    /// Where the walk goes once it's done with `node` and its children.
+    fn after(&self, node: usize) -> Cursor {
+        match (self.nodes[node].next, self.nodes[node].parent) {
+            (Some(next), _) => Cursor::Enter(next),
+            (None, Some(parent)) if parent != ROOT => Cursor::Leave(parent),
+            _ => Cursor::Done,
+        }
+    }
+
+    /// Plain text of the description of an image, markup isn't text.
+    fn alt_text(&mut self, parent: usize, subject: &str, alt: &mut Text) {
+        let mut child = self.nodes[parent].first_child;
+        while let Some(node) = child {
+            child = self.nodes[node].next;
+            let text = match std::mem::replace(&mut self.nodes[node].inline, Inline::Root) {
+                Inline::Text(text) | Inline::Code(text) => text,
+                Inline::WikiLink { text, .. } => Text::Subject(text),
+                Inline::HardBreak => Text::Owned("\n".to_string()),
+                Inline::Html(_) => continue,
+                _ => {
+                    self.alt_text(node, subject, alt);
+                    continue;
+                }
+            };
+            alt.append(text, subject);
+        }
+    }
+}
+
+/// Parses a `[label]: link "title"` definition at the start of `text` and adds it to
+/// `references`, unless the label is already defined. Returns the length of the definition, zero
+/// when the text doesn't start with one.
+pub(crate) fn parse_link_reference(text: &str, references: &mut References) -> usize {
+    let Some(label_end) = link_label(text, 0) else {
+        return 0;
+    };
+    if text.as_bytes().get(label_end) != Some(&b':') {
+        return 0;
+    }
+    let pos = skip_spaces_and_newline(text, label_end + 1);
+    let Some((link, before_title)) = link_destination(text, pos) else {
+        return 0;
+    };
+    if link.is_empty() {
+        return 0;
+    }
+    let pos = skip_spaces_and_newline(text, before_title);
+    let title = if pos > before_title {
+        link_title(text, pos)
+    } else {
+        None
+    };
+    let end = match title {
+        Some((title, pos)) if line_end(text, pos).is_some() => Some((title, line_end(text, pos).unwrap())),
+        // a title followed by something else on its line isn't part of the definition
+        _ => line_end(text, before_title).map(|end| (Cow::Borrowed(""), end)),
+    };
+    let Some((title, end)) = end else {
+        return 0;
+    };
+    let label = normalize_reference(&text[..label_end]);
+    if label.is_empty() {
+        return 0;
+    }
+    references.entry(label).or_insert(LinkReference {
+        link: link.into_owned(),
+        title: title.into_owned(),
+    });
+    end
+}
+
+/// Position after the spaces and the newline at `pos`, if there is nothing else until the end of
+/// the line.
+fn line_end(text: &str, pos: usize) -> Option<usize> {
+    let rest = text[pos..].trim_start_matches(' ');
+    if rest.is_empty() {
+        Some(text.len())
+    } else if rest.starts_with('\n') {
+        Some(text.len() - rest.len() + 1)
+    } else {
+        None
+    }
+}
+
+const ROOT: usize = 0;
+
+/// Text of an inline, a range of the subject unless it had to change, e.g. a decoded entity.
+#[derive(Debug)]
+enum Text {
+    Subject(Range<usize>),
+    Owned(String),
+}
+
+impl Text {
+    fn as_str<'s>(&'s self, subject: &'s str) -> &'s str {
+        match self {
+            Text::Subject(range) => &subject[range.clone()],
+            Text::Owned(text) => text,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Text::Subject(range) => range.len(),
+            Text::Owned(text) => text.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn truncate(&mut self, len: usize) {
+        match self {
+            Text::Subject(range) => range.end = range.start + len,
+            Text::Owned(text) => text.truncate(len),
+        }
+    }
+
+    fn take(&mut self) -> Text {
+        std::mem::replace(self, Text::Owned(String::new()))
+    }
+
+    /// Appends `text`, which stays a range of the subject when the two follow each other there.
+    fn append(&mut self, text: Text, subject: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = text;
+            return;
+        }
+        if let (Text::Subject(range), Text::Subject(next)) = (&mut *self, &text) {
+            if range.end == next.start {
+                range.end = next.end;
+                return;
+            }
+        }
+        let mut appended = match self.take() {
+            Text::Subject(range) => subject[range].to_string(),
+            Text::Owned(owned) => owned,
+        };
+        appended.push_str(text.as_str(subject));
+        *self = Text::Owned(appended);
+    }
+
+    /// Borrows the text from the subject, unless the subject itself had to be copied.
+    fn into_cow<'a>(self, subject: &Cow<'a, str>) -> Cow<'a, str> {
+        match (self, subject) {
+            (Text::Subject(range), Cow::Borrowed(subject)) => Cow::Borrowed(&subject[range]),
+            (Text::Subject(range), Cow::Owned(subject)) => Cow::Owned(subject[range].to_string()),
+            (Text::Owned(text), _) => Cow::Owned(text),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Inline {
+    Root,
+    /// Literal text, delimiter runs and brackets are text until they are matched.
+    Text(Text),
+    Emphasis,
+    Strong,
+    Link {
+        link: Text,
+        title: Text,
+    },
+    Image {
+        link: Text,
+        title: Text,
+    },
+    Span {
+        lang: Range<usize>,
+    },
+    Code(Text),
+    Html(Range<usize>),
+    HardBreak,
+    WikiLink {
+        target: Range<usize>,
+        heading: Option<Range<usize>>,
+        text: Range<usize>,
+    },
+}
+
+/// Inlines form a tree of doubly linked siblings, so that runs of nodes can be moved under a new
+/// emphasis or link node once its closing delimiter is found.
+#[derive(Debug)]
+struct Node {
+    inline: Inline,
+    parent: Option<usize>,
+    prev: Option<usize>,
+    next: Option<usize>,
+    first_child: Option<usize>,
+    last_child: Option<usize>,
+}
+
+impl Node {
+    fn new(inline: Inline) -> Node {
+        Node {
+            inline,
+            parent: None,
+            prev: None,
+            next: None,
+            first_child: None,
+            last_child: None,
+        }
+    }
+}
+
+/// Run of `*` or `_` that can open or close emphasis.
+#[derive(Debug)]
+struct Delimiter {
+    delimiter: u8,
+    count: usize,
+    original_count: usize,
+    node: usize,
+    can_open: bool,
+    can_close: bool,
+    removed: bool,
+}
+
+/// `[` or `![` waiting for its `]`.
+#[derive(Debug, Clone, Copy)]
+struct Bracket {
+    node: usize,
+    /// Position of the `[` in the subject.
+    index: usize,
+    image: bool,
+    /// Brackets inside of a link can't open another link.
+    active: bool,
+    /// There is another bracket after this one, so it can't be a shortcut reference.
+    bracket_after: bool,
+    /// Number of delimiters before the bracket, emphasis inside the link only uses the later ones.
+    previous_delimiters: usize,
+}
+
+struct InlineParser<'s, 'r> {
+    subject: &'s str,
+    pos: usize,
+    nodes: Vec<Node>,
+    delimiters: Vec<Delimiter>,
+    brackets: Vec<Bracket>,
+    /// First node text can be merged into, the ones before can be delimiters or brackets.
+    mergeable: usize,
+    references: &'r References,
+    extensions: Extensions,
+}
+
+impl<'s> InlineParser<'s, '_> {
+    fn parse(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b'\n' => self.parse_newline(),
+                b'\\' => self.parse_backslash(),
+                b'`' => self.parse_backticks(),
+                b'*' | b'_' => self.parse_delimiter_run(c),
+                b'[' => self.parse_open_bracket(),
+                b'!' => self.parse_bang(),
+                b']' => self.parse_close_bracket(),
+                b'<' => self.parse_angle_bracket(),
+                b'&' => self.parse_entity(),
+                _ => self.parse_string(),
+            }
+        }
+        self.process_emphasis(0);
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.subject.as_bytes().get(self.pos).copied()
+    }
+
+    fn rest(&self) -> &'s str {
+        &self.subject[self.pos..]
+    }
+
+    fn push(&mut self, inline: Inline) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(Node::new(inline));
+        self.append_child(ROOT, node);
+        node
+    }
+
+    /// Pushes a range of the subject, merged into the text before it when they are adjacent.
+    fn push_text(&mut self, text: Range<usize>) {
+        if let Some(last) = self.nodes[ROOT].last_child.filter(|&last| last >= self.mergeable) {
+            if let Inline::Text(Text::Subject(before)) = &mut self.nodes[last].inline {
+                if before.end == text.start {
+                    before.end = text.end;
+                    return;
+                }
+            }
+        }
+        self.push(Inline::Text(Text::Subject(text)));
+    }
+
+    /// Pushes the text of a delimiter run or a bracket, which stays a node of its own.
+    fn push_marker(&mut self, text: Range<usize>) -> usize {
+        let node = self.push(Inline::Text(Text::Subject(text)));
+        self.mergeable = node + 1;
+        node
+    }
+
+    /// Range of the `len` bytes at the position, which is moved after them.
+    fn take(&mut self, len: usize) -> Range<usize> {
+        let text = self.pos..self.pos + len;
+        self.pos += len;
+        text
+    }
+
+    /// Range of `text` in the subject if it is a slice of it, otherwise a copy.
+    fn text_of(&self, text: Cow<'_, str>) -> Text {
+        match text {
+            Cow::Borrowed(slice) => match self.offset_of(slice) {
+                Some(offset) => Text::Subject(offset..offset + slice.len()),
+                None => Text::Owned(slice.to_string()),
+            },
+            Cow::Owned(text) => Text::Owned(text),
+        }
+    }
+
+    /// Position of `text` in the subject, if it is a slice of it.
+    fn offset_of(&self, text: &str) -> Option<usize> {
+        let offset = (text.as_ptr() as usize).checked_sub(self.subject.as_ptr() as usize)?;
+        (offset + text.len() <= self.subject.len()).then_some(offset)
+    }
+
+    fn append_child(&mut self, parent: usize, child: usize) {
+        self.nodes[child].parent = Some(parent);
+        self.nodes[child].prev = self.nodes[parent].last_child;
+        self.nodes[child].next = None;
+        match self.nodes[parent].last_child {
+            Some(last) => self.nodes[last].next = Some(child),
+            None => self.nodes[parent].first_child = Some(child),
+        }
+        self.nodes[parent].last_child = Some(child);
+    }
+
+    fn insert_after(&mut self, node: usize, sibling: usize) {
+        let next = self.nodes[node].next;
+        let parent = self.nodes[node].parent;
+        self.nodes[sibling].parent = parent;
+        self.nodes[sibling].prev = Some(node);
+        self.nodes[sibling].next = next;
+        self.nodes[node].next = Some(sibling);
+        match next {
+            Some(next) => self.nodes[next].prev = Some(sibling),
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent].last_child = Some(sibling);
+                }
+            }
+        }
+    }
+
+    fn unlink(&mut self, node: usize) {
+        let Node { parent, prev, next, .. } = self.nodes[node];
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent].first_child = next;
+                }
+            }
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent].last_child = prev;
+                }
+            }
+        }
+        self.nodes[node].parent = None;
+        self.nodes[node].prev = None;
+        self.nodes[node].next = None;
+    }
+
+    /// Moves the siblings following `first` (up to, but without `end`) under `parent`.
+    fn adopt_siblings(&mut self, parent: usize, first: usize, end: Option<usize>) {
+        let mut sibling = self.nodes[first].next;
+        while let Some(node) = sibling {
+            if Some(node) == end {
+                break;
+            }
+            sibling = self.nodes[node].next;
+            self.unlink(node);
+            self.append_child(parent, node);
+        }
+    }
+
+    fn parse_string(&mut self) {
+        let len = self
+            .rest()
+            .bytes()
+            .position(is_special)
+            .unwrap_or(self.rest().len())
+            .max(1);
+        let text = self.take(len);
+        self.push_text(text);
+    }
+
+    /// A newline after two or more spaces is a hard line break, otherwise it stays in the text.
+    fn parse_newline(&mut self) {
+        let newline = self.take(1);
+        let subject = self.subject;
+        let last = self.nodes[ROOT].last_child.map(|last| &mut self.nodes[last].inline);
+        let hard_break = match last {
+            Some(Inline::Text(text)) if text.as_str(subject).ends_with(' ') => {
+                let hard_break = text.as_str(subject).ends_with("  ");
+                text.truncate(text.as_str(subject).trim_end_matches(' ').len());
+                hard_break
+            }
+            _ => false,
+        };
+        if hard_break {
+            self.push(Inline::HardBreak);
+        } else {
+            self.push_text(newline);
+        }
+        while self.peek() == Some(b' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_backslash(&mut self) {
+        let backslash = self.take(1);
+        match self.peek() {
+            Some(b'\n') => {
+                self.pos += 1;
+                self.push(Inline::HardBreak);
+            }
+            Some(c) if c.is_ascii_punctuation() => {
+                let escaped = self.take(1);
+                self.push_text(escaped);
+            }
+            _ => {
+                self.push_text(backslash);
+            }
+        }
+    }
+
+    fn parse_backticks(&mut self) {
+        let ticks = self.rest().bytes().take_while(|&c| c == b'`').count();
+        let after_opening = self.pos + ticks;
+        let bytes = self.subject.as_bytes();
+        let mut i = after_opening;
+        while i < bytes.len() {
+            if bytes[i] != b'`' {
+                i += 1;
+                continue;
+            }
+            let run_start = i;
+            while i < bytes.len() && bytes[i] == b'`' {
+                i += 1;
+            }
+            if i - run_start == ticks {
+                // line endings are spaces, one space on both sides allows code that starts or ends
+                // with a backtick
+                let (mut start, mut end) = (after_opening, run_start);
+                let is_space = |c: u8| matches!(c, b' ' | b'\n');
+                if end - start == 1 && is_space(bytes[start]) {
+                    start = end;
+                } else if end - start >= 2 && is_space(bytes[start]) && is_space(bytes[end - 1]) {
+                    start += 1;
+                    end -= 1;
+                }
+                let code = &self.subject[start..end];
+                let code = if code.contains('\n') {
+                    Text::Owned(code.replace('\n', " "))
+                } else {
+                    Text::Subject(start..end)
+                };
+                self.pos = i;
+                self.push(Inline::Code(code));
+                return;
+            }
+        }
+        // no closing run of the same length, the backticks are literal
+        let text = self.take(ticks);
+        self.push_text(text);
+    }
+
+    fn parse_delimiter_run(&mut self, delimiter: u8) {
+        let start = self.pos;
+        let count = self.rest().bytes().take_while(|&c| c == delimiter).count();
+        let before = self.subject[..start].chars().next_back().unwrap_or('\n');
+        let after = self.subject[start + count..].chars().next().unwrap_or('\n');
+        let left_flanking =
+            !after.is_whitespace() && (!is_punctuation(after) || before.is_whitespace() || is_punctuation(before));
+        let right_flanking =
+            !before.is_whitespace() && (!is_punctuation(before) || after.is_whitespace() || is_punctuation(after));
+        let (can_open, can_close) = if delimiter == b'_' {
+            (
+                left_flanking && (!right_flanking || is_punctuation(before)),
+                right_flanking && (!left_flanking || is_punctuation(after)),
+            )
+        } else {
+            (left_flanking, right_flanking)
+        };
+        let text = self.take(count);
+        let node = self.push_marker(text);
+        if can_open || can_close {
+            self.delimiters.push(Delimiter {
+                delimiter,
+                count,
+                original_count: count,
+                node,
+                can_open,
+                can_close,
+                removed: false,
+            });
+        }
+    }
+
+    fn previous_delimiter(&self, delimiter: usize, bottom: usize) -> Option<usize> {
+        (bottom..delimiter).rev().find(|&i| !self.delimiters[i].removed)
+    }
+
+    /// Matches the openers and closers above `stack_bottom` into emphasis, then drops them from
+    /// the stack.
+    fn process_emphasis(&mut self, stack_bottom: usize) {
+        // lowest opener worth looking at for `*` and `_` closers
+        let mut openers_bottom = [stack_bottom; 2];
+        let bottom_index = |delimiter: u8| usize::from(delimiter == b'_');
+        let mut odd_match = false;
+        let mut closer = stack_bottom;
+        while closer < self.delimiters.len() {
+            if self.delimiters[closer].removed || !self.delimiters[closer].can_close {
+                closer += 1;
+                continue;
+            }
+            let delimiter = self.delimiters[closer].delimiter;
+            let mut opener = self.previous_delimiter(closer, stack_bottom);
+            let mut opener_found = None;
+            while let Some(candidate) = opener.filter(|&candidate| candidate >= openers_bottom[bottom_index(delimiter)])
+            {
+                let (o, c) = (&self.delimiters[candidate], &self.delimiters[closer]);
+                // `*foo**bar*` is not `<em>foo</em><em>bar</em>`
+                odd_match = (c.can_open || o.can_close) && (o.original_count + c.original_count) % 3 == 0;
+                if o.delimiter == delimiter && o.can_open && !odd_match {
+                    opener_found = Some(candidate);
+                    break;
+                }
+                opener = self.previous_delimiter(candidate, stack_bottom);
+            }
+            let Some(opener) = opener_found else {
+                if !odd_match {
+                    openers_bottom[bottom_index(delimiter)] = self
+                        .previous_delimiter(closer, stack_bottom)
+                        .map_or(stack_bottom, |i| i + 1);
+                    if !self.delimiters[closer].can_open {
+                        self.delimiters[closer].removed = true;
+                    }
+                }
+                closer += 1;
+                continue;
+            };
+
+            let used = if self.delimiters[opener].count >= 2 && self.delimiters[closer].count >= 2 {
+                2
+            } else {
+                1
+            };
+            let (opener_node, closer_node) = (self.delimiters[opener].node, self.delimiters[closer].node);
+            for delimiter in [opener, closer] {
+                self.delimiters[delimiter].count -= used;
+                if let Inline::Text(text) = &mut self.nodes[self.delimiters[delimiter].node].inline {
+                    text.truncate(text.len() - used);
+                }
+            }
+            let emphasis = self.nodes.len();
+            self.nodes
+                .push(Node::new(if used == 1 { Inline::Emphasis } else { Inline::Strong }));
+            self.adopt_siblings(emphasis, opener_node, Some(closer_node));
+            self.insert_after(opener_node, emphasis);
+            for between in &mut self.delimiters[opener + 1..closer] {
+                between.removed = true;
+            }
+            if self.delimiters[opener].count == 0 {
+                self.unlink(opener_node);
+                self.delimiters[opener].removed = true;
+            }
+            if self.delimiters[closer].count == 0 {
+                self.unlink(closer_node);
+                self.delimiters[closer].removed = true;
+                closer += 1;
+            }
+        }
+        self.delimiters.truncate(stack_bottom);
+    }
+
+    fn push_bracket(&mut self, node: usize, index: usize, image: bool) {
+        if let Some(last) = self.brackets.last_mut() {
+            last.bracket_after = true;
+        }
+        self.brackets.push(Bracket {
+            node,
+            index,
+            image,
+            active: true,
+            bracket_after: false,
+            previous_delimiters: self.delimiters.len(),
+        });
+    }
+
+    fn parse_open_bracket(&mut self) {
+        if self.extensions.wiki_links {
+            if let Some((len, wiki_link)) = wiki_link(self.subject, self.pos) {
+                self.pos += len;
+                self.push(wiki_link);
+                return;
+            }
+        }
+        let index = self.pos;
+        let text = self.take(1);
+        let node = self.push_marker(text);
+        self.push_bracket(node, index, false);
+    }
+
+    fn parse_bang(&mut self) {
+        if self.subject.as_bytes().get(self.pos + 1) == Some(&b'[') {
+            let index = self.pos + 1;
+            let text = self.take(2);
+            let node = self.push_marker(text);
+            self.push_bracket(node, index, true);
+        } else {
+            let text = self.take(1);
+            self.push_text(text);
+        }
+    }
+
+    fn parse_close_bracket(&mut self) {
+        let bracket = self.take(1);
+        let start = self.pos;
+        let Some(&opener) = self.brackets.last() else {
+            self.push_text(bracket);
+            return;
+        };
+        if !opener.active {
+            self.brackets.pop();
+            self.push_text(bracket);
+            return;
+        }
+        if self.extensions.spans && !opener.image && self.peek() == Some(b'{') {
+            if let Some(capture) = SPAN_ATTRIBUTES_PATTERN.captures(self.rest()) {
+                let lang = capture.get(1).or(capture.get(2)).unwrap().range();
+                self.pos += capture[0].len();
+                self.wrap_in(
+                    opener,
+                    Inline::Span {
+                        lang: start + lang.start..start + lang.end,
+                    },
+                );
+                return;
+            }
+        }
+
+        let mut target = None;
+        if self.peek() == Some(b'(') {
+            target = self.inline_link_target();
+        }
+        if target.is_none() {
+            let label_end = link_label(self.subject, start);
+            let label = match label_end {
+                Some(end) if end - start > 2 => Some(&self.subject[start..end]),
+                // `[foo][]` and `[foo]` use the link text as label
+                _ if !opener.bracket_after => Some(&self.subject[opener.index..start]),
+                _ => None,
+            };
+            self.pos = label_end.unwrap_or(start);
+            target = label
+                .and_then(|label| self.references.get(&normalize_reference(label)))
+                .map(|reference| {
+                    (
+                        Text::Owned(reference.link.clone()),
+                        Text::Owned(reference.title.clone()),
+                    )
+                });
+        }
+
+        let Some((link, title)) = target else {
+            self.brackets.pop();
+            self.pos = start;
+            self.push_text(bracket);
+            return;
+        };
+        let inline = if opener.image {
+            Inline::Image { link, title }
+        } else {
+            Inline::Link { link, title }
+        };
+        self.wrap_in(opener, inline);
+        // links can't contain other links
+        if !opener.image {
+            for bracket in &mut self.brackets {
+                if !bracket.image {
+                    bracket.active = false;
+                }
+            }
+        }
+    }
+
+    /// Moves everything after the `opener` bracket under a new node, the bracket itself is dropped.
+    fn wrap_in(&mut self, opener: Bracket, inline: Inline) {
+        let node = self.nodes.len();
+        self.nodes.push(Node::new(inline));
+        self.adopt_siblings(node, opener.node, None);
+        self.append_child(ROOT, node);
+        self.process_emphasis(opener.previous_delimiters);
+        self.brackets.pop();
+        self.unlink(opener.node);
+    }
+
+    /// `(link "title")` following the link text.
+    fn inline_link_target(&mut self) -> Option<(Text, Text)> {
+        let pos = skip_spaces_and_newline(self.subject, self.pos + 1);
+        let (link, after_link) = link_destination(self.subject, pos)?;
+        let pos = skip_spaces_and_newline(self.subject, after_link);
+        let title = if self.subject[..pos].ends_with(is_whitespace) {
+            link_title(self.subject, pos)
+        } else {
+            None
+        };
+        let (title, pos) = title.unwrap_or((Cow::Borrowed(""), pos));
+        let pos = skip_spaces_and_newline(self.subject, pos);
+        if self.subject.as_bytes().get(pos) != Some(&b')') {
+            return None;
+        }
+        self.pos = pos + 1;
+        Some((self.text_of(link), self.text_of(title)))
+    }
+
+    /// Autolink, raw html or a literal `<`.
+    fn parse_angle_bracket(&mut self) {
+        let rest = self.rest();
+        let autolink = if let Some(capture) = EMAIL_AUTOLINK_PATTERN.captures(rest) {
+            let address = capture.get(1).unwrap();
+            let link = normalize_uri(Cow::Owned(format!("mailto:{}", address.as_str())));
+            Some((capture[0].len(), self.text_of(link), address.range()))
+        } else {
+            AUTOLINK_PATTERN.captures(rest).map(|capture| {
+                let link = capture.get(1).unwrap();
+                let uri = normalize_uri(Cow::Borrowed(link.as_str()));
+                (capture[0].len(), self.text_of(uri), link.range())
+            })
+        };
+        if let Some((len, link, text)) = autolink {
+            let text = self.pos + text.start..self.pos + text.end;
+            self.pos += len;
+            let node = self.push(Inline::Link {
+                link,
+                title: Text::Owned(String::new()),
+            });
+            let child = self.nodes.len();
+            self.nodes.push(Node::new(Inline::Text(Text::Subject(text))));
+            self.append_child(node, child);
+        } else if let Some(html) = HTML_TAG_PATTERN.find(rest) {
+            let html = self.take(html.len());
+            self.push(Inline::Html(html));
+        } else {
+            let text = self.take(1);
+            self.push_text(text);
+        }
+    }
+
+    fn parse_entity(&mut self) {
+        let rest = self.rest();
+        let decoded = entity_len(rest).and_then(|len| Some((len, decode_entity(&rest[..len])?)));
+        match decoded {
+            Some((len, text)) => {
+                self.pos += len;
+                self.push(Inline::Text(Text::Owned(text)));
+            }
+            None => {
+                let text = self.take(1);
+                self.push_text(text);
+            }
+        }
+    }
+}
+
+/// `[[page#heading|text]]` at `pos` in the subject, and its length.
+fn wiki_link(subject: &str, pos: usize) -> Option<(usize, Inline)> {
+    if !subject[pos..].starts_with("[[") {
+        return None;
+    }
+    let bytes = subject.as_bytes();
+    let part_end =
+        |start: usize, end_chars: &[u8]| start + bytes[start..].iter().take_while(|c| !end_chars.contains(c)).count();
+    let mut end = part_end(pos + 2, b"[]|#");
+    let target = pos + 2..end;
+    let mut heading = None;
+    if bytes.get(end) == Some(&b'#') {
+        let start = end + 1;
+        end = part_end(start, b"[]|");
+        heading = Some(start..end);
+    }
+    let mut text = None;
+    if bytes.get(end) == Some(&b'|') {
+        let start = end + 1;
+        end = part_end(start, b"[]");
+        text = Some(start..end);
+    }
+    if !subject[end..].starts_with("]]") {
+        return None;
+    }
+    let trim = |range: Range<usize>| {
+        let part = &subject[range.clone()];
+        let start = range.start + part.len() - part.trim_start().len();
+        start..start + part.trim().len()
+    };
+    let target = trim(target);
+    let heading = heading.map(trim);
+    let text = match (text.map(trim), &heading) {
+        (Some(text), _) => text,
+        (None, Some(heading)) if target.is_empty() => heading.clone(),
+        (None, _) => pos + 2..end,
+    };
+    Some((end + 2 - pos, Inline::WikiLink { target, heading, text }))
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\x0b' | '\x0c' | '\r')
+}
+
+/// ASCII punctuation and the most common punctuation outside of it, std has no way to check the
+/// unicode category of a character.
+/// Bytes that can start something other than plain text.
+fn is_special(c: u8) -> bool {
+    matches!(c, b'\n' | b'\\' | b'`' | b'*' | b'_' | b'[' | b']' | b'!' | b'<' | b'&')
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(c, '¡' | '§' | '«' | '¶' | '·' | '»' | '¿' | '\u{2010}'..='\u{2027}' | '\u{2030}'..='\u{205e}' | '\u{3001}'..='\u{3003}' | '\u{3008}'..='\u{3011}')
+}
+
+/// Position after spaces, an optional newline and spaces following it.
+fn skip_spaces_and_newline(text: &str, pos: usize) -> usize {
+    let rest = text[pos..].trim_start_matches(' ');
+    let rest = rest.strip_prefix('\n').unwrap_or(rest).trim_start_matches(' ');
+    text.len() - rest.len()
+}
+
+/// End of a `[label]` starting at `pos`.
+fn link_label(text: &str, pos: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    if bytes.get(pos) != Some(&b'[') {
+        return None;
+    }
+    let mut i = pos + 1;
+    let mut len = 0;
+    while i < bytes.len() && len < 1000 {
+        match bytes[i] {
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 2,
+            b'[' => return None,
+            b']' => return Some(i + 1),
+            _ => i += 1,
+        }
+        len += 1;
+    }
+    None
+}
+
+/// `<link>` or a link without spaces and with balanced parentheses, and the position after it.
+fn link_destination(text: &str, pos: usize) -> Option<(Cow<'_, str>, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.get(pos) == Some(&b'<') {
+        let mut i = pos + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'>' => return Some((normalize_uri(unescape(&text[pos + 1..i])), i + 1)),
+                b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 2,
+                b'\n' | b'<' => break,
+                _ => i += 1,
+            }
+        }
+    }
+    let mut i = pos;
+    let mut open_parens = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                i += 1;
+                i += text[i..].chars().next().map_or(0, char::len_utf8);
+            }
+            b'(' => {
+                open_parens += 1;
+                i += 1;
+            }
+            b')' if open_parens == 0 => break,
+            b')' => {
+                open_parens -= 1;
+                i += 1;
+            }
+            b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r' => break,
+            _ => i += 1,
+        }
+    }
+    Some((normalize_uri(unescape(&text[pos..i])), i))
+}
+
+/// `"title"`, `'title'` or `(title)` and the position after it.
+fn link_title(text: &str, pos: usize) -> Option<(Cow<'_, str>, usize)> {
+    let bytes = text.as_bytes();
+    let closing = match bytes.get(pos) {
+        Some(b'"') => b'"',
+        Some(b'\'') => b'\'',
+        Some(b'(') => b')',
+        _ => return None,
+    };
+    let mut i = pos + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 2,
+            c if c == closing => return Some((unescape(&text[pos + 1..i]), i + 1)),
+            b'\0' => return None,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Labels match case-insensitively and regardless of whitespace, `[Foo  bar]` is `[foo bar]`.
+fn normalize_reference(label: &str) -> String {
+    label[1..label.len() - 1]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .to_uppercase()
+}
+
+/// Percent-encodes the characters that can't appear in a url, leaving existing escapes alone.
+fn normalize_uri(uri: Cow<'_, str>) -> Cow<'_, str> {
+    let bytes = uri.as_bytes();
+    let is_allowed = |i: usize| {
+        let c = bytes[i];
+        let escape = c == b'%'
+            && bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        escape || c.is_ascii_alphanumeric() || b";/?:@&=+$,-_.!~*'()#".contains(&c)
+    };
+    if (0..bytes.len()).all(is_allowed) {
+        return uri;
+    }
+    let mut normalized = String::with_capacity(uri.len());
+    for (i, &c) in bytes.iter().enumerate() {
+        if is_allowed(i) {
+            normalized.push(c as char);
+        } else {
+            normalized.push_str(&format!("%{c:02X}"));
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// Resolves backslash escapes and entities in link destinations and titles.
+pub(crate) fn unescape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['\\', '&']) {
+        return Cow::Borrowed(text);
+    }
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let next = rest[c.len_utf8()..].chars().next();
+        if c == '\\' && next.is_some_and(|c| c.is_ascii_punctuation()) {
+            unescaped.push(next.unwrap());
+            rest = &rest[2..];
+            continue;
+        }
+        if c == '&' {
+            if let Some(len) = entity_len(rest) {
+                if let Some(decoded) = decode_entity(&rest[..len]) {
+                    unescaped.push_str(&decoded);
+                    rest = &rest[len..];
+                    continue;
+                }
+            }
+        }
+        unescaped.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    Cow::Owned(unescaped)
+}
+
+/// Length of the `&name;`, `&#123;` or `&#x7b;` at the start of `text`.
+fn entity_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let count = |start: usize, is_valid: fn(&u8) -> bool| bytes.iter().skip(start).take_while(|c| is_valid(c)).count();
+    let (start, len, lengths) = match (bytes.get(1), bytes.get(2)) {
+        (Some(b'#'), Some(b'x' | b'X')) => (3, count(3, u8::is_ascii_hexdigit), 1..=6),
+        (Some(b'#'), _) => (2, count(2, u8::is_ascii_digit), 1..=7),
+        (Some(c), _) if c.is_ascii_alphabetic() => (1, count(1, u8::is_ascii_alphanumeric), 2..=32),
+        _ => return None,
+    };
+    let end = start + len;
+    (lengths.contains(&len) && bytes.get(end) == Some(&b';')).then_some(end + 1)
+}
+
+/// Text of `&name;`, `&#123;` or `&#x7b;`, `None` for unknown names.
+fn decode_entity(entity: &str) -> Option<String> {
+    let name = &entity[1..entity.len() - 1];
+    let Some(number) = name.strip_prefix('#') else {
+        return entity_for(name).map(str::to_string);
+    };
+    let code = match number.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => number.parse(),
+    };
+    let c = code
+        .ok()
+        .filter(|&code| code != 0)
+        .and_then(char::from_u32)
+        .unwrap_or('\u{fffd}');
+    Some(c.to_string())
+}
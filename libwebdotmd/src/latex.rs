@@ -0,0 +1,173 @@
+use crate::markdown::{Document, Element, ListType};
+use crate::plain_text::is_block;
+use crate::renderer::Renderer;
+
+/// Renders documents into LaTeX. `render` produces a standalone article, `render_body` just its
+/// body, so pages can also be included into a bigger document.
+pub struct LatexRenderer;
+
+impl Renderer for LatexRenderer {
+    fn render_element(&self, element: &Element) -> String {
+        use Element::*;
+        match element {
+            Text(text) | WikiLink { text, .. } | Substitution { text, html: _ } => escape_latex(text),
+            HardBreak => "\\\\\n".to_string(),
+            InlineCode(code) => format!("\\texttt{{{}}}", escape_latex(code)),
+            Emphasis(elements) => format!("\\emph{{{}}}", self.inlines(elements)),
+            Strong(elements) => format!("\\textbf{{{}}}", self.inlines(elements)),
+            Span { elements, .. } => self.inlines(elements),
+            Link { elements, link, .. } => {
+                format!("\\href{{{}}}{{{}}}", escape_url(link), self.inlines(elements))
+            }
+            Image { link, .. } => format!("\\includegraphics{{{}}}", escape_url(link)),
+            Abbreviation { text, title } => format!("{} ({})", escape_latex(text), escape_latex(title)),
+            InlineHtml(_) | HtmlBlock(_) => String::new(),
+            Paragraph(elements) => format!("{}\n", self.inlines(elements)),
+            Header { level, elements } => {
+                let command = match level {
+                    1 => "section",
+                    2 => "subsection",
+                    3 => "subsubsection",
+                    4 => "paragraph",
+                    _ => "subparagraph",
+                };
+                format!("\\{command}{{{}}}\n", self.inlines(elements))
+            }
+            ThematicBreak => "\\par\\noindent\\rule{\\textwidth}{0.4pt}\n".to_string(),
+            Code { lang: _, code } => format!("\\begin{{verbatim}}\n{code}\\end{{verbatim}}\n"),
+            BlockQuote(elements) => format!("\\begin{{quote}}\n{}\\end{{quote}}\n", self.blocks(elements)),
+            List { list_type, items } => {
+                let (environment, start) = match list_type {
+                    ListType::Ordered { start, .. } if *start != 1 => {
+                        ("enumerate", format!("\\setcounter{{enumi}}{{{}}}\n", start - 1))
+                    }
+                    ListType::Ordered { .. } => ("enumerate", String::new()),
+                    ListType::Unordered { .. } => ("itemize", String::new()),
+                };
+                let items: String = items.iter().map(|item| format!("\\item {}", self.item(item))).collect();
+                format!("\\begin{{{environment}}}\n{start}{items}\\end{{{environment}}}\n")
+            }
+            DefinitionList { items } => {
+                let mut rendered = String::new();
+                for item in items {
+                    rendered.push_str(&format!("\\item[{}]", self.inlines(&item.term)));
+                    for definition in &item.definitions {
+                        rendered.push_str(&format!(" {}\n", self.inlines(definition)));
+                    }
+                }
+                format!("\\begin{{description}}\n{rendered}\\end{{description}}\n")
+            }
+        }
+    }
+
+    fn render(&self, document: &Document) -> String {
+        format!(
+            "\\documentclass{{article}}
+\\usepackage[utf8]{{inputenc}}
+\\usepackage{{graphicx}}
+\\usepackage{{hyperref}}
+
+\\begin{{document}}
+
+{}
+\\end{{document}}
+",
+            self.render_body(document)
+        )
+    }
+}
+
+impl LatexRenderer {
+    pub fn render_body(&self, document: &Document) -> String {
+        self.blocks(document.elements())
+    }
+
+    fn inlines(&self, elements: &[Element]) -> String {
+        elements.iter().map(|el| self.render_element(el)).collect()
+    }
+
+    fn blocks(&self, elements: &[Element]) -> String {
+        elements
+            .iter()
+            .map(|el| self.render_element(el))
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn item(&self, elements: &[Element]) -> String {
+        if elements.iter().any(is_block) {
+            self.blocks(elements)
+        } else {
+            format!("{}\n", self.inlines(elements))
+        }
+    }
+}
+
+/// Escapes characters that have a meaning in LaTeX, non-breaking spaces become ties.
+fn escape_latex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '\u{a0}' => escaped.push('~'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Urls are taken verbatim by `\href`, only characters that would break the argument are escaped.
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for c in url.chars() {
+        if matches!(c, '%' | '#' | '{' | '}' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatexRenderer;
+    use crate::markdown::Document;
+
+    #[test]
+    fn test_render_latex() {
+        let document = Document::parse(
+            "## Costs & *profits*
+
+Up 50% with a [link](https://example.com/#top).
+
+3. third
+4. fourth
+
+```
+x_1 = {}
+```",
+        );
+        let expected = r"\subsection{Costs \& \emph{profits}}
+
+Up 50\% with a \href{https://example.com/\#top}{link}.
+
+\begin{enumerate}
+\setcounter{enumi}{2}
+\item third
+\item fourth
+\end{enumerate}
+
+\begin{verbatim}
+x_1 = {}
+\end{verbatim}
+";
+        assert_eq!(expected, LatexRenderer.render_body(&document));
+    }
+}
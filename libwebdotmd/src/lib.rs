@@ -1,3 +1,35 @@
+mod block;
+#[cfg(test)]
+mod commonmark_spec;
+mod config;
+mod element_templates;
+mod emoji;
+mod entities;
+mod epub;
+mod events;
+mod gemtext;
+mod html;
+mod inline;
+mod latex;
 mod markdown;
+mod plain_text;
+mod reading;
+mod renderer;
+mod substitutions;
+mod typography;
+mod wiki_links;
+mod zip;
 
-pub use markdown::{load_markdown_pages, MarkdownPage, write_html_pages};
+pub use config::Config;
+pub use epub::write_epubs;
+pub use events::{Event, Parser, Tag, TagEnd};
+pub use gemtext::GemtextRenderer;
+pub use html::{render_html, HtmlRenderer};
+pub use latex::LatexRenderer;
+pub use markdown::{
+    load_markdown_pages, render_pages, write_html_pages, write_pages, DefinitionItem, Document, Element, ListType,
+    MarkdownPage,
+};
+pub use plain_text::PlainTextRenderer;
+pub use renderer::{Renderer, TemplateRenderer};
+pub use wiki_links::{resolve_wiki_links, WikiLinkError, WikiLinkErrorKind};
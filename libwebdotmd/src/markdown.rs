@@ -1,216 +1,459 @@
-use once_cell::sync::Lazy;
-use regex::Regex;
-use std::collections::HashMap;
-use std::path::Path;
+use crate::block::Extensions;
+use crate::config::Config;
+use crate::events::parse_document;
+use crate::html::escape_html;
+use crate::reading::{count_words, excerpt};
+use crate::renderer::{Renderer, TemplateRenderer};
+use crate::substitutions::{apply_substitutions, replace_emoji_shortcodes};
+use crate::typography::{apply_typography, text_direction, Typography};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use webdotx::utils::load_files_in_dir_to_string;
-use webdotx::{FuncMap, Renderable, Template};
+use webdotx::{Context, Registry, RenderError, Renderable, Template, Value};
 
 #[derive(Debug)]
 pub struct MarkdownPage {
     /// starts from first line in the file and ends on line ------
-    metadata: Metadata,
-    content: Content,
+    pub(crate) metadata: Metadata,
+    pub(crate) content: Document,
 }
 
 impl MarkdownPage {
     pub fn get_metadata(&self, name: &str) -> Option<&String> {
         self.metadata.info.get(name)
     }
+
+    pub fn document(&self) -> &Document {
+        &self.content
+    }
+
+    /// Hidden pages, with `hidden: true`, are left out of every output.
+    pub fn is_hidden(&self) -> bool {
+        self.get_metadata("hidden").is_some_and(|hidden| hidden == "true")
+    }
+
+    /// Values the page's template is filled with, `content` and `outline` are empty until the
+    /// page is rendered.
+    pub fn template_values(&self) -> Context {
+        let mut values = Context::from(self.metadata.info.clone());
+        values.insert("content", Value::Safe(String::new()));
+        values.insert("outline", Value::Safe(String::new()));
+        // metadata can also be reached as `{{ $page.meta.author$ }}`, even if it shares a name with
+        // one of the placeholders above
+        let page = HashMap::from([("meta".to_string(), self.metadata.info.clone())]);
+        values.insert("page", page);
+        values
+    }
+
+    /// Words of text and code on the page.
+    pub fn word_count(&self) -> usize {
+        count_words(&self.content.elements).total()
+    }
+
+    /// Minutes needed to read the page, code is read at `config.code_words_per_minute`.
+    pub fn reading_time(&self, config: &Config) -> usize {
+        count_words(&self.content.elements).reading_time(config)
+    }
+
+    /// Text up to `<!-- more -->`, or the first paragraph of the page.
+    pub fn excerpt(&self) -> String {
+        excerpt(&self.content.elements)
+    }
+
+    /// Adds `word_count`, `time_to_read`, `excerpt` and `summary` to the metadata, unless the
+    /// author has set them.
+    fn fill_reading_metadata(&mut self, config: &Config) {
+        let words = count_words(&self.content.elements);
+        let excerpt = self.excerpt();
+        let defaults = [
+            ("word_count", words.total().to_string()),
+            ("time_to_read", format!("{} min", words.reading_time(config))),
+            ("summary", excerpt.clone()),
+            ("excerpt", excerpt),
+        ];
+        for (key, value) in defaults {
+            self.metadata.info.entry(key.to_string()).or_insert(value);
+        }
+    }
+}
+
+/// Anchor for a header with the text, e.g. `getting-started` for "Getting Started!". Words are
+/// lowercased and joined with `-`, letters outside ASCII are percent-encoded.
+pub(crate) fn slug(text: &str) -> String {
+    let slug = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-");
+    let mut encoded = String::with_capacity(slug.len());
+    for byte in slug.bytes() {
+        if byte.is_ascii() {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
 }
 
-fn header_to_text(header: &str) -> String {
-    header
-        .replace(">", "")
-        .replace("<", "")
-        .replace("\n", "")
-        .replace("\"", "'")
-    // .replace(" ", "_")
+/// Anchors of the headers of a page, a header with the same slug as an earlier one gets a number
+/// after it, e.g. `usage-1`.
+#[derive(Default)]
+pub(crate) struct Anchors {
+    used: HashSet<String>,
 }
 
-impl Renderable for MarkdownPage {
-    fn render(&self, templates: &HashMap<String, Template>, autofill_funcs: &Option<FuncMap>) -> Option<String> {
-        if let Some(hidden) = self.get_metadata("hidden") {
-            if hidden == "true" {
-                return None;
+impl Anchors {
+    /// Anchor placed before the next header of the page, it is also used by the outline and wiki
+    /// links to point at the header.
+    pub(crate) fn next(&mut self, elements: &[Element]) -> String {
+        let slug = match slug(&plain_text(elements)) {
+            slug if slug.is_empty() => "section".to_string(),
+            slug => slug,
+        };
+        let mut anchor = slug.clone();
+        let mut count = 0;
+        while !self.used.insert(anchor.clone()) {
+            count += 1;
+            anchor = format!("{slug}-{count}");
+        }
+        anchor
+    }
+}
+
+/// Every header of the page, including ones nested in e.g. block quotes, with its anchor, in the
+/// order they are rendered in.
+pub(crate) fn header_anchors(elements: &[Element]) -> Vec<(&[Element], String)> {
+    fn collect<'a>(elements: &'a [Element], anchors: &mut Anchors, headers: &mut Vec<(&'a [Element], String)>) {
+        for el in elements {
+            match el {
+                Element::Header { level: _, elements } => headers.push((elements, anchors.next(elements))),
+                el => {
+                    for children in el.children() {
+                        collect(children, anchors, headers);
+                    }
+                }
             }
         }
+    }
+    let mut headers = vec![];
+    collect(elements, &mut Anchors::default(), &mut headers);
+    headers
+}
+
+pub(crate) fn plain_text(elements: &[Element]) -> String {
+    use Element::*;
+    elements
+        .iter()
+        .map(|el| match el {
+            Text(text) | InlineCode(text) | Substitution { text, html: _ } => text.to_string(),
+            HardBreak => "\n".to_string(),
+            // markup isn't text
+            InlineHtml(_) | HtmlBlock(_) | ThematicBreak => String::new(),
+            Header { level: _, elements }
+            | Paragraph(elements)
+            | BlockQuote(elements)
+            | Emphasis(elements)
+            | Strong(elements)
+            | Link { elements, .. }
+            | Span { elements, .. } => plain_text(elements),
+            Image { alt: text, .. } | WikiLink { text, .. } | Abbreviation { text, title: _ } => text.to_string(),
+            List { list_type: _, items } => items.iter().map(|item| plain_text(item)).collect::<Vec<_>>().join(" "),
+            DefinitionList { items } => items
+                .iter()
+                .flat_map(|item| std::iter::once(&item.term).chain(&item.definitions))
+                .map(|elements| plain_text(elements))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Code { lang: _, code } => code.to_string(),
+        })
+        .collect()
+}
+
+impl Renderable for MarkdownPage {
+    fn render(
+        &self,
+        templates: &HashMap<String, Template>,
+        registry: &Registry,
+    ) -> Result<Option<String>, RenderError> {
+        if self.is_hidden() {
+            return Ok(None);
+        }
+        let template_name = &self.metadata.info["template"];
+        let template = templates
+            .get(template_name)
+            .ok_or_else(|| RenderError::unknown_template(template_name, templates))?;
+        // pages can pick their own element templates, e.g. `element_templates: templates/elements/slides/`
+        let renderer = TemplateRenderer::new(
+            templates,
+            self.get_metadata("element_templates").map(String::as_str),
+            registry,
+        )
+        .with_permalinks(self.get_metadata("permalinks").is_some_and(|enabled| enabled == "true"));
         let mut content = String::new();
         let mut outline = String::new();
-        for el in &self.content.elements {
-            let rendered = &el.render(templates, autofill_funcs);
-            let Some(rendered) = rendered else {
-                continue;
-            };
-            content.push_str(rendered);
-            if let Element::Header { level: _, elements } = el {
-                let content = elements
-                    .iter()
-                    .map(|el| el.render(templates, autofill_funcs).unwrap())
-                    .collect::<String>();
-                let text = &header_to_text(&content);
-                outline.push_str(r##"<li class="hover:text-l-accent-secondary dark:hover:text-d-accent-secondary">"##);
-                outline.push_str("<a href=\"#");
-                outline.push_str(text);
-                outline.push_str("\">");
-                outline.push_str(text);
-                outline.push_str("</a></li><br>\n");
+        for (i, el) in self.content.elements.iter().enumerate() {
+            if i > 0 {
+                content.push_str(r#"<div class="py-1.5"></div>"#);
             }
+            content.push_str(&renderer.try_render_element(el)?);
         }
-        let mut filled_placeholders = self.metadata.info.clone();
-        filled_placeholders.insert("content".to_string(), content);
-        filled_placeholders.insert("outline".to_string(), outline);
-        Some(
-            templates
-                .get(&self.metadata.info["template"])
-                .expect("Template to be found")
-                .fill_template(filled_placeholders, autofill_funcs),
-        )
+        for (elements, anchor) in header_anchors(&self.content.elements) {
+            outline.push_str(r##"<li class="hover:text-l-accent-secondary dark:hover:text-d-accent-secondary">"##);
+            outline.push_str("<a href=\"#");
+            outline.push_str(&anchor);
+            outline.push_str("\">");
+            outline.push_str(&escape_html(&plain_text(elements)));
+            outline.push_str("</a></li><br>\n");
+        }
+        let mut filled_placeholders = self.template_values();
+        filled_placeholders.insert("content", Value::Safe(content));
+        filled_placeholders.insert("outline", Value::Safe(outline));
+        template.fill_template(filled_placeholders, registry).map(Some)
     }
 }
 
 #[derive(PartialEq, Debug)]
-struct Metadata {
+pub(crate) struct Metadata {
     /// each line is treated as a key, value pair in the form of key: value
-    info: HashMap<String, String>,
+    pub(crate) info: HashMap<String, String>,
 }
 
-#[derive(PartialEq, Debug)]
-struct Content {
-    elements: Vec<Element>,
+impl Metadata {
+    /// Sets `lang` to the one of the site unless the page has its own, and `dir` to the direction
+    /// of its script, e.g. `rtl` for `ar`.
+    fn fill_language(&mut self, config: &Config) {
+        let lang = self
+            .info
+            .entry("lang".to_string())
+            .or_insert_with(|| config.lang.clone())
+            .clone();
+        self.info
+            .entry("dir".to_string())
+            .or_insert_with(|| text_direction(&lang).to_string());
+    }
 }
 
-#[derive(PartialEq, Debug)]
-enum Element {
+/// Parsed markdown, ready to be rendered by a `Renderer`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Document {
+    pub(crate) elements: Vec<Element>,
+}
+
+impl Document {
+    /// Parses CommonMark with the extensions of the site: wiki links, definition lists,
+    /// abbreviations and `a)` lists.
+    pub fn parse(markdown: &str) -> Document {
+        Document {
+            elements: parse_document(markdown, Extensions::ALL),
+        }
+    }
+
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Element {
+    /// Text with soft line breaks kept as `\n`.
     Text(String),
-    Break,
-    Header {
-        level: usize,
+    HardBreak,
+    InlineCode(String),
+    Emphasis(Vec<Element>),
+    Strong(Vec<Element>),
+    Link {
         elements: Vec<Element>,
+        link: String,
+        /// Empty when the link has no title.
+        title: String,
     },
-    Link {
-        text: String,
+    Image {
+        alt: String,
         link: String,
+        title: String,
+    },
+    InlineHtml(String),
+    /// Value of a site substitution, `html` is put into html as it is and `text` is used by
+    /// renderers of other formats.
+    Substitution {
+        text: String,
+        html: String,
+    },
+    /// `[[target#heading|text]]` link to another page, turned into a `Link` by
+    /// `resolve_wiki_links`. `target` is the page name, empty when linking to a header of the
+    /// same page.
+    WikiLink {
+        target: String,
+        heading: Option<String>,
+        text: String,
+    },
+    /// Occurrence of a term defined with `*[term]: title` somewhere on the page.
+    Abbreviation {
+        text: String,
+        title: String,
+    },
+    /// `[text]{lang=pl}` text in another language than the rest of the page.
+    Span {
+        elements: Vec<Element>,
+        lang: String,
     },
+    Paragraph(Vec<Element>),
+    Header {
+        level: usize,
+        elements: Vec<Element>,
+    },
+    ThematicBreak,
+    BlockQuote(Vec<Element>),
+    /// Items of tight lists hold the contents of their paragraphs directly.
     List {
         list_type: ListType,
         items: Vec<Vec<Element>>,
     },
-    // BUG: for some reason when code block is the last thing in a markdown file then it isn't
-    // properly rendered into html (it's missing from the page)
+    DefinitionList {
+        items: Vec<DefinitionItem>,
+    },
     Code {
         lang: String,
         code: String,
     },
+    HtmlBlock(String),
 }
 
-#[derive(PartialEq, Debug)]
-enum ListType {
-    Ordered { symbol: String },
-    Unordered { symbol: String },
+#[derive(PartialEq, Debug, Clone)]
+pub struct DefinitionItem {
+    pub term: Vec<Element>,
+    pub definitions: Vec<Vec<Element>>,
 }
 
-impl Renderable for Element {
-    fn render(&self, templates: &HashMap<String, Template>, autofill_funcs: &Option<FuncMap>) -> Option<String> {
+impl Element {
+    /// Nested lists of elements, e.g. contents of a header or items of a list.
+    pub(crate) fn children(&self) -> Vec<&Vec<Element>> {
         use Element::*;
-        Some(match self {
-            Text(text) => text.to_string(),
-            Break => r#"<div class="py-1.5"></div>"#.to_string(),
-            Header { level, elements } => {
-                let content = elements
-                    .iter()
-                    .map(|el| el.render(templates, autofill_funcs).unwrap())
-                    .collect::<String>();
-                let rendered = templates
-                    .get("templates/elements/header.html")
-                    .expect("Header template not found")
-                    .fill_template(
-                        HashMap::from([
-                            ("level".to_string(), level.to_string()),
-                            ("content".to_string(), content.to_string()),
-                            ("text".to_string(), header_to_text(&content)),
-                        ]),
-                        autofill_funcs,
-                    );
-                rendered
-            }
-            Link { text, link } => templates
-                .get("templates/elements/link.html")
-                .expect("Link template not found")
-                .fill_template(
-                    HashMap::from([
-                        ("text".to_string(), text.to_string()),
-                        ("link".to_string(), link.to_string()),
-                    ]),
-                    autofill_funcs,
-                ),
-            List { list_type, items } => {
-                let mut rendered = String::new();
-                for item in items {
-                    let mut item_content = String::new();
-                    for el in item {
-                        item_content.push_str(&el.render(templates, autofill_funcs).unwrap().replace("\n", ""));
-                    }
-                    let item_template = templates.get("templates/elements/list_item.html").unwrap();
-                    let item_rendered = item_template
-                        .fill_template(HashMap::from([("item".to_string(), item_content)]), autofill_funcs);
-                    rendered.push_str(&item_rendered);
-                }
-                match list_type {
-                    ListType::Ordered { symbol } => {
-                        let list_template = templates.get("templates/elements/ordered_list.html").unwrap();
-                        let list_type = html_list_type_from(symbol);
-                        list_template.fill_template(
-                            HashMap::from([("items".to_string(), rendered), ("list_type".to_string(), list_type)]),
-                            autofill_funcs,
-                        )
-                    }
-                    ListType::Unordered { symbol } => {
-                        let list_template = templates.get("templates/elements/unordered_list.html").unwrap();
-                        let list_type = html_list_type_from(symbol);
-                        list_template.fill_template(
-                            HashMap::from([("items".to_string(), rendered), ("list_type".to_string(), list_type)]),
-                            autofill_funcs,
-                        )
-                    }
+        match self {
+            Header { level: _, elements }
+            | Paragraph(elements)
+            | BlockQuote(elements)
+            | Emphasis(elements)
+            | Strong(elements)
+            | Link { elements, .. }
+            | Span { elements, .. } => vec![elements],
+            List { list_type: _, items } => items.iter().collect(),
+            DefinitionList { items } => items
+                .iter()
+                .flat_map(|item| std::iter::once(&item.term).chain(item.definitions.iter()))
+                .collect(),
+            Text(_)
+            | HardBreak
+            | InlineCode(_)
+            | Image { .. }
+            | InlineHtml(_)
+            | Substitution { .. }
+            | WikiLink { .. }
+            | Abbreviation { .. }
+            | ThematicBreak
+            | Code { .. }
+            | HtmlBlock(_) => vec![],
+        }
+    }
+
+    /// Nested lists of elements like `children`, to change them.
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut Vec<Element>> {
+        use Element::*;
+        match self {
+            Header { level: _, elements }
+            | Paragraph(elements)
+            | BlockQuote(elements)
+            | Emphasis(elements)
+            | Strong(elements)
+            | Link { elements, .. }
+            | Span { elements, .. } => vec![elements],
+            List { list_type: _, items } => items.iter_mut().collect(),
+            DefinitionList { items } => items
+                .iter_mut()
+                .flat_map(|item| std::iter::once(&mut item.term).chain(item.definitions.iter_mut()))
+                .collect(),
+            Text(_)
+            | HardBreak
+            | InlineCode(_)
+            | Image { .. }
+            | InlineHtml(_)
+            | Substitution { .. }
+            | WikiLink { .. }
+            | Abbreviation { .. }
+            | ThematicBreak
+            | Code { .. }
+            | HtmlBlock(_) => vec![],
+        }
+    }
+}
+
+/// Calls `f` with the text of every text node, including text of links. Code is not visited.
+pub(crate) fn for_each_text_mut(elements: &mut [Element], f: &mut impl FnMut(&mut String)) {
+    for el in elements {
+        match el {
+            Element::Text(text) | Element::WikiLink { text, .. } => f(text),
+            _ => {
+                for children in el.children_mut() {
+                    for_each_text_mut(children, f);
                 }
             }
-            Code { lang, code } => {
-                let code_template = templates.get("templates/elements/code.html").unwrap();
-                code_template.fill_template(
-                    HashMap::from([
-                        ("lang".to_string(), lang.to_string()),
-                        ("code".to_string(), code.to_string()),
-                    ]),
-                    autofill_funcs,
-                )
-            }
-        })
+        }
     }
 }
 
-fn html_list_type_from(symbol: &str) -> String {
-    match symbol {
-        "-" => "list-disc".to_string(),
-        "+" => "list-[circle]".to_string(),
-        "1." => "list-decimal".to_string(),
-        "a)" => "list-[lower-roman]".to_string(),
-        _ => panic!("Invalid list type"),
-    }
+#[derive(PartialEq, Debug, Clone)]
+pub enum ListType {
+    /// `start` is the number of the first item, for `a)` lists the number of its letter.
+    Ordered {
+        symbol: String,
+        start: usize,
+    },
+    Unordered {
+        symbol: String,
+    },
 }
 
-pub fn load_markdown_pages(pages_path: &Path) -> Result<HashMap<String, MarkdownPage>, std::io::Error> {
+pub fn load_markdown_pages(
+    pages_path: &Path,
+    config: &Config,
+) -> Result<HashMap<String, MarkdownPage>, std::io::Error> {
     let markdown_pages_content = load_files_in_dir_to_string(pages_path, Some("md"))?;
     let mut pages = HashMap::new();
     for (path, content) in &markdown_pages_content {
-        let metadata = parse_metadata(content);
-        let content = parse_content(content);
-        let page = MarkdownPage { metadata, content };
+        let mut metadata = parse_metadata(content);
+        if let Some(directory) = &config.element_templates {
+            metadata
+                .info
+                .entry("element_templates".to_string())
+                .or_insert_with(|| directory.clone());
+        }
+        if config.permalinks {
+            metadata
+                .info
+                .entry("permalinks".to_string())
+                .or_insert_with(|| "true".to_string());
+        }
+        metadata.fill_language(config);
+        let mut content = parse_content(content);
+        replace_emoji_shortcodes(&mut content.elements);
+        // typography follows the conventions of page language, it can be turned off with
+        // `typography: false`
+        if metadata.info.get("typography").is_none_or(|enabled| enabled != "false") {
+            let lang = metadata.info.get("lang").map_or("en", |lang| lang.as_str());
+            apply_typography(&mut content.elements, &Typography::for_language(lang));
+        }
+        // substitutions come last, so that html they insert isn't changed by typography
+        apply_substitutions(&mut content.elements, &config.substitutions);
+        let mut page = MarkdownPage { metadata, content };
+        page.fill_reading_metadata(config);
         pages.insert(path.to_str().unwrap().to_string(), page);
     }
     Ok(pages)
 }
 
-fn parse_metadata(content: &str) -> Metadata {
+pub(crate) fn parse_metadata(content: &str) -> Metadata {
     let mut info = HashMap::new();
     for line in content.lines() {
         if line == "------" {
@@ -232,192 +475,111 @@ fn parse_metadata(content: &str) -> Metadata {
     Metadata { info }
 }
 
-fn parse_content(content: &str) -> Content {
+pub(crate) fn parse_content(content: &str) -> Document {
     let start_of_content = content.find("------\n").unwrap() + "------\n".len();
-    let content = &content[start_of_content..];
-    let content = content.replace(" -- ", " — ");
-    let mut elements = vec![];
-    let blocks = content.split("\n\n");
-    let mut _blocks = Vec::new();
-    let mut _block = String::new();
-    let mut in_code_block = false;
-    for block in blocks {
-        if in_code_block && block.ends_with("```") {
-            _block.push_str("\n\n");
-            _block.push_str(block);
-            _blocks.push(_block);
-            _block = String::new();
-            in_code_block = false;
-            continue;
-        } else if in_code_block {
-            _block.push_str("\n\n");
-            _block.push_str(block);
-            continue;
-        }
-        if block.starts_with("```") && block.ends_with("```") {
-        } else if block.starts_with("```") {
-            in_code_block = true;
-            _block.push_str(block);
-            continue;
-        }
-
-        _blocks.push(block.to_string());
-    }
-    for block in _blocks {
-        let block_elements = parse_block(&block);
-        elements.extend(block_elements);
-        elements.push(Element::Break);
-    }
-    elements.pop();
-    Content { elements }
-}
-
-static LINK_PATTERN: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"\[(.*?)\]\((.*?)\)").expect("Failed to compile link pattern"));
-
-fn parse_block(block: &str) -> Vec<Element> {
-    let mut elements = vec![];
-    use Element::*;
-
-    // NOTE: ORDER IS IMPORTANT, matching links first breaks matching list items that have links
-    if block.starts_with('#') {
-        let (level, text) = block.split_once(' ').unwrap();
-        let header = Header {
-            level: level.len(),
-            elements: parse_block(text),
-        };
-        elements.push(header);
-    } else if is_code(block) {
-        let block = block.trim();
-        let (_, lang) = block.lines().next().unwrap().split_once("```").unwrap();
-        let code = block
-            .lines()
-            .skip(1)
-            .collect::<Vec<&str>>()
-            .into_iter()
-            .rev()
-            .skip(1)
-            .rev()
-            .map(|s| {
-                let mut s = s.to_string();
-                s.push('\n');
-                s
-            })
-            .collect::<String>();
-        let lang = lang.to_string();
-        let code = code.trim().to_string();
-        let code = Code { lang, code };
-        elements.push(code);
-    } else if is_a_list(block) {
-        let mut items = vec![];
-        let mut nested_list_lines = vec![];
-        for line in block.trim().lines() {
-            // parse nested list
-            if is_indented(line) && is_a_list(line) {
-                let mut line = line.to_string();
-                line.push('\n');
-                nested_list_lines.push(line);
-                continue;
-            }
-            if !nested_list_lines.is_empty() {
-                let nested_list_block = nested_list_lines.iter().map(|s| s.trim_start()).collect::<String>();
-                items.push(parse_block(&nested_list_block));
-                nested_list_lines = vec![];
-            }
-            let (_list_symbol, item) = line.split_once(' ').unwrap();
-            items.push(parse_block(item.trim()));
-        }
-        let list_type = parse_list_type(block);
-        let list = List { list_type, items };
-        elements.push(list);
-    } else if LINK_PATTERN.is_match(block) {
-        let link_capture = LINK_PATTERN.captures(block).unwrap();
-        let link_match = link_capture.get(0).unwrap();
-        let (link_start_idx, link_end_idx) = (link_match.range().start, link_match.range().end);
-        if link_start_idx > 0 {
-            elements.push(Text(block[..link_start_idx].to_string()));
-        }
-        let link = Link {
-            text: link_capture.get(1).unwrap().as_str().to_string(),
-            link: link_capture.get(2).unwrap().as_str().to_string(),
-        };
-        elements.push(link);
-        if link_end_idx != block.len() {
-            let rest_of_block = &block[link_end_idx..];
-            elements.extend(parse_block(rest_of_block));
-        }
-    } else {
-        elements.push(Text(block.to_string()));
-    }
-    elements
+    Document::parse(&content[start_of_content..])
 }
 
-fn is_code(block: &str) -> bool {
-    let block = block.trim();
-    block.starts_with("```") && block.ends_with("```")
+pub fn write_html_pages(
+    html_pages: &HashMap<String, String>,
+    source_path: &Path,
+    output_path: &Path,
+) -> Result<(), std::io::Error> {
+    write_pages(html_pages, source_path, output_path, "html")
 }
 
-const LIST_TYPES: &[&str] = &["-", "+", "1.", "a)"];
-const UNORDERED_LIST_TYPES: &[&str] = &["-", "+"];
-const ORDERED_LIST_TYPES: &[&str] = &["1.", "a)"];
-
-fn is_a_list(block: &str) -> bool {
-    let lines = block.lines();
-    for line in lines {
-        let Some((line_start, _)) = line.trim().split_once(' ') else {
-            return false;
-        };
-        if !LIST_TYPES.contains(&line_start) {
-            return false;
+/// Writes rendered pages into `output_path`, keeping the directories they have under
+/// `source_path` and giving them the `extension` of the output format, e.g. `txt` or `gmi`.
+pub fn write_pages(
+    rendered_pages: &HashMap<String, String>,
+    source_path: &Path,
+    output_path: &Path,
+    extension: &str,
+) -> Result<(), std::io::Error> {
+    for (path, rendered) in rendered_pages {
+        let mut path = output_path.join(output_relative_path(path, source_path));
+        path.set_extension(extension);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        std::fs::write(path, rendered)?;
     }
-    true
+    Ok(())
 }
 
-fn is_indented(s: &str) -> bool {
-    s.starts_with("    ")
+/// Renders pages that aren't hidden with a `Renderer` other than the templates of the site,
+/// e.g. into plain text or gemtext. Links between pages are pointed at files with `extension`.
+pub fn render_pages(
+    md_pages: &HashMap<String, MarkdownPage>,
+    renderer: &impl Renderer,
+    extension: &str,
+) -> HashMap<String, String> {
+    md_pages
+        .iter()
+        .filter(|(_, page)| !page.is_hidden())
+        .map(|(path, page)| {
+            let mut document = page.content.clone();
+            relink_pages(&mut document.elements, extension);
+            (path.to_string(), renderer.render(&document))
+        })
+        .collect()
 }
 
-fn parse_list_type(s: &str) -> ListType {
-    let s = s.trim();
-    for u_type in UNORDERED_LIST_TYPES {
-        if s.starts_with(u_type) {
-            return ListType::Unordered {
-                symbol: u_type.to_string(),
+/// Relative links to `.html` pages, e.g. from resolved wiki links, are changed to `extension`.
+fn relink_pages(elements: &mut [Element], extension: &str) {
+    for el in elements {
+        if let Element::Link { link, .. } = el {
+            let (path, anchor) = match link.split_once('#') {
+                Some((path, anchor)) => (path, format!("#{anchor}")),
+                None => (link.as_str(), String::new()),
             };
+            if !path.contains(':') {
+                if let Some(stem) = path.strip_suffix(".html") {
+                    *link = format!("{stem}.{extension}{anchor}");
+                }
+            }
         }
-    }
-    for o_type in ORDERED_LIST_TYPES {
-        if s.starts_with(o_type) {
-            return ListType::Ordered {
-                symbol: o_type.to_string(),
-            };
+        for children in el.children_mut() {
+            relink_pages(children, extension);
         }
     }
-    eprintln!("Must always return a valid ListType, didn't for: {}", s);
-    panic!();
 }
 
-pub fn write_html_pages(
-    html_pages: &HashMap<String, String>,
-    source_path: &Path,
-    output_path: &Path,
-) -> Result<(), std::io::Error> {
+/// Path of the html file rendered from the page at `page_path`, relative to the output directory.
+pub(crate) fn output_relative_path(page_path: &str, source_path: &Path) -> PathBuf {
     let mut source_path = source_path.to_str().unwrap().to_string();
     source_path.push('/');
-    for (path, html) in html_pages {
-        let path = path.trim_start_matches(&source_path);
-        let mut path = output_path.join(path);
-        path.set_extension("html");
-        std::fs::write(path, html)?;
-    }
-    Ok(())
+    let mut path = PathBuf::from(page_path.trim_start_matches(&source_path));
+    path.set_extension("html");
+    path
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{is_a_list, parse_content, parse_list_type, parse_metadata, Content, Element, ListType, Metadata};
+    use super::{
+        header_anchors, parse_content, parse_metadata, render_pages, DefinitionItem, Document, Element, ListType,
+        MarkdownPage, Metadata,
+    };
+    use crate::config::Config;
+    use crate::plain_text::PlainTextRenderer;
     use std::collections::HashMap;
+    use webdotx::{render, Registry, Renderable, Template};
+
+    fn text(text: &str) -> Element {
+        Element::Text(text.to_string())
+    }
+
+    fn paragraph(text: &str) -> Element {
+        Element::Paragraph(vec![Element::Text(text.to_string())])
+    }
+
+    fn link(text: &str, link: &str) -> Element {
+        Element::Link {
+            elements: vec![Element::Text(text.to_string())],
+            link: link.to_string(),
+            title: "".to_string(),
+        }
+    }
 
     #[test]
     fn test_parse_metadata() {
@@ -447,13 +609,143 @@ key: value
         parse_metadata(content);
     }
 
+    #[test]
+    fn test_reading_metadata_defaults() {
+        let content = "template: t.html
+summary: Written by hand
+------
+Four words of text.";
+        let mut page = MarkdownPage {
+            metadata: parse_metadata(content),
+            content: parse_content(content),
+        };
+        page.fill_reading_metadata(&Config::default());
+        assert_eq!(Some(&"4".to_string()), page.get_metadata("word_count"));
+        assert_eq!(Some(&"1 min".to_string()), page.get_metadata("time_to_read"));
+        assert_eq!(Some(&"Four words of text.".to_string()), page.get_metadata("excerpt"));
+        assert_eq!(Some(&"Written by hand".to_string()), page.get_metadata("summary"));
+    }
+
+    #[test]
+    fn test_summary_is_escaped_once() {
+        let content = "template: t.html\n------\nFish & \"chips\" <3";
+        let mut page = MarkdownPage {
+            metadata: parse_metadata(content),
+            content: parse_content(content),
+        };
+        page.fill_reading_metadata(&Config::default());
+        let template = Template::new(r#"<title>{{ $summary$ }}</title><meta content="{{ $excerpt$ }}">"#);
+        let templates = HashMap::from([("t.html".to_string(), template)]);
+        let rendered = page.render(&templates, &Registry::new()).unwrap();
+        let expected = "<title>Fish &amp; \"chips\" &lt;3</title><meta content=\"Fish &amp; &quot;chips&quot; &lt;3\">";
+        assert_eq!(Some(expected.to_string()), rendered);
+    }
+
+    #[test]
+    fn test_render_pages() {
+        let page = |content: &str| MarkdownPage {
+            metadata: parse_metadata(content),
+            content: parse_content(content),
+        };
+        let pages = HashMap::from([
+            (
+                "pages/index.md".to_string(),
+                page(
+                    "template: t.html\n------\nSee [intro](intro.html#Start), [site](https://example.com/a.html) and [notes](notes.txt).",
+                ),
+            ),
+            (
+                "pages/draft.md".to_string(),
+                page("template: t.html\nhidden: true\n------\nDraft."),
+            ),
+        ]);
+        let got = render_pages(&pages, &PlainTextRenderer, "txt");
+        let expected = HashMap::from([(
+            "pages/index.md".to_string(),
+            "See intro (intro.txt#Start), site (https://example.com/a.html) and notes (notes.txt).\n".to_string(),
+        )]);
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_header_anchors() {
+        let content =
+            parse_content("------\n# Getting *started*\n\n## Getting started!\n\n> ## Zażółć gęślą\n\n## ???");
+        let anchors = header_anchors(&content.elements)
+            .into_iter()
+            .map(|(_, anchor)| anchor)
+            .collect::<Vec<_>>();
+        let expected = vec![
+            "getting-started",
+            "getting-started-1",
+            "za%C5%BC%C3%B3%C5%82%C4%87-g%C4%99%C5%9Bl%C4%85",
+            "section",
+        ];
+        assert_eq!(expected, anchors);
+    }
+
+    #[test]
+    fn test_header_permalinks() {
+        let templates = HashMap::from([("t.html".to_string(), Template::new("{{ $content$ }}"))]);
+        let page = |metadata: &str| {
+            let content = format!("{metadata}------\n## Getting *started*");
+            MarkdownPage {
+                metadata: parse_metadata(&content),
+                content: parse_content(&content),
+            }
+        };
+
+        let expected = "<h2 id=\"getting-started\">Getting <em>started</em>\n<a href=\"#getting-started\" class=\"permalink\" aria-label=\"Link to this section\">¶</a>\n</h2>\n";
+        let rendered = page("template: t.html\npermalinks: true\n")
+            .render(&templates, &Registry::new())
+            .unwrap();
+        assert_eq!(Some(expected.to_string()), rendered);
+
+        let expected = "<h2 id=\"getting-started\">Getting <em>started</em>\n</h2>\n";
+        let rendered = page("template: t.html\npermalinks: false\n")
+            .render(&templates, &Registry::new())
+            .unwrap();
+        assert_eq!(Some(expected.to_string()), rendered);
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let page = |metadata: &str| {
+            let content = format!("{metadata}------\nSome text.");
+            MarkdownPage {
+                metadata: parse_metadata(&content),
+                content: parse_content(&content),
+            }
+        };
+        let templates = HashMap::from([
+            ("t.html".to_string(), Template::new("{{ $content$ }}")),
+            (
+                "templates/elements/paragraph.html".to_string(),
+                Template::new("<p>{{ $contnet$ }}</p>"),
+            ),
+        ]);
+        let pages = HashMap::from([("pages/index.md".to_string(), page("template: t.html\n"))]);
+        let error = render(&pages, &templates, &Registry::new()).unwrap_err();
+        assert_eq!(
+            "pages/index.md: 1:4: missing value for placeholder contnet, did you mean content?",
+            error.to_string()
+        );
+
+        let pages = HashMap::from([("pages/index.md".to_string(), page("template: t.htm\n"))]);
+        let error = render(&pages, &templates, &Registry::new()).unwrap_err();
+        assert_eq!(
+            "pages/index.md: unknown template t.htm, did you mean t.html?",
+            error.to_string()
+        );
+    }
+
     #[test]
     fn test_parse_content_text() {
         let content = "------
 Some random text.";
         let got = parse_content(content);
-        let expected = Content {
-            elements: vec![Element::Text("Some random text.".to_string())],
+        let expected = Document {
+            elements: vec![paragraph("Some random text.")],
         };
         assert_eq!(expected, got);
     }
@@ -465,12 +757,8 @@ Some random text.
 
 Some other random text.";
         let got = parse_content(content);
-        let expected = Content {
-            elements: vec![
-                Element::Text("Some random text.".to_string()),
-                Element::Break,
-                Element::Text("Some other random text.".to_string()),
-            ],
+        let expected = Document {
+            elements: vec![paragraph("Some random text."), paragraph("Some other random text.")],
         };
         assert_eq!(expected, got);
     }
@@ -486,28 +774,13 @@ Some other random text.";
 
 #### Header";
         let got = parse_content(content);
-        let expected = Content {
-            elements: vec![
-                Element::Header {
-                    level: 1,
-                    elements: vec![Element::Text("Header".to_string())],
-                },
-                Element::Break,
-                Element::Header {
-                    level: 2,
-                    elements: vec![Element::Text("Header".to_string())],
-                },
-                Element::Break,
-                Element::Header {
-                    level: 3,
-                    elements: vec![Element::Text("Header".to_string())],
-                },
-                Element::Break,
-                Element::Header {
-                    level: 4,
-                    elements: vec![Element::Text("Header".to_string())],
-                },
-            ],
+        let expected = Document {
+            elements: (1..=4)
+                .map(|level| Element::Header {
+                    level,
+                    elements: vec![text("Header")],
+                })
+                .collect(),
         };
         assert_eq!(expected, got);
     }
@@ -517,15 +790,12 @@ Some other random text.";
         let content = "------
 Some text with a link: [link text](coolpage.com). Cool.";
         let got = parse_content(content);
-        let expected = Content {
-            elements: vec![
-                Element::Text("Some text with a link: ".to_string()),
-                Element::Link {
-                    text: "link text".to_string(),
-                    link: "coolpage.com".to_string(),
-                },
-                Element::Text(". Cool.".to_string()),
-            ],
+        let expected = Document {
+            elements: vec![Element::Paragraph(vec![
+                text("Some text with a link: "),
+                link("link text", "coolpage.com"),
+                text(". Cool."),
+            ])],
         };
         assert_eq!(expected, got);
     }
@@ -535,16 +805,13 @@ Some text with a link: [link text](coolpage.com). Cool.";
         let content = "------
 # Some text with a link: [link text](coolpage.com). Cool.";
         let got = parse_content(content);
-        let expected = Content {
+        let expected = Document {
             elements: vec![Element::Header {
                 level: 1,
                 elements: vec![
-                    Element::Text("Some text with a link: ".to_string()),
-                    Element::Link {
-                        text: "link text".to_string(),
-                        link: "coolpage.com".to_string(),
-                    },
-                    Element::Text(". Cool.".to_string()),
+                    text("Some text with a link: "),
+                    link("link text", "coolpage.com"),
+                    text(". Cool."),
                 ],
             }],
         };
@@ -552,59 +819,59 @@ Some text with a link: [link text](coolpage.com). Cool.";
     }
 
     #[test]
-    fn test_is_a_list() {
-        let block = "Some text.";
-        let block_is_a_list = is_a_list(block);
-        assert!(!block_is_a_list);
-
-        let block = "- Item 1
-- Item 2";
-        let block_is_a_list = is_a_list(block);
-        assert!(block_is_a_list);
+    fn test_parse_content_inlines() {
+        let content = r#"------
+*Emphasis*, __strong__ and `code`, <span>html</span>\
+and ![alt *text*](image.png "Title") [ref] &amp; &copy;
 
-        let block = "+ Item 1
-+ Item 2";
-        let block_is_a_list = is_a_list(block);
-        assert!(block_is_a_list);
-
-        let block = "1. Item 1
-1. Item 2";
-        let block_is_a_list = is_a_list(block);
-        assert!(block_is_a_list);
-
-        let block = "a) Item 1
-a) Item 2";
-        let block_is_a_list = is_a_list(block);
-        assert!(block_is_a_list);
-    }
-
-    #[test]
-    fn test_parse_list_type() {
-        let line = "- Item 1";
-        let got = parse_list_type(line);
-        let expected = ListType::Unordered {
-            symbol: "-".to_string(),
-        };
-        assert_eq!(expected, got);
-
-        let line = "+ Item 1";
-        let got = parse_list_type(line);
-        let expected = ListType::Unordered {
-            symbol: "+".to_string(),
-        };
-        assert_eq!(expected, got);
-
-        let line = "1. Item 1";
-        let got = parse_list_type(line);
-        let expected = ListType::Ordered {
-            symbol: "1.".to_string(),
+[ref]: <https://example.com> 'Reference'"#;
+        let got = parse_content(content);
+        let expected = Document {
+            elements: vec![Element::Paragraph(vec![
+                Element::Emphasis(vec![text("Emphasis")]),
+                text(", "),
+                Element::Strong(vec![text("strong")]),
+                text(" and "),
+                Element::InlineCode("code".to_string()),
+                text(", "),
+                Element::InlineHtml("<span>".to_string()),
+                text("html"),
+                Element::InlineHtml("</span>".to_string()),
+                Element::HardBreak,
+                text("and "),
+                Element::Image {
+                    alt: "alt text".to_string(),
+                    link: "image.png".to_string(),
+                    title: "Title".to_string(),
+                },
+                text(" "),
+                Element::Link {
+                    elements: vec![text("ref")],
+                    link: "https://example.com".to_string(),
+                    title: "Reference".to_string(),
+                },
+                text(" & ©"),
+            ])],
         };
         assert_eq!(expected, got);
+    }
 
-        let line = "a) Item 1";
-        let got = parse_list_type(line);
-        let expected = ListType::Ordered {
-            symbol: "a)".to_string(),
+    #[test]
+    fn test_parse_content_blocks() {
+        let content = "------
+> quoted
+continued lazily
+***
+<div>
+*raw*
+</div>";
+        let got = parse_content(content);
+        let expected = Document {
+            elements: vec![
+                Element::BlockQuote(vec![paragraph("quoted\ncontinued lazily")]),
+                Element::ThematicBreak,
+                Element::HtmlBlock("<div>\n*raw*\n</div>".to_string()),
+            ],
         };
         assert_eq!(expected, got);
     }
@@ -624,78 +891,43 @@ a) Item 2";
 a) item 1
 a) item 2
 
-a) item with a link [text](link.com), hurray!
-a) item 2
+3) item with a link [text](link.com), hurray!
+4) item 2
 
-- [text](link.com)";
+* [text](link.com)
+
+  loose";
         let got = parse_content(content);
-        let expected = Content {
+        let list = |list_type: ListType| Element::List {
+            list_type,
+            items: vec![vec![text("item 1")], vec![text("item 2")]],
+        };
+        let unordered = |symbol: &str| ListType::Unordered {
+            symbol: symbol.to_string(),
+        };
+        let ordered = |symbol: &str, start: usize| ListType::Ordered {
+            symbol: symbol.to_string(),
+            start,
+        };
+        let expected = Document {
             elements: vec![
+                list(unordered("-")),
+                list(unordered("+")),
+                list(ordered("1.", 1)),
+                list(ordered("a)", 1)),
                 Element::List {
-                    list_type: ListType::Unordered {
-                        symbol: "-".to_string(),
-                    },
+                    list_type: ordered("1)", 3),
                     items: vec![
-                        vec![Element::Text("item 1".to_string())],
-                        vec![Element::Text("item 2".to_string())],
+                        vec![text("item with a link "), link("text", "link.com"), text(", hurray!")],
+                        vec![text("item 2")],
                     ],
                 },
-                Element::Break,
                 Element::List {
-                    list_type: ListType::Unordered {
-                        symbol: "+".to_string(),
-                    },
-                    items: vec![
-                        vec![Element::Text("item 1".to_string())],
-                        vec![Element::Text("item 2".to_string())],
-                    ],
-                },
-                Element::Break,
-                Element::List {
-                    list_type: ListType::Ordered {
-                        symbol: "1.".to_string(),
-                    },
-                    items: vec![
-                        vec![Element::Text("item 1".to_string())],
-                        vec![Element::Text("item 2".to_string())],
-                    ],
-                },
-                Element::Break,
-                Element::List {
-                    list_type: ListType::Ordered {
-                        symbol: "a)".to_string(),
-                    },
-                    items: vec![
-                        vec![Element::Text("item 1".to_string())],
-                        vec![Element::Text("item 2".to_string())],
-                    ],
-                },
-                Element::Break,
-                Element::List {
-                    list_type: ListType::Ordered {
-                        symbol: "a)".to_string(),
-                    },
-                    items: vec![
-                        vec![
-                            Element::Text("item with a link ".to_string()),
-                            Element::Link {
-                                text: "text".to_string(),
-                                link: "link.com".to_string(),
-                            },
-                            Element::Text(", hurray!".to_string()),
-                        ],
-                        vec![Element::Text("item 2".to_string())],
-                    ],
-                },
-                Element::Break,
-                Element::List {
-                    list_type: ListType::Unordered {
-                        symbol: "-".to_string(),
-                    },
-                    items: vec![vec![Element::Link {
-                        text: "text".to_string(),
-                        link: "link.com".to_string(),
-                    }]],
+                    list_type: unordered("*"),
+                    items: vec![vec![
+                        Element::Paragraph(vec![link("text", "link.com")]),
+                        paragraph("loose"),
+                    ]],
                 },
             ],
         };
@@ -710,36 +942,28 @@ a) item 2
     a) item with a link [text](link.com), hurray!
     a) item 2
 - [text](link.com)";
-        // TODO: work on infinitely nested lists
         let got = parse_content(content);
-        let expected = Content {
+        let expected = Document {
             elements: vec![Element::List {
                 list_type: ListType::Unordered {
                     symbol: "-".to_string(),
                 },
                 items: vec![
-                    vec![Element::Text("item 1".to_string())],
-                    vec![Element::Text("item 2:".to_string())],
-                    vec![Element::List {
-                        list_type: ListType::Ordered {
-                            symbol: "a)".to_string(),
-                        },
-                        items: vec![
-                            vec![
-                                Element::Text("item with a link ".to_string()),
-                                Element::Link {
-                                    text: "text".to_string(),
-                                    link: "link.com".to_string(),
-                                },
-                                Element::Text(", hurray!".to_string()),
+                    vec![text("item 1")],
+                    vec![
+                        text("item 2:"),
+                        Element::List {
+                            list_type: ListType::Ordered {
+                                symbol: "a)".to_string(),
+                                start: 1,
+                            },
+                            items: vec![
+                                vec![text("item with a link "), link("text", "link.com"), text(", hurray!")],
+                                vec![text("item 2")],
                             ],
-                            vec![Element::Text("item 2".to_string())],
-                        ],
-                    }],
-                    vec![Element::Link {
-                        text: "text".to_string(),
-                        link: "link.com".to_string(),
-                    }],
+                        },
+                    ],
+                    vec![link("text", "link.com")],
                 ],
             }],
         };
@@ -755,15 +979,214 @@ fn hello_world() -> ! {
 }
 ```";
         let got = parse_content(content);
-        let expected = Content {
+        let expected = Document {
             elements: vec![Element::Code {
                 lang: "rust".to_string(),
                 code: "fn hello_world() -> ! {
     while true {}
-}"
+}
+"
                 .to_string(),
             }],
         };
         assert_eq!(expected, got);
     }
+
+    #[test]
+    fn test_parse_content_code_fences() {
+        let content = "------
+~~~
+tildes
+```
+~~~
+
+  ````markdown
+  ```
+  still code
+
+  ```
+  ````
+
+    indented code
+
+    with a blank line
+text
+```";
+        let got = parse_content(content);
+        let code = |lang: &str, code: &str| Element::Code {
+            lang: lang.to_string(),
+            code: code.to_string(),
+        };
+        let expected = Document {
+            elements: vec![
+                code("", "tildes\n```\n"),
+                code("markdown", "```\nstill code\n\n```\n"),
+                code("", "indented code\n\nwith a blank line\n"),
+                paragraph("text"),
+                code("", ""),
+            ],
+        };
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_parse_content_definition_list() {
+        let content = "------
+Markdown
+: lightweight markup language, see [spec](commonmark.org)
+: also a verb
+    with a long definition
+
+Rust
+: programming language";
+        let got = parse_content(content);
+        let expected = Document {
+            elements: vec![Element::DefinitionList {
+                items: vec![
+                    DefinitionItem {
+                        term: vec![text("Markdown")],
+                        definitions: vec![
+                            vec![
+                                text("lightweight markup language, see "),
+                                link("spec", "commonmark.org"),
+                            ],
+                            vec![text("also a verb\nwith a long definition")],
+                        ],
+                    },
+                    DefinitionItem {
+                        term: vec![text("Rust")],
+                        definitions: vec![vec![text("programming language")]],
+                    },
+                ],
+            }],
+        };
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_parse_content_spans() {
+        let content = "------
+Read [po *polsku*]{lang=pl} or [بالعربية]{ lang=\"ar\" }, [not a span]{class=x}.";
+        let got = parse_content(content);
+        let expected = Document {
+            elements: vec![Element::Paragraph(vec![
+                text("Read "),
+                Element::Span {
+                    elements: vec![text("po "), Element::Emphasis(vec![text("polsku")])],
+                    lang: "pl".to_string(),
+                },
+                text(" or "),
+                Element::Span {
+                    elements: vec![text("بالعربية")],
+                    lang: "ar".to_string(),
+                },
+                text(", [not a span]{class=x}."),
+            ])],
+        };
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_language_metadata() {
+        let mut metadata = parse_metadata("template: t.html\n------\n");
+        metadata.fill_language(&Config::default());
+        assert_eq!(Some(&"en".to_string()), metadata.info.get("lang"));
+        assert_eq!(Some(&"ltr".to_string()), metadata.info.get("dir"));
+
+        let mut metadata = parse_metadata("template: t.html\nlang: ar\n------\n");
+        metadata.fill_language(&Config {
+            lang: "pl".to_string(),
+            ..Config::default()
+        });
+        assert_eq!(Some(&"ar".to_string()), metadata.info.get("lang"));
+        assert_eq!(Some(&"rtl".to_string()), metadata.info.get("dir"));
+    }
+
+    #[test]
+    fn test_parse_content_abbreviations() {
+        let content = "------
+# HTML and CSS
+
+HTML5 is not HTML, but XHTML is neither.
+*[HTML]: Hyper Text Markup Language
+*[HTML5]: Fifth version of HTML
+
+```
+*[CSS]: code stays as is
+```";
+        let got = parse_content(content);
+        let html = || Element::Abbreviation {
+            text: "HTML".to_string(),
+            title: "Hyper Text Markup Language".to_string(),
+        };
+        let expected = Document {
+            elements: vec![
+                Element::Header {
+                    level: 1,
+                    elements: vec![html(), text(" and CSS")],
+                },
+                Element::Paragraph(vec![
+                    Element::Abbreviation {
+                        text: "HTML5".to_string(),
+                        title: "Fifth version of HTML".to_string(),
+                    },
+                    text(" is not "),
+                    html(),
+                    text(", but XHTML is neither."),
+                ]),
+                Element::Code {
+                    lang: "".to_string(),
+                    code: "*[CSS]: code stays as is\n".to_string(),
+                },
+            ],
+        };
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_parse_content_heading_edge_cases() {
+        let content = "------
+#hashtag is not a header
+####### neither are seven of them
+
+## Closed ##
+# Not closed#
+   ### Indented
+#
+
+Some text
+# Header in the middle
+more text
+
+Setext
+header
+======
+
+Second level
+---";
+        let got = parse_content(content);
+        let header = |level: usize, content: &str| Element::Header {
+            level,
+            elements: if content.is_empty() {
+                vec![]
+            } else {
+                vec![text(content)]
+            },
+        };
+        let expected = Document {
+            elements: vec![
+                paragraph("#hashtag is not a header\n####### neither are seven of them"),
+                header(2, "Closed"),
+                header(1, "Not closed#"),
+                header(3, "Indented"),
+                header(1, ""),
+                paragraph("Some text"),
+                header(1, "Header in the middle"),
+                paragraph("more text"),
+                header(1, "Setext\nheader"),
+                header(2, "Second level"),
+            ],
+        };
+        assert_eq!(expected, got);
+    }
 }
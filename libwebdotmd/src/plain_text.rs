@@ -0,0 +1,212 @@
+use crate::markdown::{Document, Element, ListType};
+use crate::renderer::Renderer;
+
+/// Renders documents into plain text. Blocks are separated by blank lines, headers are
+/// underlined, links are followed by their url and raw html is left out.
+pub struct PlainTextRenderer;
+
+impl Renderer for PlainTextRenderer {
+    fn render_element(&self, element: &Element) -> String {
+        use Element::*;
+        match element {
+            Text(text)
+            | InlineCode(text)
+            | WikiLink { text, .. }
+            | Abbreviation { text, title: _ }
+            | Substitution { text, html: _ } => text.to_string(),
+            HardBreak => "\n".to_string(),
+            Emphasis(elements) | Strong(elements) | Span { elements, .. } => self.inlines(elements),
+            Link { elements, link, .. } => {
+                let text = self.inlines(elements);
+                if text == *link || link.strip_prefix("mailto:") == Some(&text) {
+                    text
+                } else {
+                    format!("{text} ({link})")
+                }
+            }
+            Image { alt, link, .. } => format!("{alt} ({link})"),
+            InlineHtml(_) | HtmlBlock(_) => String::new(),
+            Paragraph(elements) => format!("{}\n", self.inlines(elements)),
+            Header { level, elements } => {
+                let text = self.inlines(elements);
+                let underline = match level {
+                    1 => "=",
+                    2 => "-",
+                    _ => return format!("{text}\n"),
+                };
+                let width = text.lines().map(|line| line.chars().count()).max().unwrap_or_default();
+                format!("{text}\n{}\n", underline.repeat(width))
+            }
+            ThematicBreak => "* * *\n".to_string(),
+            Code { lang: _, code } => indent(code, "    ", "    "),
+            BlockQuote(elements) => indent(&self.blocks(elements), "> ", "> "),
+            List { list_type, items } => {
+                let mut rendered = String::new();
+                for (i, item) in items.iter().enumerate() {
+                    let marker = match list_type {
+                        ListType::Unordered { .. } => "-".to_string(),
+                        ListType::Ordered { symbol, start } if symbol == "a)" => {
+                            format!(
+                                "{})",
+                                char::from_u32('a' as u32 + (start + i - 1) as u32).unwrap_or('?')
+                            )
+                        }
+                        ListType::Ordered { symbol, start } => format!("{}{}", start + i, &symbol[1..]),
+                    };
+                    let padding = " ".repeat(marker.len() + 1);
+                    rendered.push_str(&indent(&self.item(item), &format!("{marker} "), &padding));
+                }
+                rendered
+            }
+            DefinitionList { items } => {
+                let mut rendered = vec![];
+                for item in items {
+                    let mut entry = format!("{}\n", self.inlines(&item.term));
+                    for definition in &item.definitions {
+                        entry.push_str(&indent(&format!("{}\n", self.inlines(definition)), "    ", "    "));
+                    }
+                    rendered.push(entry);
+                }
+                rendered.join("\n")
+            }
+        }
+    }
+
+    fn render(&self, document: &Document) -> String {
+        self.blocks(document.elements())
+    }
+}
+
+impl PlainTextRenderer {
+    fn inlines(&self, elements: &[Element]) -> String {
+        elements.iter().map(|el| self.render_element(el)).collect()
+    }
+
+    fn blocks(&self, elements: &[Element]) -> String {
+        elements
+            .iter()
+            .map(|el| self.render_element(el))
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Items of tight lists hold inline elements, items of loose ones hold blocks.
+    fn item(&self, elements: &[Element]) -> String {
+        let mut rendered = String::new();
+        let mut line = String::new();
+        for el in elements {
+            if is_block(el) {
+                if !line.is_empty() {
+                    rendered.push_str(&line);
+                    rendered.push('\n');
+                    line.clear();
+                }
+                rendered.push_str(&self.render_element(el));
+            } else {
+                line.push_str(&self.render_element(el));
+            }
+        }
+        if !line.is_empty() {
+            rendered.push_str(&line);
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
+pub(crate) fn is_block(element: &Element) -> bool {
+    use Element::*;
+    matches!(
+        element,
+        Paragraph(_)
+            | Header { .. }
+            | ThematicBreak
+            | BlockQuote(_)
+            | List { .. }
+            | DefinitionList { .. }
+            | Code { .. }
+            | HtmlBlock(_)
+    )
+}
+
+/// Prefixes the first line of text with `first` and the other ones with `rest`, blank lines
+/// are left without trailing spaces.
+pub(crate) fn indent(text: &str, first: &str, rest: &str) -> String {
+    let mut indented = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let prefix = if i == 0 { first } else { rest };
+        if line.is_empty() {
+            indented.push_str(prefix.trim_end());
+        } else {
+            indented.push_str(prefix);
+            indented.push_str(line);
+        }
+        indented.push('\n');
+    }
+    indented
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PlainTextRenderer;
+    use crate::markdown::{header_anchors, Document};
+    use crate::renderer::Renderer;
+    use crate::substitutions::apply_substitutions;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_render_plain_text() {
+        let document = Document::parse(
+            "# Title
+
+Some *text* with a [link](https://example.com) and <https://example.com>.
+
+> quoted
+> text
+
+1. first
+2. second
+   - nested
+
+```
+code
+```",
+        );
+        let expected = "Title
+=====
+
+Some text with a link (https://example.com) and https://example.com.
+
+> quoted
+> text
+
+1. first
+2. second
+   - nested
+
+    code
+";
+        assert_eq!(expected, PlainTextRenderer.render(&document));
+    }
+
+    #[test]
+    fn test_render_substitutions() {
+        let mut document = Document::parse("# Intro (tm)\n\nCopyright (c) 2024 :ferris:");
+        let substitutions = HashMap::from([
+            ("(c)".to_string(), "©".to_string()),
+            ("(tm)".to_string(), "™".to_string()),
+            (":ferris:".to_string(), r#"<img src="ferris.svg">"#.to_string()),
+        ]);
+        apply_substitutions(&mut document.elements, &substitutions);
+        assert_eq!(
+            "Intro ™\n=======\n\nCopyright © 2024 :ferris:\n",
+            PlainTextRenderer.render(&document)
+        );
+        let anchors = header_anchors(&document.elements);
+        assert_eq!(
+            vec!["intro".to_string()],
+            anchors.into_iter().map(|(_, anchor)| anchor).collect::<Vec<_>>()
+        );
+    }
+}
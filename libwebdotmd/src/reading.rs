@@ -0,0 +1,170 @@
+use crate::config::Config;
+use crate::markdown::{plain_text, Element};
+
+/// Html comment ending the excerpt of a page when it is placed on a line of its own.
+const MORE_MARKER: &str = "<!-- more -->";
+
+/// Words of a page, code is counted separately because it is read slower than text.
+#[derive(Debug, PartialEq, Default)]
+pub(crate) struct WordCount {
+    pub(crate) text: usize,
+    pub(crate) code: usize,
+}
+
+impl WordCount {
+    pub(crate) fn total(&self) -> usize {
+        self.text + self.code
+    }
+
+    /// Minutes needed to read the words, at least one.
+    pub(crate) fn reading_time(&self, config: &Config) -> usize {
+        let minutes = self.text as f64 / config.words_per_minute.max(1) as f64
+            + self.code as f64 / config.code_words_per_minute.max(1) as f64;
+        (minutes.ceil() as usize).max(1)
+    }
+}
+
+pub(crate) fn count_words(elements: &[Element]) -> WordCount {
+    let mut count = WordCount::default();
+    for el in elements {
+        match el {
+            Element::Code { lang: _, code } | Element::InlineCode(code) => {
+                count.code += code.split_whitespace().count()
+            }
+            // raw html is markup, not something to read
+            Element::InlineHtml(_) | Element::HtmlBlock(_) => {}
+            Element::Text(text)
+            | Element::WikiLink { text, .. }
+            | Element::Abbreviation { text, title: _ }
+            | Element::Substitution { text, html: _ }
+            | Element::Image { alt: text, .. } => count.text += words_of(text),
+            Element::HardBreak | Element::ThematicBreak => {}
+            Element::Paragraph(elements)
+            | Element::Header { level: _, elements }
+            | Element::BlockQuote(elements)
+            | Element::Emphasis(elements)
+            | Element::Strong(elements)
+            | Element::Link { elements, .. }
+            | Element::Span { elements, .. } => add(&mut count, count_words(elements)),
+            Element::List { list_type: _, items } => {
+                for item in items {
+                    add(&mut count, count_words(item));
+                }
+            }
+            Element::DefinitionList { items } => {
+                for item in items {
+                    add(&mut count, count_words(&item.term));
+                    for definition in &item.definitions {
+                        add(&mut count, count_words(definition));
+                    }
+                }
+            }
+        }
+    }
+    count
+}
+
+/// Punctuation after a link or emphasis is a text node of its own, it isn't counted as a word.
+fn words_of(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+fn add(count: &mut WordCount, other: WordCount) {
+    count.text += other.text;
+    count.code += other.code;
+}
+
+/// Text of the page up to `<!-- more -->`, or of its first paragraph when there is no marker.
+/// Headers, code and raw html are left out. The text isn't escaped, templates escape it where it
+/// is placed.
+pub(crate) fn excerpt(elements: &[Element]) -> String {
+    let marker = elements
+        .iter()
+        .position(|el| matches!(el, Element::HtmlBlock(html) if html.trim() == MORE_MARKER));
+    let text = match marker {
+        Some(marker) => elements[..marker]
+            .iter()
+            .filter(|el| {
+                !matches!(
+                    el,
+                    Element::Header { .. } | Element::Code { .. } | Element::HtmlBlock(_) | Element::ThematicBreak
+                )
+            })
+            .map(|el| plain_text(std::slice::from_ref(el)))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => elements
+            .iter()
+            .find_map(|el| match el {
+                Element::Paragraph(elements) => Some(plain_text(elements)),
+                _ => None,
+            })
+            .unwrap_or_default(),
+    };
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_words, excerpt, WordCount};
+    use crate::config::Config;
+    use crate::markdown::parse_content;
+
+    #[test]
+    fn test_count_words() {
+        let content = "------
+# Three word header
+
+Some *emphasized* text with `inline code` and a [link text](link.com).
+
+<div>raw html</div>
+
+```rust
+let x = 1;
+```";
+        let got = count_words(&parse_content(content).elements);
+        assert_eq!(WordCount { text: 11, code: 6 }, got);
+    }
+
+    #[test]
+    fn test_reading_time() {
+        let config = Config {
+            words_per_minute: 200,
+            code_words_per_minute: 50,
+            ..Default::default()
+        };
+        assert_eq!(1, WordCount { text: 0, code: 0 }.reading_time(&config));
+        assert_eq!(1, WordCount { text: 200, code: 0 }.reading_time(&config));
+        assert_eq!(2, WordCount { text: 201, code: 0 }.reading_time(&config));
+        assert_eq!(3, WordCount { text: 200, code: 100 }.reading_time(&config));
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let content = "------
+# Header
+
+First <b>paragraph</b>
+with \"two\" lines.
+
+Second paragraph.";
+        assert_eq!(
+            "First paragraph with \"two\" lines.",
+            excerpt(&parse_content(content).elements)
+        );
+
+        let content = "------
+# Header
+
+First paragraph.
+
+- and a list
+
+<!-- more -->
+
+Rest of the page.";
+        assert_eq!("First paragraph. and a list", excerpt(&parse_content(content).elements));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::element_templates::ElementTemplates;
+use crate::html::escape_html;
+use crate::markdown::{Anchors, Document, Element, ListType};
+use crate::typography::text_direction;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use webdotx::{Registry, RenderError, Template, Value};
+
+/// Turns a parsed document into text of some format, e.g. html.
+pub trait Renderer {
+    /// Renders an element together with its children.
+    fn render_element(&self, element: &Element) -> String;
+
+    /// Renders an element like `render_element`, but returns the error of a renderer that can
+    /// fail, e.g. one filling user templates, instead of panicking.
+    fn try_render_element(&self, element: &Element) -> Result<String, RenderError> {
+        Ok(self.render_element(element))
+    }
+
+    fn render(&self, document: &Document) -> String {
+        document.elements().iter().map(|el| self.render_element(el)).collect()
+    }
+}
+
+/// Renders elements with html templates, see `ElementTemplates` for where they are looked up.
+pub struct TemplateRenderer<'a> {
+    element_templates: ElementTemplates<'a>,
+    registry: &'a Registry,
+    permalinks: bool,
+    /// Anchors of the headers rendered so far, so that headers with the same text get different
+    /// ones.
+    anchors: RefCell<Anchors>,
+}
+
+impl<'a> TemplateRenderer<'a> {
+    /// `directory` with element templates is searched before `templates/elements/`.
+    pub fn new(
+        templates: &'a HashMap<String, Template>,
+        directory: Option<&'a str>,
+        registry: &'a Registry,
+    ) -> TemplateRenderer<'a> {
+        TemplateRenderer {
+            element_templates: ElementTemplates::new(templates, directory),
+            registry,
+            permalinks: false,
+            anchors: RefCell::default(),
+        }
+    }
+
+    /// Fills the `permalink` placeholder of headers with a link to the header itself.
+    pub fn with_permalinks(mut self, permalinks: bool) -> TemplateRenderer<'a> {
+        self.permalinks = permalinks;
+        self
+    }
+}
+
+impl Renderer for TemplateRenderer<'_> {
+    /// Panics if an element template can't be filled, `try_render_element` returns the error.
+    fn render_element(&self, element: &Element) -> String {
+        self.try_render_element(element)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Fails if an element template can't be filled, e.g. because it has a placeholder that
+    /// elements don't provide or uses an unknown filter.
+    fn try_render_element(&self, element: &Element) -> Result<String, RenderError> {
+        use Element::*;
+        let render_all = |elements: &Vec<Element>| {
+            elements
+                .iter()
+                .map(|el| self.try_render_element(el))
+                .collect::<Result<String, _>>()
+        };
+        // values are already escaped or rendered HTML
+        let fill = |name: &str, placeholders: &[(&str, String)]| {
+            self.element_templates.get(name).fill_template(
+                placeholders
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), Value::Safe(value.to_string())))
+                    .collect::<HashMap<_, _>>(),
+                self.registry,
+            )
+        };
+        let rendered = match element {
+            // newlines are removed from the rendered page, soft line breaks have to stay spaces
+            Text(text) => escape_html(text).replace('\n', " "),
+            HardBreak => "<br>".to_string(),
+            InlineCode(code) => fill("inline_code", &[("code", escape_html(code))])?,
+            Emphasis(elements) => fill("emphasis", &[("content", render_all(elements)?)])?,
+            Strong(elements) => fill("strong", &[("content", render_all(elements)?)])?,
+            Link {
+                elements,
+                link,
+                title: _,
+            } => fill("link", &[("text", render_all(elements)?), ("link", escape_html(link))])?,
+            Image { alt, link, title } => fill(
+                "image",
+                &[
+                    ("alt", escape_html(alt)),
+                    ("link", escape_html(link)),
+                    ("title", escape_html(title)),
+                ],
+            )?,
+            InlineHtml(html) | HtmlBlock(html) | Substitution { text: _, html } => html.to_string(),
+            // wiki links that couldn't be resolved are reported and rendered as plain text
+            WikiLink { text, .. } => escape_html(text),
+            Abbreviation { text, title } => fill(
+                "abbreviation",
+                &[("text", escape_html(text)), ("title", escape_html(title))],
+            )?,
+            Span { elements, lang } => fill(
+                "span",
+                &[
+                    ("content", render_all(elements)?),
+                    ("lang", escape_html(lang)),
+                    ("dir", text_direction(lang).to_string()),
+                ],
+            )?,
+            Paragraph(elements) => fill("paragraph", &[("content", render_all(elements)?)])?,
+            Header { level, elements } => {
+                // the outline links to the same anchor
+                let anchor = self.anchors.borrow_mut().next(elements);
+                let permalink = if self.permalinks {
+                    fill("permalink", &[("anchor", anchor.clone())])?
+                } else {
+                    String::new()
+                };
+                fill(
+                    "header",
+                    &[
+                        ("level", level.to_string()),
+                        ("content", render_all(elements)?),
+                        ("anchor", anchor),
+                        ("permalink", permalink),
+                    ],
+                )?
+            }
+            ThematicBreak => fill("thematic_break", &[])?,
+            BlockQuote(elements) => fill("blockquote", &[("content", render_all(elements)?)])?,
+            List { list_type, items } => {
+                let mut rendered = String::new();
+                for item in items {
+                    let mut item_content = String::new();
+                    for el in item {
+                        item_content.push_str(&self.try_render_element(el)?.replace("\n", ""));
+                    }
+                    rendered.push_str(&fill("list_item", &[("item", item_content)])?);
+                }
+                match list_type {
+                    ListType::Ordered { symbol, start } => fill(
+                        "ordered_list",
+                        &[
+                            ("items", rendered),
+                            ("list_type", html_list_type_from(symbol)),
+                            ("start", start.to_string()),
+                        ],
+                    )?,
+                    ListType::Unordered { symbol } => fill(
+                        "unordered_list",
+                        &[("items", rendered), ("list_type", html_list_type_from(symbol))],
+                    )?,
+                }
+            }
+            DefinitionList { items } => {
+                let mut rendered = String::new();
+                for item in items {
+                    rendered.push_str(&fill("definition_term", &[("term", render_all(&item.term)?)])?);
+                    for definition in &item.definitions {
+                        rendered.push_str(&fill("definition", &[("definition", render_all(definition)?)])?);
+                    }
+                }
+                fill("definition_list", &[("items", rendered)])?
+            }
+            Code { lang, code } => fill(
+                "code",
+                &[
+                    ("lang", escape_html(lang)),
+                    ("code", escape_html(code.strip_suffix('\n').unwrap_or(code))),
+                ],
+            )?,
+        };
+        Ok(rendered)
+    }
+}
+
+fn html_list_type_from(symbol: &str) -> String {
+    match symbol {
+        "-" => "list-disc".to_string(),
+        "+" => "list-[circle]".to_string(),
+        "*" => "list-[square]".to_string(),
+        "1." | "1)" => "list-decimal".to_string(),
+        "a)" => "list-[lower-roman]".to_string(),
+        _ => panic!("Invalid list type"),
+    }
+}
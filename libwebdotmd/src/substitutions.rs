@@ -0,0 +1,143 @@
+use crate::emoji::emoji_for;
+use crate::markdown::{for_each_text_mut, Element};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+
+static SHORTCODE_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r":([a-z0-9_+\-]+):").expect("Failed to compile emoji shortcode pattern"));
+
+/// Replaces `:shortcode:` in text with the emoji from the bundled table, unknown shortcodes are
+/// left as they are.
+pub(crate) fn replace_emoji_shortcodes(elements: &mut [Element]) {
+    for_each_text_mut(elements, &mut |text| {
+        *text = SHORTCODE_PATTERN
+            .replace_all(text, |capture: &Captures| {
+                emoji_for(&capture[1]).map_or(capture[0].to_string(), str::to_string)
+            })
+            .into_owned();
+    });
+}
+
+/// Replaces every occurrence of a key of `substitutions` in text with its value, longer keys are
+/// replaced first. Values are inserted as raw html, formats other than html get the value when it
+/// is plain text and the replaced key otherwise.
+pub(crate) fn apply_substitutions(elements: &mut Vec<Element>, substitutions: &HashMap<String, String>) {
+    if substitutions.is_empty() {
+        return;
+    }
+    let mut keys = substitutions.keys().collect::<Vec<_>>();
+    keys.sort_by_key(|key| std::cmp::Reverse(key.len()));
+    let pattern = keys.iter().map(|key| regex::escape(key)).collect::<Vec<_>>().join("|");
+    let pattern = Regex::new(&pattern).expect("Escaped substitutions to form a valid pattern");
+    substitute_in(elements, &pattern, substitutions);
+}
+
+fn substitute_in(elements: &mut Vec<Element>, pattern: &Regex, substitutions: &HashMap<String, String>) {
+    let mut substituted = Vec::with_capacity(elements.len());
+    for mut el in elements.drain(..) {
+        let Element::Text(text) = &el else {
+            for children in el.children_mut() {
+                substitute_in(children, pattern, substitutions);
+            }
+            substituted.push(el);
+            continue;
+        };
+        let mut end_of_last_match = 0;
+        for found in pattern.find_iter(text) {
+            if found.start() > end_of_last_match {
+                substituted.push(Element::Text(text[end_of_last_match..found.start()].to_string()));
+            }
+            let html = &substitutions[found.as_str()];
+            let text = if html.contains(['<', '&']) {
+                found.as_str()
+            } else {
+                html
+            };
+            substituted.push(Element::Substitution {
+                text: text.to_string(),
+                html: html.to_string(),
+            });
+            end_of_last_match = found.end();
+        }
+        if end_of_last_match < text.len() {
+            substituted.push(Element::Text(text[end_of_last_match..].to_string()));
+        }
+    }
+    *elements = substituted;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_substitutions, replace_emoji_shortcodes};
+    use crate::markdown::{parse_content, Element};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_replace_emoji_shortcodes() {
+        let content = "------
+Launch :rocket: at 12:30:00, :not_an_emoji: stays, `:rocket:` too.
+
+```
+:rocket:
+```";
+        let mut got = parse_content(content);
+        replace_emoji_shortcodes(&mut got.elements);
+        let expected = vec![
+            Element::Paragraph(vec![
+                Element::Text("Launch 🚀 at 12:30:00, :not_an_emoji: stays, ".to_string()),
+                Element::InlineCode(":rocket:".to_string()),
+                Element::Text(" too.".to_string()),
+            ]),
+            Element::Code {
+                lang: "".to_string(),
+                code: ":rocket:\n".to_string(),
+            },
+        ];
+        assert_eq!(expected, got.elements);
+    }
+
+    #[test]
+    fn test_apply_substitutions() {
+        let content = "------
+- (c) 2024 :ferris: [(c) link](:ferris:)
+- `(c)`";
+        let mut got = parse_content(content);
+        let substitutions = HashMap::from([
+            ("(c)".to_string(), "©".to_string()),
+            (":ferris:".to_string(), r#"<img src="ferris.svg">"#.to_string()),
+            (":ferris".to_string(), "partial match".to_string()),
+        ]);
+        apply_substitutions(&mut got.elements, &substitutions);
+        let Element::List { list_type: _, items } = &got.elements[0] else {
+            panic!("Expected a list, got: {:?}", got.elements);
+        };
+        let expected = vec![
+            vec![
+                Element::Substitution {
+                    text: "©".to_string(),
+                    html: "©".to_string(),
+                },
+                Element::Text(" 2024 ".to_string()),
+                Element::Substitution {
+                    text: ":ferris:".to_string(),
+                    html: r#"<img src="ferris.svg">"#.to_string(),
+                },
+                Element::Text(" ".to_string()),
+                Element::Link {
+                    elements: vec![
+                        Element::Substitution {
+                            text: "©".to_string(),
+                            html: "©".to_string(),
+                        },
+                        Element::Text(" link".to_string()),
+                    ],
+                    link: ":ferris:".to_string(),
+                    title: "".to_string(),
+                },
+            ],
+            vec![Element::InlineCode("(c)".to_string())],
+        ];
+        assert_eq!(&expected, items);
+    }
+}
@@ -0,0 +1,342 @@
+use crate::markdown::Element;
+
+/// Typographic conventions of a language, used to replace straight quotes, dashes, ellipses and
+/// spaces in text with their proper typographic counterparts.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Typography {
+    double_quotes: (char, char),
+    single_quotes: (char, char),
+    apostrophe: char,
+    /// Dash used for ` -- ` surrounded by spaces.
+    spaced_dash: char,
+    /// French puts a non-breaking space inside guillemets and before `;`, `:`, `!` and `?`.
+    spaced_punctuation: bool,
+}
+
+const NBSP: char = '\u{a0}';
+
+/// Units that get a non-breaking space between them and the number before them.
+const UNITS: &[&str] = &[
+    "%", "‰", "°C", "°F", "K", "mm", "cm", "m", "km", "in", "ft", "mi", "mg", "g", "kg", "t", "ml", "l", "ms", "s",
+    "min", "h", "Hz", "kHz", "MHz", "GHz", "B", "kB", "KB", "MB", "GB", "TB", "KiB", "MiB", "GiB", "TiB", "px", "pt",
+    "em", "rem", "W", "kW", "V", "A", "mAh", "zł", "€", "$", "£",
+];
+
+const URL_PREFIXES: &[&str] = &["http://", "https://", "ftp://", "mailto:", "www."];
+
+impl Typography {
+    /// Conventions for a language tag from page metadata, e.g. `en`, `pl` or `de-AT`. Unknown
+    /// languages use the english ones.
+    pub(crate) fn for_language(lang: &str) -> Typography {
+        let lang = lang.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+        match lang.as_str() {
+            "pl" => Typography {
+                double_quotes: ('„', '”'),
+                single_quotes: ('‚', '’'),
+                apostrophe: '’',
+                spaced_dash: '–',
+                spaced_punctuation: false,
+            },
+            "de" => Typography {
+                double_quotes: ('„', '“'),
+                single_quotes: ('‚', '‘'),
+                apostrophe: '’',
+                spaced_dash: '–',
+                spaced_punctuation: false,
+            },
+            "fr" => Typography {
+                double_quotes: ('«', '»'),
+                single_quotes: ('‹', '›'),
+                apostrophe: '’',
+                spaced_dash: '–',
+                spaced_punctuation: true,
+            },
+            _ => Typography {
+                double_quotes: ('“', '”'),
+                single_quotes: ('‘', '’'),
+                apostrophe: '’',
+                spaced_dash: '—',
+                spaced_punctuation: false,
+            },
+        }
+    }
+}
+
+/// Scripts written from right to left, as ISO 15924 codes.
+const RTL_SCRIPTS: &[&str] = &["arab", "hebr", "syrc", "thaa", "nkoo", "adlm", "rohg"];
+/// Languages written from right to left unless their tag names another script.
+const RTL_LANGUAGES: &[&str] = &[
+    "ar", "arc", "ckb", "dv", "fa", "he", "iw", "ks", "ps", "sd", "syr", "ug", "ur", "yi",
+];
+
+/// `rtl` or `ltr` for a language tag, e.g. `ar`, `pl` or `az-Arab`.
+pub(crate) fn text_direction(lang: &str) -> &'static str {
+    let mut subtags = lang.split(['-', '_']).map(str::to_lowercase);
+    let language = subtags.next().unwrap_or_default();
+    let rtl = match subtags.find(|subtag| subtag.len() == 4 && subtag.chars().all(|c| c.is_ascii_alphabetic())) {
+        Some(script) => RTL_SCRIPTS.contains(&script.as_str()),
+        None => RTL_LANGUAGES.contains(&language.as_str()),
+    };
+    if rtl {
+        "rtl"
+    } else {
+        "ltr"
+    }
+}
+
+/// Applies the typographic replacements to every text node of the elements. Code, raw html and
+/// link targets are left as they are, and so are urls found inside of text.
+pub(crate) fn apply_typography(elements: &mut [Element], typography: &Typography) {
+    let mut replacer = Replacer {
+        typography,
+        prev: None,
+        single_quote_open: false,
+    };
+    replacer.replace_in(elements);
+}
+
+struct Replacer<'a> {
+    typography: &'a Typography,
+    /// Last character written, carried over between text nodes of the same block so that a quote
+    /// right after a link or emphasis is still recognized as a closing one.
+    prev: Option<char>,
+    /// Whether a single quote was opened in the current block and not closed yet, so that a
+    /// quote after a letter closes it instead of being an apostrophe.
+    single_quote_open: bool,
+}
+
+impl Replacer<'_> {
+    fn replace_in(&mut self, elements: &mut [Element]) {
+        use Element::*;
+        for el in elements {
+            match el {
+                Text(text) | WikiLink { text, .. } => *text = self.replace(text),
+                InlineCode(text) | Abbreviation { text, title: _ } | Substitution { text, html: _ } => {
+                    self.prev = text.chars().last()
+                }
+                Image { .. } | InlineHtml(_) => {}
+                HardBreak | ThematicBreak | Code { .. } | HtmlBlock(_) => self.prev = None,
+                Emphasis(elements) | Strong(elements) | Link { elements, .. } | Span { elements, .. } => {
+                    self.replace_in(elements)
+                }
+                Paragraph(_) | Header { .. } | BlockQuote(_) | List { .. } | DefinitionList { .. } => {
+                    for children in el.children_mut() {
+                        self.prev = None;
+                        self.single_quote_open = false;
+                        self.replace_in(children);
+                    }
+                    self.prev = None;
+                    self.single_quote_open = false;
+                }
+            }
+        }
+    }
+
+    fn replace(&mut self, text: &str) -> String {
+        let typography = self.typography;
+        let chars = text.chars().collect::<Vec<char>>();
+        let mut replaced = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            if is_opening_context(self.prev) && starts_with_url(&chars[i..]) {
+                let url_len = chars[i..].iter().take_while(|c| !c.is_whitespace()).count();
+                replaced.extend(&chars[i..i + url_len]);
+                i += url_len;
+                self.prev = replaced.chars().last();
+                continue;
+            }
+
+            let mut skip = 1;
+            match c {
+                '.' if next == Some('.') && chars.get(i + 2) == Some(&'.') => {
+                    replaced.push('…');
+                    skip = 3;
+                }
+                '-' if next == Some('-') && chars.get(i + 2) == Some(&'-') => {
+                    replaced.push('—');
+                    skip = 3;
+                }
+                // `--` is a dash only between digits, as in ranges, or surrounded by spaces, so that
+                // command line flags like `--verbose` are kept
+                '-' if next == Some('-')
+                    && self.prev.is_some_and(|c| c.is_ascii_digit())
+                    && chars.get(i + 2).is_some_and(char::is_ascii_digit) =>
+                {
+                    replaced.push('–');
+                    skip = 2;
+                }
+                '-' if next == Some('-')
+                    && self.prev.is_some_and(char::is_whitespace)
+                    && chars.get(i + 2).is_none_or(|c| c.is_whitespace()) =>
+                {
+                    replaced.push(typography.spaced_dash);
+                    skip = 2;
+                }
+                '"' if is_opening_context(self.prev) => {
+                    replaced.push(typography.double_quotes.0);
+                    if typography.spaced_punctuation {
+                        replaced.push(NBSP);
+                    }
+                }
+                '"' => {
+                    if typography.spaced_punctuation {
+                        push_nbsp_before(&mut replaced);
+                    }
+                    replaced.push(typography.double_quotes.1);
+                }
+                '\'' if self.prev.is_some_and(char::is_alphanumeric) && next.is_some_and(char::is_alphanumeric) => {
+                    replaced.push(typography.apostrophe)
+                }
+                '\'' if is_opening_context(self.prev) => {
+                    replaced.push(typography.single_quotes.0);
+                    self.single_quote_open = true;
+                }
+                '\'' if self.single_quote_open => {
+                    replaced.push(typography.single_quotes.1);
+                    self.single_quote_open = false;
+                }
+                '\'' if self.prev.is_some_and(char::is_alphanumeric) => replaced.push(typography.apostrophe),
+                '\'' => replaced.push(typography.single_quotes.1),
+                ';' | ':' | '!' | '?' if typography.spaced_punctuation && self.prev == Some(' ') => {
+                    push_nbsp_before(&mut replaced);
+                    replaced.push(c);
+                }
+                ' ' if self.prev.is_some_and(|c| c.is_ascii_digit()) && starts_with_unit(&chars[i + 1..]) => {
+                    replaced.push(NBSP);
+                }
+                _ => replaced.push(c),
+            }
+            i += skip;
+            self.prev = replaced.chars().last();
+        }
+        replaced
+    }
+}
+
+/// Quote after whitespace, an opening bracket or another quote opens a quotation.
+fn is_opening_context(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{<-–—/„‚“‘«‹".contains(c),
+    }
+}
+
+fn push_nbsp_before(replaced: &mut String) {
+    if replaced.ends_with(' ') {
+        replaced.pop();
+    }
+    if !replaced.ends_with(NBSP) {
+        replaced.push(NBSP);
+    }
+}
+
+fn starts_with_url(chars: &[char]) -> bool {
+    URL_PREFIXES
+        .iter()
+        .any(|prefix| prefix.len() <= chars.len() && prefix.chars().zip(chars).all(|(a, b)| a.eq_ignore_ascii_case(b)))
+}
+
+fn starts_with_unit(chars: &[char]) -> bool {
+    let word = chars
+        .iter()
+        .take_while(|c| !c.is_whitespace() && !",.;:!?)]".contains(**c))
+        .collect::<String>();
+    UNITS.contains(&word.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_typography, text_direction, Typography};
+    use crate::markdown::{parse_content, Element};
+
+    fn typeset(text: &str, lang: &str) -> String {
+        let mut elements = vec![Element::Text(text.to_string())];
+        apply_typography(&mut elements, &Typography::for_language(lang));
+        let [Element::Text(text)] = elements.as_slice() else {
+            unreachable!()
+        };
+        text.to_string()
+    }
+
+    #[test]
+    fn test_quotes_and_apostrophes() {
+        assert_eq!(
+            "“Don’t,” she said, ‘twice’.",
+            typeset(r#""Don't," she said, 'twice'."#, "en")
+        );
+        assert_eq!(
+            "Powiedział „nie” (‚raz’).",
+            typeset(r#"Powiedział "nie" ('raz')."#, "pl-PL")
+        );
+        assert_eq!(
+            "«\u{a0}Bonjour\u{a0}» ça va\u{a0}?",
+            typeset(r#""Bonjour" ça va ?"#, "fr")
+        );
+        assert_eq!("Er sagte ‚Wort‘ und ging.", typeset("Er sagte 'Wort' und ging.", "de"));
+        assert_eq!("Il a dit ‹mot› et l’a vu.", typeset("Il a dit 'mot' et l'a vu.", "fr"));
+        assert_eq!(
+            "‘Don’t go’, the dogs’ bowls",
+            typeset("'Don't go', the dogs' bowls", "en")
+        );
+    }
+
+    #[test]
+    fn test_dashes_ellipses_and_units() {
+        assert_eq!("Wait — what… 1990–2000", typeset("Wait -- what... 1990--2000", "en"));
+        assert_eq!("Czekaj – co—nie", typeset("Czekaj -- co---nie", "pl"));
+        assert_eq!(
+            "use --verbose or — to end options, a--b",
+            typeset("use --verbose or -- to end options, a--b", "en")
+        );
+        assert_eq!(
+            "5\u{a0}km and 10\u{a0}MB, 3 apples",
+            typeset("5 km and 10 MB, 3 apples", "en")
+        );
+    }
+
+    #[test]
+    fn test_urls_and_code_are_untouched() {
+        assert_eq!(
+            "See https://example.com/a--b?x=\"1\" — ok",
+            typeset("See https://example.com/a--b?x=\"1\" -- ok", "en")
+        );
+
+        let content = "------
+Say \"[hello](link.com/'x')\" -- now, `let s = \"...\"`
+
+```rust
+let s = \"a -- b\";
+```";
+        let mut got = parse_content(content);
+        apply_typography(&mut got.elements, &Typography::for_language("en"));
+        let expected = vec![
+            Element::Paragraph(vec![
+                Element::Text("Say “".to_string()),
+                Element::Link {
+                    elements: vec![Element::Text("hello".to_string())],
+                    link: "link.com/'x'".to_string(),
+                    title: "".to_string(),
+                },
+                Element::Text("” — now, ".to_string()),
+                Element::InlineCode("let s = \"...\"".to_string()),
+            ]),
+            Element::Code {
+                lang: "rust".to_string(),
+                code: "let s = \"a -- b\";\n".to_string(),
+            },
+        ];
+        assert_eq!(expected, got.elements);
+    }
+
+    #[test]
+    fn test_text_direction() {
+        assert_eq!("ltr", text_direction("en"));
+        assert_eq!("ltr", text_direction("pl-PL"));
+        assert_eq!("rtl", text_direction("ar"));
+        assert_eq!("rtl", text_direction("he_IL"));
+        assert_eq!("rtl", text_direction("az-Arab"));
+        assert_eq!("ltr", text_direction("ku-Latn"));
+    }
+}
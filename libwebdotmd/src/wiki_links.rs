@@ -0,0 +1,359 @@
+use crate::markdown::{header_anchors, output_relative_path, slug, Element, MarkdownPage};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Problem found while resolving a `[[target#heading|text]]` link on a page.
+#[derive(Debug, PartialEq)]
+pub struct WikiLinkError {
+    /// Path of the page containing the link, as returned by `load_markdown_pages`.
+    pub page: String,
+    pub target: String,
+    pub kind: WikiLinkErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WikiLinkErrorKind {
+    /// No page with that name exists.
+    UnknownPage,
+    /// More than one page has that file name, the link has to use the full name instead.
+    AmbiguousPage(Vec<String>),
+    /// The page exists, but it has no header with that text.
+    MissingHeading(String),
+}
+
+impl fmt::Display for WikiLinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { page, target, kind } = self;
+        match kind {
+            WikiLinkErrorKind::UnknownPage => write!(f, "{page}: link to unknown page [[{target}]]"),
+            WikiLinkErrorKind::AmbiguousPage(candidates) => write!(
+                f,
+                "{page}: link [[{target}]] matches more than one page: {}",
+                candidates.join(", ")
+            ),
+            WikiLinkErrorKind::MissingHeading(heading) => {
+                write!(f, "{page}: page linked by [[{target}#{heading}]] has no such heading")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WikiLinkError {}
+
+struct LinkablePage {
+    output_path: PathBuf,
+    anchors: Vec<String>,
+}
+
+/// Rewrites every wiki link on the pages into a normal link pointing at the html file that
+/// `write_html_pages` writes for the target page, relative to the page containing the link.
+///
+/// A page can be linked by its path relative to `source_path` without the extension
+/// (`[[articles/intro]]`), or by its file name alone (`[[intro]]`) when that name is unique.
+/// `[[#heading]]` links to a header on the same page. Links that can't be resolved are left as
+/// they were and are returned as errors, links to a missing heading still point at the page.
+pub fn resolve_wiki_links(pages: &mut HashMap<String, MarkdownPage>, source_path: &Path) -> Vec<WikiLinkError> {
+    let mut linkable_pages = HashMap::new();
+    let mut full_names = HashMap::new();
+    let mut file_names: HashMap<String, Vec<String>> = HashMap::new();
+    for (path, page) in pages.iter() {
+        let output_path = output_relative_path(path, source_path);
+        let name = output_path.with_extension("");
+        let name = name
+            .components()
+            .map(|c| c.as_os_str().to_str().unwrap())
+            .collect::<Vec<_>>();
+        full_names.insert(name.join("/"), path.to_string());
+        let file_name = name.last().unwrap().to_string();
+        file_names.entry(file_name).or_default().push(path.to_string());
+        let anchors = header_anchors(&page.content.elements)
+            .into_iter()
+            .map(|(_, anchor)| anchor)
+            .collect();
+        linkable_pages.insert(path.to_string(), LinkablePage { output_path, anchors });
+    }
+
+    let mut page_paths = pages.keys().cloned().collect::<Vec<_>>();
+    page_paths.sort();
+    let mut errors = vec![];
+    for path in page_paths {
+        let page = pages.get_mut(&path).unwrap();
+        let resolver = Resolver {
+            page: &path,
+            pages: &linkable_pages,
+            full_names: &full_names,
+            file_names: &file_names,
+        };
+        resolver.resolve(&mut page.content.elements, &mut errors);
+    }
+    errors
+}
+
+struct Resolver<'a> {
+    page: &'a str,
+    pages: &'a HashMap<String, LinkablePage>,
+    full_names: &'a HashMap<String, String>,
+    file_names: &'a HashMap<String, Vec<String>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&self, elements: &mut [Element], errors: &mut Vec<WikiLinkError>) {
+        for el in elements {
+            match el {
+                Element::WikiLink { target, heading, text } => match self.link_for(target, heading) {
+                    Ok(link) => {
+                        *el = Element::Link {
+                            elements: vec![Element::Text(std::mem::take(text))],
+                            link,
+                            title: String::new(),
+                        }
+                    }
+                    Err(kind) => {
+                        if let WikiLinkErrorKind::MissingHeading(_) = kind {
+                            let page_link = self.link_for(target, &None).unwrap();
+                            errors.push(self.error(target, kind));
+                            *el = Element::Link {
+                                elements: vec![Element::Text(std::mem::take(text))],
+                                link: page_link,
+                                title: String::new(),
+                            };
+                        } else {
+                            errors.push(self.error(target, kind));
+                        }
+                    }
+                },
+                _ => {
+                    for children in el.children_mut() {
+                        self.resolve(children, errors);
+                    }
+                }
+            }
+        }
+    }
+
+    fn link_for(&self, target: &str, heading: &Option<String>) -> Result<String, WikiLinkErrorKind> {
+        let target_path = self.find_page(target)?;
+        let target_page = &self.pages[target_path];
+        let mut link = if target_path == self.page {
+            String::new()
+        } else {
+            relative_url(&self.pages[self.page].output_path, &target_page.output_path)
+        };
+        if let Some(heading) = heading {
+            let anchor = slug(heading);
+            if !target_page.anchors.contains(&anchor) {
+                return Err(WikiLinkErrorKind::MissingHeading(heading.to_string()));
+            }
+            link.push('#');
+            link.push_str(&anchor);
+        }
+        Ok(link)
+    }
+
+    fn find_page(&self, target: &str) -> Result<&'a str, WikiLinkErrorKind> {
+        if target.is_empty() {
+            return Ok(self.pages.get_key_value(self.page).unwrap().0);
+        }
+        let name = target.trim_end_matches(".md").trim_end_matches(".html");
+        if let Some(path) = self.full_names.get(name) {
+            return Ok(path);
+        }
+        match self.file_names.get(name).map(|paths| paths.as_slice()) {
+            Some([path]) => Ok(path),
+            Some(paths) if !paths.is_empty() => {
+                let mut candidates = paths.to_vec();
+                candidates.sort();
+                Err(WikiLinkErrorKind::AmbiguousPage(candidates))
+            }
+            _ => Err(WikiLinkErrorKind::UnknownPage),
+        }
+    }
+
+    fn error(&self, target: &str, kind: WikiLinkErrorKind) -> WikiLinkError {
+        WikiLinkError {
+            page: self.page.to_string(),
+            target: target.to_string(),
+            kind,
+        }
+    }
+}
+
+/// Url of `to` as seen from the page at `from`, both paths relative to the output directory.
+fn relative_url(from: &Path, to: &Path) -> String {
+    let dirs = |path: &Path| -> Vec<String> {
+        path.parent()
+            .unwrap_or(Path::new(""))
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(dir) => Some(dir.to_str().unwrap().to_string()),
+                _ => None,
+            })
+            .collect()
+    };
+    let from_dirs = dirs(from);
+    let to_dirs = dirs(to);
+    let common = from_dirs.iter().zip(&to_dirs).take_while(|(a, b)| a == b).count();
+    let mut url = "../".repeat(from_dirs.len() - common);
+    for dir in &to_dirs[common..] {
+        url.push_str(dir);
+        url.push('/');
+    }
+    url.push_str(to.file_name().unwrap().to_str().unwrap());
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relative_url, resolve_wiki_links, WikiLinkError, WikiLinkErrorKind};
+    use crate::markdown::{parse_content, parse_metadata, Element, MarkdownPage};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn page(content: &str) -> MarkdownPage {
+        MarkdownPage {
+            metadata: parse_metadata(content),
+            content: parse_content(content),
+        }
+    }
+
+    #[test]
+    fn test_relative_url() {
+        assert_eq!("b.html", relative_url(Path::new("a.html"), Path::new("b.html")));
+        assert_eq!(
+            "../b.html",
+            relative_url(Path::new("articles/a.html"), Path::new("b.html"))
+        );
+        assert_eq!(
+            "articles/b.html",
+            relative_url(Path::new("a.html"), Path::new("articles/b.html"))
+        );
+        assert_eq!(
+            "../notes/b.html",
+            relative_url(Path::new("articles/a.html"), Path::new("notes/b.html"))
+        );
+    }
+
+    #[test]
+    fn test_parse_wiki_links() {
+        let content = "template: t.html
+------
+See [[intro]], [[articles/intro#Getting started|the start]] and [text](link.com).";
+        let got = parse_content(content);
+        let expected = vec![Element::Paragraph(vec![
+            Element::Text("See ".to_string()),
+            Element::WikiLink {
+                target: "intro".to_string(),
+                heading: None,
+                text: "intro".to_string(),
+            },
+            Element::Text(", ".to_string()),
+            Element::WikiLink {
+                target: "articles/intro".to_string(),
+                heading: Some("Getting started".to_string()),
+                text: "the start".to_string(),
+            },
+            Element::Text(" and ".to_string()),
+            Element::Link {
+                elements: vec![Element::Text("text".to_string())],
+                link: "link.com".to_string(),
+                title: "".to_string(),
+            },
+            Element::Text(".".to_string()),
+        ])];
+        assert_eq!(expected, got.elements);
+    }
+
+    #[test]
+    fn test_resolve_wiki_links() {
+        let mut pages = HashMap::from([
+            (
+                "pages/index.md".to_string(),
+                page("template: t.html\n------\n[[intro#Getting started|start here]] or [[#Top]]\n\n# Top"),
+            ),
+            (
+                "pages/articles/intro.md".to_string(),
+                page("template: t.html\n------\n# Getting started\n\nBack to [[index]]."),
+            ),
+        ]);
+        let errors = resolve_wiki_links(&mut pages, Path::new("pages"));
+        assert_eq!(Vec::<WikiLinkError>::new(), errors);
+
+        let Element::Paragraph(index) = &pages["pages/index.md"].content.elements[0] else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            Element::Link {
+                elements: vec![Element::Text("start here".to_string())],
+                link: "articles/intro.html#getting-started".to_string(),
+                title: "".to_string(),
+            },
+            index[0]
+        );
+        assert_eq!(
+            Element::Link {
+                elements: vec![Element::Text("Top".to_string())],
+                link: "#top".to_string(),
+                title: "".to_string(),
+            },
+            index[2]
+        );
+        let Element::Paragraph(intro) = &pages["pages/articles/intro.md"].content.elements[1] else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            Element::Link {
+                elements: vec![Element::Text("index".to_string())],
+                link: "../index.html".to_string(),
+                title: "".to_string(),
+            },
+            intro[1]
+        );
+    }
+
+    #[test]
+    fn test_resolve_wiki_links_errors() {
+        let mut pages = HashMap::from([
+            (
+                "pages/index.md".to_string(),
+                page("template: t.html\n------\n[[missing]] [[notes#Nope]] [[todo]]"),
+            ),
+            ("pages/notes.md".to_string(), page("template: t.html\n------\n# Notes")),
+            ("pages/a/todo.md".to_string(), page("template: t.html\n------\nA")),
+            ("pages/b/todo.md".to_string(), page("template: t.html\n------\nB")),
+        ]);
+        let errors = resolve_wiki_links(&mut pages, Path::new("pages"));
+        let expected = vec![
+            WikiLinkError {
+                page: "pages/index.md".to_string(),
+                target: "missing".to_string(),
+                kind: WikiLinkErrorKind::UnknownPage,
+            },
+            WikiLinkError {
+                page: "pages/index.md".to_string(),
+                target: "notes".to_string(),
+                kind: WikiLinkErrorKind::MissingHeading("Nope".to_string()),
+            },
+            WikiLinkError {
+                page: "pages/index.md".to_string(),
+                target: "todo".to_string(),
+                kind: WikiLinkErrorKind::AmbiguousPage(vec![
+                    "pages/a/todo.md".to_string(),
+                    "pages/b/todo.md".to_string(),
+                ]),
+            },
+        ];
+        assert_eq!(expected, errors);
+        let Element::Paragraph(index) = &pages["pages/index.md"].content.elements[0] else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            Element::Link {
+                elements: vec![Element::Text("notes#Nope".to_string())],
+                link: "notes.html".to_string(),
+                title: "".to_string(),
+            },
+            index[2]
+        );
+    }
+}
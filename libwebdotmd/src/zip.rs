@@ -0,0 +1,148 @@
+//! Minimal zip writer for EPUB files. Files are stored without compression, which readers must
+//! accept anyway for the `mimetype` file, so nothing but a CRC-32 is needed.
+
+/// Date of every file in the archive, 1980-01-01 in DOS format, so builds are reproducible.
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// Bit 11 of the flags marks file names as UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+const VERSION: u16 = 20;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Builds a zip archive in memory, files are stored in the order they are added.
+#[derive(Default)]
+pub(crate) struct ZipWriter {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+impl ZipWriter {
+    pub(crate) fn add(&mut self, name: &str, contents: &[u8]) {
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32(contents),
+            size: contents.len() as u32,
+            offset: self.data.len() as u32,
+        };
+        self.u32(0x0403_4b50);
+        self.u16(VERSION);
+        self.file_header(&entry);
+        self.u16(0);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(contents);
+        self.entries.push(entry);
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let directory_offset = self.data.len() as u32;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.u32(0x0201_4b50);
+            self.u16(VERSION);
+            self.u16(VERSION);
+            self.file_header(entry);
+            // extra field, comment, disk, internal and external attributes
+            for _ in 0..4 {
+                self.u16(0);
+            }
+            self.u32(0);
+            self.u32(entry.offset);
+            self.data.extend_from_slice(entry.name.as_bytes());
+        }
+        let directory_size = self.data.len() as u32 - directory_offset;
+        self.u32(0x0605_4b50);
+        self.u16(0);
+        self.u16(0);
+        self.u16(entries.len() as u16);
+        self.u16(entries.len() as u16);
+        self.u32(directory_size);
+        self.u32(directory_offset);
+        self.u16(0);
+        self.data
+    }
+
+    /// Fields shared by local and central headers, from the flags up to the file name length.
+    fn file_header(&mut self, entry: &Entry) {
+        self.u16(UTF8_NAMES);
+        // stored
+        self.u16(0);
+        self.u16(0);
+        self.u16(DOS_DATE);
+        self.u32(entry.crc);
+        self.u32(entry.size);
+        self.u32(entry.size);
+        self.u16(entry.name.len() as u16);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, ZipWriter};
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_zip_writer() {
+        let mut zip = ZipWriter::default();
+        zip.add("mimetype", b"application/epub+zip");
+        zip.add("a/b.txt", b"text");
+        let data = zip.finish();
+
+        // the first file starts right after its 30 byte header, as EPUB readers expect
+        assert_eq!(&[0x50, 0x4b, 0x03, 0x04], &data[..4]);
+        assert_eq!(b"mimetype", &data[30..38]);
+        assert_eq!(b"application/epub+zip", &data[38..58]);
+
+        let end = &data[data.len() - 22..];
+        assert_eq!(&[0x50, 0x4b, 0x05, 0x06], &end[..4]);
+        assert_eq!(2, u16::from_le_bytes([end[10], end[11]]));
+        let directory_offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(&[0x50, 0x4b, 0x01, 0x02], &data[directory_offset..directory_offset + 4]);
+        assert_eq!(b"mimetype", &data[directory_offset + 46..directory_offset + 54]);
+    }
+}
@@ -1,43 +1,238 @@
 use std::{collections::HashMap, path::Path};
 
-use libwebdotmd::{load_markdown_pages, write_html_pages};
-use webdotx::{load_template, load_templates, render, FuncMap};
+use libwebdotmd::{
+    load_markdown_pages, render_pages, resolve_wiki_links, write_epubs, write_html_pages, write_pages, Config,
+    GemtextRenderer, LatexRenderer, MarkdownPage, PlainTextRenderer,
+};
+use webdotx::{
+    check, load_template, load_templates, load_templates_with_errors, render, Arguments, Registry, RenderContext,
+    Template, TemplateError, Value,
+};
+
+/// Output formats of a build, picked with `--format html,txt,gmi,tex,epub`. Formats other than
+/// html get their own output tree next to `output/`, e.g. `output-gmi/`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Html,
+    Txt,
+    Gmi,
+    Tex,
+    /// A book for every `series` of pages.
+    Epub,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "html" => Some(Format::Html),
+            "txt" => Some(Format::Txt),
+            "gmi" => Some(Format::Gmi),
+            "tex" => Some(Format::Tex),
+            "epub" => Some(Format::Epub),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Txt => "txt",
+            Format::Gmi => "gmi",
+            Format::Tex => "tex",
+            Format::Epub => "epub",
+        }
+    }
+
+    fn output_path(self) -> String {
+        match self {
+            Format::Html => "output".to_string(),
+            format => format!("output-{}", format.extension()),
+        }
+    }
+}
+
+/// `webdotmd` builds the site, `webdotmd check` only checks the templates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Build,
+    Check,
+}
+
+struct Args {
+    command: Command,
+    formats: Vec<Format>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut command = Command::Build;
+    let mut formats = vec![Format::Html];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--format=") {
+            Some(value) => value.to_string(),
+            None if arg == "--format" => args.next().ok_or("--format needs a list of formats")?,
+            None if arg == "check" => {
+                command = Command::Check;
+                continue;
+            }
+            None => return Err(format!("unknown argument {arg}")),
+        };
+        formats = value
+            .split(',')
+            .map(|name| {
+                Format::from_name(name.trim())
+                    .ok_or(format!("unknown format {name}, expected html, txt, gmi, tex or epub"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(Args { command, formats })
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // let args = parse_args();
+    let args = parse_args()?;
     let templates_path = Path::new("templates");
     let pages_path = Path::new("pages");
-    let output_path = Path::new("output");
+    let config = create_config();
+    let mut md_pages = load_markdown_pages(pages_path, &config)?;
+    for error in resolve_wiki_links(&mut md_pages, pages_path) {
+        eprintln!("warning: {error}");
+    }
+    if args.command == Command::Check {
+        // broken templates are reported with the other findings
+        let (templates, errors) = load_templates_with_errors(templates_path, Some("html"));
+        return check_templates(&templates, &errors, &md_pages);
+    }
     let templates = load_templates(templates_path, Some("html"))?;
-    let md_pages = load_markdown_pages(pages_path)?;
-    let autofill_funcs = create_autofill_funcs();
-    let html_pages = render(&md_pages, &templates, &Some(autofill_funcs));
-    clear_output_directory(None)?;
-    write_html_pages(&html_pages, pages_path, output_path)?;
     let assets = Path::new("assets");
-    copy_files_from_dir_to_dir(assets, output_path)?;
+    for format in args.formats {
+        let output_path = format.output_path();
+        let output_path = Path::new(&output_path);
+        clear_output_directory(Some(output_path))?;
+        match format {
+            Format::Html => {
+                let html_pages = render(&md_pages, &templates, &create_registry(&md_pages))?;
+                write_html_pages(&html_pages, pages_path, output_path)?;
+            }
+            Format::Txt => write_pages(
+                &render_pages(&md_pages, &PlainTextRenderer, format.extension()),
+                pages_path,
+                output_path,
+                format.extension(),
+            )?,
+            Format::Gmi => write_pages(
+                &render_pages(&md_pages, &GemtextRenderer, format.extension()),
+                pages_path,
+                output_path,
+                format.extension(),
+            )?,
+            Format::Tex => write_pages(
+                &render_pages(&md_pages, &LatexRenderer, format.extension()),
+                pages_path,
+                output_path,
+                format.extension(),
+            )?,
+            // images are packed into the books
+            Format::Epub => write_epubs(&md_pages, assets, output_path)?,
+        }
+        // assets are the site's scripts, styles and images, other formats don't load them
+        if format == Format::Html {
+            copy_files_from_dir_to_dir(assets, output_path)?;
+        }
+    }
     Ok(())
 }
 
-fn create_autofill_funcs() -> FuncMap {
-    let mut autofill_funcs: FuncMap = HashMap::new();
-    autofill_funcs.insert("navbar", &create_navbar);
-    autofill_funcs.insert("footer", &create_footer);
-    autofill_funcs.insert("article_entry_list", &create_article_entry_list);
-    autofill_funcs.insert("favicon_trash", &create_favicon_trash);
-    autofill_funcs.insert("code_highlighting", &get_code_highlighting);
+/// Functions of the site, `article_entry_list` lists the articles among `md_pages`.
+fn create_registry(md_pages: &HashMap<String, MarkdownPage>) -> Registry {
+    let mut registry = create_element_registry();
+    // pages are listed once, every call only picks from the list
+    let articles = list_articles(md_pages);
+    let list_template =
+        load_template(Path::new("templates/elements/article_list.html")).map_err(|error| error.to_string());
+    let element_registry = create_element_registry();
+    registry.register("article_entry_list", move |_, arguments| {
+        let articles = articles.as_ref().map_err(Clone::clone)?;
+        let list_template = list_template.as_ref().map_err(Clone::clone)?;
+        create_article_entry_list(articles, arguments, list_template, &element_registry)
+    });
+    registry
+}
+
+/// Functions available to every template, including the list filled by
+/// `create_article_entry_list`.
+fn create_element_registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register("active_link_classes", active_link_classes);
+    registry.register("favicon_trash", constant(create_favicon_trash));
+    registry.register("code_highlighting", constant(get_code_highlighting));
     // TODO: add an ability to specify features for pages via tagging in metadata, for example:
     // `features: math, code`
-    autofill_funcs.insert("mathjax", &get_mathjax);
-    autofill_funcs.insert("outline_highlighting", &get_outline_highlighting);
-    autofill_funcs.insert("tw_classes_push_footer", &get_classes_to_push_footer_down);
-    autofill_funcs.insert("link_classes", &get_link_classes);
-    autofill_funcs.insert("body_classes", &get_body_classes);
-    autofill_funcs.insert("default_theme", &get_default_theme);
-    autofill_funcs.insert("main_opening", &main_opening);
-    autofill_funcs.insert("main_closing", &main_closing);
+    registry.register("mathjax", constant(get_mathjax));
+    registry.register("outline_highlighting", constant(get_outline_highlighting));
+    registry.register("tw_classes_push_footer", constant(get_classes_to_push_footer_down));
+    registry.register("link_classes", constant(get_link_classes));
+    registry.register("body_classes", constant(get_body_classes));
+    registry.register("default_theme", constant(get_default_theme));
+    registry.register("main_opening", constant(main_opening));
+    registry.register("main_closing", constant(main_closing));
+
+    registry
+}
+
+/// Autofill function that ignores the page and arguments, and always returns the same HTML.
+fn constant(text: fn() -> &'static str) -> impl Fn(&RenderContext, &Arguments) -> Result<String, String> {
+    move |_, _| Ok(text().to_string())
+}
+
+/// Prints every problem found in the templates, fails if there is any.
+fn check_templates(
+    templates: &HashMap<String, Template>,
+    errors: &[TemplateError],
+    md_pages: &HashMap<String, MarkdownPage>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let registry = create_registry(md_pages);
+    let pages = md_pages
+        .values()
+        .filter(|page| !page.is_hidden())
+        .filter_map(|page| Some((page.get_metadata("template")?, page.template_values())))
+        .collect::<Vec<_>>();
+    let mut checked = check(templates, &registry).with_errors(errors);
+    for (template, values) in &pages {
+        checked = checked.with_use(template, Some(values));
+    }
+    // element templates are filled while rendering pages, and `article_list.html` by
+    // `create_article_entry_list`
+    let element_directories = md_pages
+        .values()
+        .filter_map(|page| page.get_metadata("element_templates").map(String::as_str))
+        .chain(["templates/elements/"])
+        .collect::<Vec<_>>();
+    for name in templates.keys() {
+        if element_directories.iter().any(|directory| name.starts_with(directory)) {
+            checked = checked.with_use(name, None);
+        }
+    }
+
+    let findings = checked.findings();
+    for finding in &findings {
+        eprintln!("{finding}");
+    }
+    match findings.len() {
+        0 => Ok(()),
+        count => Err(format!("found {count} problems in templates").into()),
+    }
+}
 
-    autofill_funcs
+fn create_config() -> Config {
+    let substitutions = HashMap::from([
+        ("(c)".to_string(), "©".to_string()),
+        ("(tm)".to_string(), "™".to_string()),
+    ]);
+    Config {
+        substitutions,
+        permalinks: true,
+        ..Default::default()
+    }
 }
 
 fn get_outline_highlighting() -> &'static str {
@@ -102,33 +297,6 @@ fn main_closing() -> &'static str {
 "
 }
 
-fn create_navbar() -> &'static str {
-    let link_classes = get_link_classes();
-    let navbar = Box::from(format!(
-        r##"<nav class="flex justify-between items-center p-4 px-8 max-h-16 bg-l-bg-accent dark:bg-d-bg-accent">
-<a href="https://maciejurban.dev"><img src="website-logo.svg" class="object-contain w-20"/></a>
-<div class="flex justify-between gap-6">
-    <a href="/" class="{link_classes}">Home</a>
-    <a href="articles.html" class="{link_classes}">Articles</a>
-    <a href="https://github.com/BlankTiger" class="{link_classes}">GitHub</a>
-    
-    <div>
-        <label class="inline-flex items-center cursor-pointer">
-          <input id="themeToggle" type="checkbox" value="" class="sr-only peer" checked>
-          <div class="relative w-11 h-6 bg-gray-200 rounded-full peer peer-focus:ring-4 peer-focus:ring-blue-300 dark:peer-focus:ring-blue-800 dark:bg-gray-700 peer-checked:after:translate-x-full rtl:peer-checked:after:-translate-x-full peer-checked:after:border-white after:content-[''] after:absolute after:top-0.5 after:start-[2px] after:bg-white after:border-gray-300 after:border after:rounded-full after:h-5 after:w-5 after:transition-all dark:border-gray-600 peer-checked:bg-blue-600"></div>
-        </label>
-    </div>
-
-    <script src="ThemeToggle.js"></script>
-    <script src="PresetTheme.js" strategy="beforeInteractive"></script>
-</div>
-</nav>
-<hr/>
-"##
-    ));
-    Box::leak(navbar)
-}
-
 fn get_link_classes() -> &'static str {
     "text-l-accent 
     hover:text-l-accent-secondary 
@@ -136,69 +304,85 @@ fn get_link_classes() -> &'static str {
     dark:hover:text-d-accent-secondary"
 }
 
-fn create_footer() -> &'static str {
-    // TODO: think on how to resolve placeholders in autofill_funcs
-    // TODO: fix how footer is positioned with css grid
-    r#"
-<footer class="w-full bg-l-bg-accent dark:bg-d-bg-accent">
-<hr/>
-<div class="p-4 px-8">© Maciej Urban 2024</div>
-</footer>"#
+/// Classes added to a navbar link when the page being rendered is in the link's `section`, given
+/// as an argument. The page's section is its `section` metadata, or else comes from its template.
+fn active_link_classes(context: &RenderContext, arguments: &Arguments) -> Result<String, String> {
+    let section = arguments
+        .get("section")
+        .and_then(Value::as_text)
+        .ok_or("missing argument section")?;
+    let page_section = match context.get("page.meta.section").and_then(Value::as_text) {
+        Some(section) => section.to_string(),
+        None => match context.get("page.meta.template").and_then(Value::as_text).as_deref() {
+            Some("templates/index.html") => "home".to_string(),
+            Some("templates/articles.html" | "templates/article_entry.html") => "articles".to_string(),
+            _ => String::new(),
+        },
+    };
+    let classes = if page_section == section {
+        get_active_link_classes()
+    } else {
+        ""
+    };
+    Ok(classes.to_string())
 }
 
-struct CardWithDate {
-    card: String,
-    date: chrono::NaiveDate,
+fn get_active_link_classes() -> &'static str {
+    "font-semibold underline underline-offset-4"
 }
 
-fn create_article_entry_list() -> &'static str {
-    // let templates_path = Path::new("templates");
-    // let templates = load_templates(templates_path, Some("html")).unwrap();
-    let card_template = load_template(Path::new("templates/elements/article_card.html")).unwrap();
-    let pages_path = Path::new("pages");
-    let md_pages = load_markdown_pages(pages_path).unwrap();
-    let mut list: Vec<CardWithDate> = Vec::new();
+/// Visible articles, pages with the `templates/article_entry.html` template, newest first.
+fn list_articles(md_pages: &HashMap<String, MarkdownPage>) -> Result<Vec<Value>, String> {
+    let mut articles: Vec<(chrono::NaiveDate, Value)> = Vec::new();
     for (name, page) in md_pages {
-        if let Some(template_name) = page.get_metadata("template") {
-            if template_name == "templates/article_entry.html" {
-                if let Some(hidden) = page.get_metadata("hidden") {
-                    if hidden == "true" {
-                        continue;
-                    }
-                }
-                let (_, href) = name.split_once('/').unwrap();
-                let href = href.replace(".md", ".html");
-                let page_title = page.get_metadata("title").unwrap();
-                let date = page.get_metadata("date").unwrap().to_string();
-                let filled_placeholders = HashMap::from([
-                    ("title".to_string(), page_title.to_string()),
-                    ("summary".to_string(), page.get_metadata("summary").unwrap().to_string()),
-                    ("date".to_string(), date.clone()),
-                    (
-                        "time_to_read".to_string(),
-                        page.get_metadata("time_to_read").unwrap().to_string(),
-                    ),
-                    ("link".to_string(), href),
-                ]);
-                let autofill_funcs = create_autofill_funcs();
-                let entry = card_template.fill_template(filled_placeholders, &Some(autofill_funcs));
-                let (year, month, day) = parse_year_month_date_from_str(&date);
-                list.push(CardWithDate {
-                    card: entry,
-                    date: chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap(),
-                });
-            }
+        if page.is_hidden()
+            || page
+                .get_metadata("template")
+                .is_none_or(|name| name != "templates/article_entry.html")
+        {
+            continue;
         }
+        let metadata = |field: &str| {
+            page.get_metadata(field)
+                .ok_or(format!("{name}: article has no {field} in its metadata"))
+        };
+        let (_, href) = name
+            .split_once('/')
+            .ok_or(format!("{name}: article isn't in the pages directory"))?;
+        let href = href.replace(".md", ".html");
+        let date = metadata("date")?;
+        let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|error| format!("{name}: incorrect date {date}, expected YYYY-MM-DD: {error}"))?;
+        let mut article = HashMap::from([("link".to_string(), href)]);
+        // guest posts can be written in another language than the listing
+        for field in ["title", "summary", "date", "time_to_read", "lang", "dir"] {
+            article.insert(field.to_string(), metadata(field)?.to_string());
+        }
+        articles.push((date, Value::from(article)));
     }
-    list.sort_by(|a, b| b.date.cmp(&a.date));
-    let list = Box::from(list.iter().map(|a| a.card.clone()).collect::<String>());
-    Box::leak(list)
+    articles.sort_by_key(|(date, _)| std::cmp::Reverse(*date));
+    Ok(articles.into_iter().map(|(_, article)| article).collect())
 }
 
-fn parse_year_month_date_from_str(date: &str) -> (i32, u32, u32) {
-    let (year, rest) = date.split_once("-").unwrap();
-    let (month, day) = rest.split_once("-").unwrap();
-    (year.parse().unwrap(), month.parse().unwrap(), day.parse().unwrap())
+/// Fills the article list with the articles, only the newest `count` of them if the argument is
+/// given, e.g. `{{ %article_entry_list count="3"% }}`.
+fn create_article_entry_list(
+    articles: &[Value],
+    arguments: &Arguments,
+    list_template: &Template,
+    registry: &Registry,
+) -> Result<String, String> {
+    let count = match arguments.get("count").and_then(Value::as_text) {
+        Some(count) => count
+            .parse()
+            .map_err(|_| format!("incorrect count {count}, expected a number"))?,
+        None => articles.len(),
+    };
+    let articles = articles.iter().take(count).cloned().collect::<Vec<_>>();
+    let filled_placeholders = HashMap::from([("articles".to_string(), Value::from(articles))]);
+    list_template
+        .fill_template(filled_placeholders, registry)
+        .map_err(|error| error.to_string())
 }
 
 fn create_favicon_trash() -> &'static str {
@@ -223,8 +407,9 @@ fn get_default_theme() -> &'static str {
 
 fn clear_output_directory(output_dir: Option<&Path>) -> Result<(), std::io::Error> {
     let output_dir = output_dir.unwrap_or(Path::new("output"));
-    assert!(output_dir.is_dir());
-    std::fs::remove_dir_all(output_dir)?;
+    if output_dir.is_dir() {
+        std::fs::remove_dir_all(output_dir)?;
+    }
     std::fs::create_dir(output_dir)?;
     Ok(())
 }
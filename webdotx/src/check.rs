@@ -0,0 +1,495 @@
+use crate::error::suggest;
+use crate::template::{Node, Operand, Placeholder, Position};
+use crate::{Context, Registry, RenderContext, Template, TemplateError, TemplateErrorKind};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Problem found in templates by `check`, without rendering them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    /// Name of the template the problem is written in, as returned by `load_templates`, or the
+    /// name of the checked template if it was created with `Template::new`.
+    pub template: String,
+    /// Line of the problem counting from 1, 0 when the problem is the whole template.
+    pub line: usize,
+    /// Column of the problem in characters counting from 1, 0 when the problem is the whole
+    /// template.
+    pub column: usize,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FindingKind {
+    /// A half of a placeholder's delimiters without the other one, e.g. `{{ $name }}`, which is
+    /// rendered as text.
+    UnbalancedDelimiters,
+    /// No autofill function of that name is registered, `suggestion` is a registered name close to it.
+    UnknownFunction { name: String, suggestion: Option<String> },
+    /// No filter of that name is registered, `suggestion` is a registered name close to it.
+    UnknownFilter {
+        placeholder: String,
+        filter: String,
+        suggestion: Option<String>,
+    },
+    /// None of the uses of the template provides a value for the placeholder, `suggestion` is a
+    /// provided name close to it.
+    UnprovidedPlaceholder {
+        placeholder: String,
+        suggestion: Option<String>,
+    },
+    /// The template isn't used, and no used template extends or includes it.
+    UnusedTemplate,
+    /// The template couldn't be read, `message` is the reason.
+    Unreadable { message: String },
+    /// `{{ if }}`, `{{ for }}` or `{{ block }}` without its `{{ end }}`, only autofill functions
+    /// and includes of the template are checked.
+    Unclosed { tag: String },
+    /// `{{ end }}`, `{{ else }}` or `{{ else if }}` where it doesn't close anything, only autofill
+    /// functions and includes of the template are checked.
+    UnexpectedEnd { tag: String },
+    /// Tag that can't be parsed, the rest of the template is checked without it.
+    Malformed { message: String },
+    /// `{{ extends "name" }}` names a template that doesn't exist.
+    UnknownParent { name: String },
+    /// `{{ include "name" }}` names a template that doesn't exist.
+    UnknownInclude { name: String },
+    /// Templates extend each other in a loop, from the template that starts it back to itself.
+    InheritanceCycle { cycle: Vec<String> },
+    /// Templates include each other in a loop, from the template that starts it back to itself.
+    IncludeCycle { cycle: Vec<String> },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.template)?;
+        if self.line > 0 {
+            write!(f, "{}:{}:", self.line, self.column)?;
+        }
+        let did_you_mean = |suggestion: &Option<String>| match suggestion {
+            Some(suggestion) => format!(", did you mean {suggestion}?"),
+            None => String::new(),
+        };
+        match &self.kind {
+            FindingKind::UnbalancedDelimiters => write!(f, " unbalanced placeholder delimiters"),
+            FindingKind::UnknownFunction { name, suggestion } => {
+                write!(f, " unknown autofill function {name}{}", did_you_mean(suggestion))
+            }
+            FindingKind::UnknownFilter {
+                placeholder,
+                filter,
+                suggestion,
+            } => write!(
+                f,
+                " unknown filter {filter} for placeholder {placeholder}{}",
+                did_you_mean(suggestion)
+            ),
+            FindingKind::UnprovidedPlaceholder {
+                placeholder,
+                suggestion,
+            } => write!(
+                f,
+                " no value is ever provided for placeholder {placeholder}{}",
+                did_you_mean(suggestion)
+            ),
+            FindingKind::UnusedTemplate => write!(f, " template is never used"),
+            FindingKind::Unreadable { message } => write!(f, " {message}"),
+            FindingKind::Unclosed { tag } => write!(f, " `{{{{ {tag} }}}}` without `{{{{ end }}}}`"),
+            FindingKind::UnexpectedEnd { tag } => write!(f, " unexpected `{{{{ {tag} }}}}`"),
+            FindingKind::Malformed { message } => write!(f, " {message}"),
+            FindingKind::UnknownParent { name } => write!(f, " extends unknown template {name}"),
+            FindingKind::UnknownInclude { name } => write!(f, " includes unknown template {name}"),
+            FindingKind::InheritanceCycle { cycle } => {
+                write!(f, " templates extend each other: {}", cycle.join(" -> "))
+            }
+            FindingKind::IncludeCycle { cycle } => {
+                write!(f, " templates include each other: {}", cycle.join(" -> "))
+            }
+        }
+    }
+}
+
+/// Starts checking templates returned by `load_templates` for problems that can be found without
+/// rendering them. Errors returned by `load_templates_with_errors` are reported with the findings
+/// when passed to `with_errors`.
+///
+/// Example use:
+/// ```rust
+/// use std::collections::HashMap;
+/// use webdotx::{check, Context, Registry, Template};
+///
+/// let templates = HashMap::from([
+///     ("page.html".to_string(), Template::new("{{ $titel$ }}{{ %curr_yaer% }}")),
+///     ("old.html".to_string(), Template::new("")),
+/// ]);
+/// let registry = Registry::new();
+/// let mut context = Context::new();
+/// context.insert("title", "Home");
+/// let findings = check(&templates, &registry).with_use("page.html", Some(&context)).findings();
+/// let findings = findings.iter().map(|finding| finding.to_string()).collect::<Vec<_>>();
+/// assert_eq!(
+///     vec![
+///         "old.html: template is never used",
+///         "page.html:1:1: no value is ever provided for placeholder titel, did you mean title?",
+///         "page.html:1:14: unknown autofill function curr_yaer, did you mean curr_year?",
+///     ],
+///     findings
+/// );
+/// ```
+pub fn check<'a>(templates: &'a HashMap<String, Template>, registry: &'a Registry) -> Check<'a> {
+    Check {
+        templates,
+        registry,
+        uses: vec![],
+        errors: &[],
+    }
+}
+
+/// Templates checked by `check`, with how they are used.
+pub struct Check<'a> {
+    templates: &'a HashMap<String, Template>,
+    registry: &'a Registry,
+    uses: Vec<(String, Option<&'a Context>)>,
+    errors: &'a [TemplateError],
+}
+
+impl<'a> Check<'a> {
+    /// Marks the template as filled with the values, or with values that aren't known before
+    /// rendering when `None`. Templates are checked for unused templates and for placeholders
+    /// that never get a value only if they have at least one use.
+    pub fn with_use(mut self, template: &str, values: Option<&'a Context>) -> Check<'a> {
+        self.uses.push((template.to_string(), values));
+        self
+    }
+
+    /// Reports the errors of loading the templates as findings.
+    pub fn with_errors(mut self, errors: &'a [TemplateError]) -> Check<'a> {
+        self.errors = errors;
+        self
+    }
+
+    /// Returns every problem found, sorted by template and position.
+    pub fn findings(&self) -> Vec<Finding> {
+        let mut findings = self.errors.iter().map(load_finding).collect::<Vec<_>>();
+        for (name, template) in self.templates {
+            for position in &template.unbalanced {
+                findings.push(finding(name, position, FindingKind::UnbalancedDelimiters));
+            }
+            walk(&template.nodes, &mut vec![], &mut |visit, _| {
+                if let Visit::Placeholder(placeholder) = visit {
+                    self.check_calls(name, placeholder, &mut findings);
+                }
+            });
+        }
+        if !self.uses.is_empty() {
+            self.check_unused(&mut findings);
+            self.check_values(&mut findings);
+        }
+        // templates extending or including the same template report its problems again
+        findings.sort();
+        findings.dedup();
+        findings
+    }
+
+    fn check_calls(&self, template: &str, placeholder: &Placeholder, findings: &mut Vec<Finding>) {
+        let name = &placeholder.name;
+        if placeholder.is_autofill && self.registry.function(name).is_none() {
+            let kind = FindingKind::UnknownFunction {
+                name: name.clone(),
+                suggestion: suggest(name, self.registry.function_names()),
+            };
+            findings.push(finding(template, &placeholder.position, kind));
+        }
+        for filter in &placeholder.filters {
+            if self.registry.filter(&filter.name).is_none() {
+                let kind = FindingKind::UnknownFilter {
+                    placeholder: name.clone(),
+                    filter: filter.name.clone(),
+                    suggestion: suggest(&filter.name, self.registry.filter_names()),
+                };
+                findings.push(finding(template, &placeholder.position, kind));
+            }
+        }
+    }
+
+    /// Finds templates that can't be reached from the used ones through `extends` and `include`.
+    fn check_unused(&self, findings: &mut Vec<Finding>) {
+        let mut used = HashSet::new();
+        let mut to_visit = self.uses.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        while let Some(name) = to_visit.pop() {
+            if !used.insert(name) {
+                continue;
+            }
+            if let Some(template) = self.templates.get(name) {
+                to_visit.extend(template.dependencies.iter().map(String::as_str));
+            }
+        }
+        for name in self.templates.keys().filter(|name| !used.contains(name.as_str())) {
+            findings.push(Finding {
+                template: name.clone(),
+                line: 0,
+                column: 0,
+                kind: FindingKind::UnusedTemplate,
+            });
+        }
+    }
+
+    /// Finds placeholders that none of the values of their template's uses provide. Loop
+    /// variables and include overrides are always provided.
+    fn check_values(&self, findings: &mut Vec<Finding>) {
+        // values used with each placeholder, keyed by position, as a parent or an included
+        // template is a part of many templates
+        let mut placeholders = BTreeMap::<(&Position, &str), (&str, Vec<&Context>)>::new();
+        for (name, values) in &self.uses {
+            let (Some(template), Some(values)) = (self.templates.get(name), values) else {
+                continue;
+            };
+            walk(&template.nodes, &mut vec![], &mut |visit, locals| {
+                if let Visit::Value(path, position) = visit {
+                    let variable = path.split('.').next().unwrap_or(path);
+                    if !locals.contains(&variable) {
+                        let (_, contexts) = placeholders.entry((position, path)).or_insert((name, vec![]));
+                        contexts.push(values);
+                    }
+                }
+            });
+        }
+        for ((position, path), (template, contexts)) in placeholders {
+            if contexts.iter().any(|values| values.get(path).is_some()) {
+                continue;
+            }
+            let suggestion = contexts
+                .iter()
+                .find_map(|values| RenderContext::new(values).suggest(path));
+            let kind = FindingKind::UnprovidedPlaceholder {
+                placeholder: path.to_string(),
+                suggestion,
+            };
+            findings.push(finding(template, position, kind));
+        }
+    }
+}
+
+/// Finding at the position, in the `template` being checked if the position isn't in a file.
+fn finding(template: &str, position: &Position, kind: FindingKind) -> Finding {
+    let template = match position.template.as_str() {
+        "" => template,
+        written_in => written_in,
+    };
+    Finding {
+        template: template.to_string(),
+        line: position.line,
+        column: position.column,
+        kind,
+    }
+}
+
+/// Finding of an error of loading templates.
+fn load_finding(error: &TemplateError) -> Finding {
+    let ((line, column), kind) = match &error.kind {
+        TemplateErrorKind::Io(error) => (
+            (0, 0),
+            FindingKind::Unreadable {
+                message: error.to_string(),
+            },
+        ),
+        TemplateErrorKind::Unclosed { tag, line, column } => {
+            ((*line, *column), FindingKind::Unclosed { tag: tag.clone() })
+        }
+        TemplateErrorKind::UnexpectedEnd { tag, line, column } => {
+            ((*line, *column), FindingKind::UnexpectedEnd { tag: tag.clone() })
+        }
+        TemplateErrorKind::Malformed { message, line, column } => (
+            (*line, *column),
+            FindingKind::Malformed {
+                message: message.clone(),
+            },
+        ),
+        TemplateErrorKind::UnknownParent { name, line, column } => {
+            ((*line, *column), FindingKind::UnknownParent { name: name.clone() })
+        }
+        TemplateErrorKind::UnknownInclude { name, line, column } => {
+            ((*line, *column), FindingKind::UnknownInclude { name: name.clone() })
+        }
+        TemplateErrorKind::InheritanceCycle(cycle) => ((0, 0), FindingKind::InheritanceCycle { cycle: cycle.clone() }),
+        TemplateErrorKind::IncludeCycle(cycle) => ((0, 0), FindingKind::IncludeCycle { cycle: cycle.clone() }),
+    };
+    Finding {
+        template: error.template.clone(),
+        line,
+        column,
+        kind,
+    }
+}
+
+/// Part of a template visited by `walk`.
+enum Visit<'n> {
+    Placeholder(&'n Placeholder),
+    /// Path to a value, from a placeholder, a loop or an operand, with the position of its tag.
+    Value(&'n str, &'n Position),
+}
+
+/// Calls `visit` for placeholders and values in the nodes, with names of loop variables and
+/// include overrides visible there. Conditions are skipped, as they are how templates handle
+/// values that might be missing.
+fn walk<'n>(nodes: &'n [Node], locals: &mut Vec<&'n str>, visit: &mut impl FnMut(Visit<'n>, &[&'n str])) {
+    for node in nodes {
+        match node {
+            Node::Text(_) | Node::Extends { .. } => {}
+            Node::Placeholder(placeholder) => {
+                visit(Visit::Placeholder(placeholder), locals);
+                if !placeholder.is_autofill {
+                    visit(Visit::Value(&placeholder.name, &placeholder.position), locals);
+                }
+                for (_, operand) in &placeholder.arguments {
+                    if let Operand::Placeholder(name) = operand {
+                        visit(Visit::Value(name, &placeholder.position), locals);
+                    }
+                }
+            }
+            Node::If {
+                condition: _,
+                then,
+                otherwise,
+            } => {
+                walk(then, locals, visit);
+                walk(otherwise, locals, visit);
+            }
+            Node::For {
+                variable,
+                list,
+                body,
+                otherwise,
+                position,
+            } => {
+                visit(Visit::Value(list, position), locals);
+                locals.extend([variable.as_str(), "loop"]);
+                walk(body, locals, visit);
+                locals.truncate(locals.len() - 2);
+                walk(otherwise, locals, visit);
+            }
+            Node::Block { name: _, body } => walk(body, locals, visit),
+            Node::Include {
+                template: _,
+                overrides,
+                nodes,
+                position,
+            } => {
+                for (_, operand) in overrides {
+                    if let Operand::Placeholder(name) = operand {
+                        visit(Visit::Value(name, position), locals);
+                    }
+                }
+                let count = locals.len();
+                locals.extend(overrides.iter().map(|(key, _)| key.as_str()));
+                walk(nodes, locals, visit);
+                locals.truncate(count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, FindingKind};
+    use crate::template::parse_templates;
+    use crate::{Context, Registry, Template, TemplateError};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn load(contents: &[(&str, &str)]) -> (HashMap<String, Template>, Vec<TemplateError>) {
+        let contents = contents
+            .iter()
+            .map(|(name, content)| (format!("templates/{name}"), content.to_string()));
+        parse_templates(contents, Path::new("templates"))
+    }
+
+    fn templates(contents: &[(&str, &str)]) -> HashMap<String, Template> {
+        let (templates, errors) = load(contents);
+        assert!(errors.is_empty(), "{errors:?}");
+        templates
+    }
+
+    #[test]
+    fn test_check() {
+        let templates = templates(&[
+            (
+                "base.html",
+                "<title>{{ $title$ }}</title>\n{{ block main }}{{ end }}{{ include \"footer.html\" with year=$yeer$ }}",
+            ),
+            (
+                "page.html",
+                "{{ extends \"base.html\" }}{{ block main }}{{ $content | uper $}} {{ $name }}\n\
+                {{ for item in $items$ }}{{ $item.title$ }}{{ $loop.index$ }}{{ end }}{{ end }}",
+            ),
+            ("footer.html", "{{ $year$ }} {{ %copyright% }}"),
+            ("old.html", "{{ $content$ }}"),
+            ("elements/link.html", "{{ $href$ }}"),
+        ]);
+        let mut values = Context::new();
+        values.insert("title", "Title");
+        values.insert("content", "Content");
+        values.insert("year", "2026");
+        let registry = Registry::new();
+        let findings = check(&templates, &registry)
+            .with_use("templates/page.html", Some(&values))
+            .with_use("templates/elements/link.html", None)
+            .findings();
+        let findings = findings.iter().map(|finding| finding.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "templates/base.html:2:26: no value is ever provided for placeholder yeer, did you mean year?",
+                "templates/footer.html:1:14: unknown autofill function copyright",
+                "templates/old.html: template is never used",
+                "templates/page.html:1:42: unknown filter uper for placeholder content, did you mean upper?",
+                "templates/page.html:1:65: unbalanced placeholder delimiters",
+                "templates/page.html:2:1: no value is ever provided for placeholder items",
+            ],
+            findings
+        );
+    }
+
+    #[test]
+    fn test_check_load_errors() {
+        let (templates, errors) = load(&[
+            (
+                "base.html",
+                "<main>{{ block main }}{{ end }}</main>{{ include \"footer.html\" }}",
+            ),
+            (
+                "page.html",
+                "{{ extends \"base.html\" }}\n{{ block main }}{{ if $a$ }}{{ end }}",
+            ),
+            ("post.html", "{{ extends \"bsae.html\" }}{{ $titel$ }}"),
+            ("index.html", "{{ include \"nav.html\" }}{{ %curr_yaer% }}"),
+            (
+                "about.html",
+                "{{ if $a$ }}{{ $date | date:%Y $}}\n{{ for x in $xs$ }}{{ %navbar% }}{{ $x$ }}{{ include \"side.html\" }}",
+            ),
+        ]);
+        let findings = check(&templates, &Registry::new()).with_errors(&errors).findings();
+        let findings = findings.iter().map(|finding| finding.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "templates/about.html:1:13: incorrect filter argument, expected a quoted string: %Y",
+                "templates/about.html:2:1: `{{ for }}` without `{{ end }}`",
+                "templates/about.html:2:20: unknown autofill function navbar",
+                "templates/about.html:2:43: includes unknown template side.html",
+                "templates/base.html:1:39: includes unknown template footer.html",
+                "templates/index.html:1:1: includes unknown template nav.html",
+                "templates/index.html:1:25: unknown autofill function curr_yaer, did you mean curr_year?",
+                "templates/page.html:2:1: `{{ block }}` without `{{ end }}`",
+                "templates/post.html:1:1: extends unknown template bsae.html",
+            ],
+            findings
+        );
+    }
+
+    #[test]
+    fn test_check_without_uses() {
+        let templates = templates(&[("page.html", "{{ $title$ }} $name$ }}")]);
+        let findings = check(&templates, &Registry::new()).findings();
+        assert_eq!(1, findings.len());
+        assert_eq!(FindingKind::UnbalancedDelimiters, findings[0].kind);
+        assert_eq!((1, 20), (findings[0].line, findings[0].column));
+    }
+}
@@ -0,0 +1,256 @@
+use crate::Template;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Problem found while loading templates.
+#[derive(Debug)]
+pub struct TemplateError {
+    /// Name of the template as returned by `load_templates`, or the templates directory if it
+    /// couldn't be read.
+    pub template: String,
+    pub kind: TemplateErrorKind,
+}
+
+#[derive(Debug)]
+pub enum TemplateErrorKind {
+    Io(std::io::Error),
+    /// `{{ extends "name" }}` names a template that doesn't exist.
+    UnknownParent {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+    /// Templates extend each other in a loop, from the template that starts it back to itself.
+    InheritanceCycle(Vec<String>),
+    /// `{{ include "name" }}` names a template that doesn't exist.
+    UnknownInclude {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+    /// Templates include each other in a loop, from the template that starts it back to itself.
+    IncludeCycle(Vec<String>),
+    /// `{{ if }}`, `{{ for }}` or `{{ block }}` without its `{{ end }}`, `tag` is the tag's keyword.
+    Unclosed {
+        tag: String,
+        line: usize,
+        column: usize,
+    },
+    /// `{{ end }}`, `{{ else }}` or `{{ else if }}` where it doesn't close anything, e.g. a second
+    /// `{{ else }}` of the same `{{ if }}`.
+    UnexpectedEnd {
+        tag: String,
+        line: usize,
+        column: usize,
+    },
+    /// Tag that can't be parsed, e.g. `{{ for item $list$ }}` or an unquoted filter argument.
+    Malformed {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { template, kind } = self;
+        match kind {
+            TemplateErrorKind::Io(error) => write!(f, "{template}: {error}"),
+            TemplateErrorKind::UnknownParent { name, line, column } => {
+                write!(f, "{template}:{line}:{column}: extends unknown template {name}")
+            }
+            TemplateErrorKind::InheritanceCycle(cycle) => {
+                write!(f, "{template}: templates extend each other: {}", cycle.join(" -> "))
+            }
+            TemplateErrorKind::UnknownInclude { name, line, column } => {
+                write!(f, "{template}:{line}:{column}: includes unknown template {name}")
+            }
+            TemplateErrorKind::IncludeCycle(cycle) => {
+                write!(f, "{template}: templates include each other: {}", cycle.join(" -> "))
+            }
+            TemplateErrorKind::Unclosed { tag, line, column } => {
+                write!(
+                    f,
+                    "{template}:{line}:{column}: `{{{{ {tag} }}}}` without `{{{{ end }}}}`"
+                )
+            }
+            TemplateErrorKind::UnexpectedEnd { tag, line, column } => {
+                write!(f, "{template}:{line}:{column}: unexpected `{{{{ {tag} }}}}`")
+            }
+            TemplateErrorKind::Malformed { message, line, column } => {
+                write!(f, "{template}:{line}:{column}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            TemplateErrorKind::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Problem found while filling a template.
+#[derive(Debug)]
+pub struct RenderError {
+    /// Name of the renderable that failed, set by `render`.
+    pub renderable: Option<String>,
+    /// Name of the template the failing tag is written in, as returned by `load_templates`, which
+    /// can be a parent or an included template of the one being filled. Empty for templates
+    /// created with `Template::new`.
+    pub template: String,
+    /// Line of the failing tag, counting from 1, 0 when the error isn't about a tag.
+    pub line: usize,
+    /// Column of the failing tag in characters, counting from 1, 0 when the error isn't about a
+    /// tag.
+    pub column: usize,
+    /// Boxed, so results of rendering stay small when nothing fails.
+    pub kind: Box<RenderErrorKind>,
+}
+
+#[derive(Debug)]
+pub enum RenderErrorKind {
+    /// No value was provided for a placeholder, `suggestion` is a provided name close to it.
+    MissingValue {
+        placeholder: String,
+        suggestion: Option<String>,
+    },
+    /// Value of a placeholder is a list or a map, which can't be inserted into the page.
+    NotText(String),
+    /// `{{ for }}` goes over a value that isn't a list.
+    NotAList(String),
+    /// No autofill function of that name is registered, `suggestion` is a registered name close to it.
+    UnknownFunction { name: String, suggestion: Option<String> },
+    /// Autofill function returned an error.
+    FunctionFailed { name: String, message: String },
+    /// No filter of that name is registered, `suggestion` is a registered name close to it.
+    UnknownFilter {
+        placeholder: String,
+        filter: String,
+        suggestion: Option<String>,
+    },
+    /// Filter returned an error.
+    FilterFailed {
+        placeholder: String,
+        filter: String,
+        message: String,
+    },
+    /// Renderable chose a template that wasn't loaded, `suggestion` is a loaded name close to it.
+    UnknownTemplate { name: String, suggestion: Option<String> },
+}
+
+impl RenderError {
+    /// Error for a renderable that chose the template `name`, which isn't among `templates`.
+    pub fn unknown_template(name: &str, templates: &HashMap<String, Template>) -> RenderError {
+        RenderError {
+            renderable: None,
+            template: String::new(),
+            line: 0,
+            column: 0,
+            kind: Box::new(RenderErrorKind::UnknownTemplate {
+                name: name.to_string(),
+                suggestion: suggest(name, templates.keys().map(String::as_str)),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(renderable) = &self.renderable {
+            write!(f, "{renderable}: ")?;
+        }
+        if !self.template.is_empty() {
+            write!(f, "{}:", self.template)?;
+        }
+        if self.line > 0 {
+            write!(f, "{}:{}: ", self.line, self.column)?;
+        }
+        let did_you_mean = |suggestion: &Option<String>| match suggestion {
+            Some(suggestion) => format!(", did you mean {suggestion}?"),
+            None => String::new(),
+        };
+        match self.kind.as_ref() {
+            RenderErrorKind::MissingValue {
+                placeholder,
+                suggestion,
+            } => write!(
+                f,
+                "missing value for placeholder {placeholder}{}",
+                did_you_mean(suggestion)
+            ),
+            RenderErrorKind::NotText(placeholder) => write!(f, "placeholder {placeholder} is not a text value"),
+            RenderErrorKind::NotAList(placeholder) => write!(f, "placeholder {placeholder} is not a list"),
+            RenderErrorKind::UnknownFunction { name, suggestion } => {
+                write!(f, "unknown autofill function {name}{}", did_you_mean(suggestion))
+            }
+            RenderErrorKind::FunctionFailed { name, message } => {
+                write!(f, "autofill function {name} failed: {message}")
+            }
+            RenderErrorKind::UnknownFilter {
+                placeholder,
+                filter,
+                suggestion,
+            } => write!(
+                f,
+                "unknown filter {filter} for placeholder {placeholder}{}",
+                did_you_mean(suggestion)
+            ),
+            RenderErrorKind::FilterFailed {
+                placeholder,
+                filter,
+                message,
+            } => write!(f, "filter {filter} failed for placeholder {placeholder}: {message}"),
+            RenderErrorKind::UnknownTemplate { name, suggestion } => {
+                write!(f, "unknown template {name}{}", did_you_mean(suggestion))
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Finds the candidate closest to a misspelled `name`, if it's close enough to be a typo.
+pub(crate) fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let max_distance = name.chars().count().div_ceil(3);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        // ties go to the first name alphabetically, so the suggestion doesn't change between runs
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance, the number of inserted, removed or replaced characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replaced = previous[j] + usize::from(a_char != *b_char);
+            current.push(replaced.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, suggest};
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(2, edit_distance("titel", "title"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+        let names = ["title", "summary", "date"];
+        assert_eq!(Some("title".to_string()), suggest("titel", names));
+        assert_eq!(Some("date".to_string()), suggest("dat", names));
+        assert_eq!(None, suggest("author", names));
+    }
+}
@@ -0,0 +1,114 @@
+/// Where in the HTML a placeholder is, which decides the characters its value escapes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Escape {
+    /// Between tags, e.g. `<title>{{ $title$ }}</title>`.
+    Text,
+    /// Inside a tag, usually in an attribute value, e.g. `<a href="{{ $link$ }}">`.
+    Attribute,
+}
+
+/// State of the HTML scanned so far by `HtmlContext`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Text,
+    Tag,
+    DoubleQuoted,
+    SingleQuoted,
+}
+
+/// Follows template text to know whether a placeholder after it is in text or inside a tag.
+/// Values of placeholders are escaped, so only the template's own text can change the state.
+#[derive(Debug)]
+pub(crate) struct HtmlContext {
+    state: State,
+}
+
+impl HtmlContext {
+    pub(crate) fn new() -> HtmlContext {
+        HtmlContext { state: State::Text }
+    }
+
+    pub(crate) fn advance(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        while let Some(char) = chars.next() {
+            self.state = match (self.state, char) {
+                (State::Text, '<')
+                    if chars
+                        .peek()
+                        .is_some_and(|next| next.is_ascii_alphabetic() || "/!".contains(*next)) =>
+                {
+                    State::Tag
+                }
+                (State::Tag, '>') => State::Text,
+                (State::Tag, '"') => State::DoubleQuoted,
+                (State::Tag, '\'') => State::SingleQuoted,
+                (State::DoubleQuoted, '"') | (State::SingleQuoted, '\'') => State::Tag,
+                (state, _) => state,
+            };
+        }
+    }
+
+    pub(crate) fn escape(&self) -> Escape {
+        match self.state {
+            State::Text => Escape::Text,
+            State::Tag | State::DoubleQuoted | State::SingleQuoted => Escape::Attribute,
+        }
+    }
+}
+
+/// Escapes `&`, `<` and `>` in text, and also quotes inside tags.
+pub(crate) fn escape(text: &str, escape: Escape) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match (char, escape) {
+            ('&', _) => escaped.push_str("&amp;"),
+            ('<', _) => escaped.push_str("&lt;"),
+            ('>', _) => escaped.push_str("&gt;"),
+            ('"', Escape::Attribute) => escaped.push_str("&quot;"),
+            ('\'', Escape::Attribute) => escaped.push_str("&#39;"),
+            (char, _) => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, Escape, HtmlContext};
+
+    #[test]
+    fn test_html_context() {
+        let mut context = HtmlContext::new();
+        let mut escapes = vec![];
+        for text in [
+            "<title>",
+            "</title><a href=\"",
+            "\" title='a > b ",
+            "'>",
+            " 1 < 2 ",
+            "<!-- comment -->",
+        ] {
+            context.advance(text);
+            escapes.push(context.escape());
+        }
+        let expected = [
+            Escape::Text,
+            Escape::Attribute,
+            Escape::Attribute,
+            Escape::Text,
+            Escape::Text,
+            Escape::Text,
+        ];
+        assert_eq!(expected.to_vec(), escapes);
+    }
+
+    #[test]
+    fn test_escape() {
+        let text = r#"<b>"Tom" & 'Jerry'</b>"#;
+        assert_eq!(r#"&lt;b&gt;"Tom" &amp; 'Jerry'&lt;/b&gt;"#, escape(text, Escape::Text));
+        assert_eq!(
+            "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;",
+            escape(text, Escape::Attribute)
+        );
+    }
+}
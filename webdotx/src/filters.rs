@@ -0,0 +1,204 @@
+use crate::Value;
+use std::fmt::Write;
+
+/// Filter applied to a placeholder's value with `{{ $name | filter:argument, other $ }}`, gets the
+/// value and the arguments, and returns the new value or a message explaining why it can't. Custom
+/// filters are added with `Registry::register_filter`.
+pub type Filter = fn(&Value, &[Value]) -> Result<Value, String>;
+
+pub(crate) const BUILTIN_FILTERS: [(&str, Filter); 7] = [
+    ("date", date),
+    ("upper", upper),
+    ("lower", lower),
+    ("truncate", truncate),
+    ("urlencode", urlencode),
+    ("json", json),
+    ("safe", safe),
+];
+
+fn text(value: &Value) -> Result<String, String> {
+    value
+        .as_text()
+        .map(|text| text.into_owned())
+        .ok_or("expected text, got a list or a map".to_string())
+}
+
+fn argument<'a>(arguments: &'a [Value], index: usize, name: &str) -> Result<&'a Value, String> {
+    arguments.get(index).ok_or(format!("missing {name} argument"))
+}
+
+/// `date:"%d %b %Y"`, formats a `2024-10-17` date or a `2024-10-17 12:30` date with time.
+fn date(value: &Value, arguments: &[Value]) -> Result<Value, String> {
+    let format = text(argument(arguments, 0, "format")?)?;
+    let value = text(value)?;
+    let date_time = chrono::NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M"))
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| format!("{value} is not a date"))?;
+    let mut formatted = String::new();
+    write!(formatted, "{}", date_time.format(&format)).map_err(|_| format!("incorrect date format {format}"))?;
+    Ok(Value::from(formatted))
+}
+
+fn upper(value: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::from(text(value)?.to_uppercase()))
+}
+
+fn lower(value: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::from(text(value)?.to_lowercase()))
+}
+
+/// `truncate:100` or `truncate:100, "..."`, cuts text longer than the given number of characters
+/// and ends it with `…` or the second argument.
+fn truncate(value: &Value, arguments: &[Value]) -> Result<Value, String> {
+    let length = match argument(arguments, 0, "length")? {
+        Value::Number(length) if *length >= 0.0 => *length as usize,
+        length => return Err(format!("length has to be a number, got {length:?}")),
+    };
+    let ending = match arguments.get(1) {
+        Some(ending) => text(ending)?,
+        None => "…".to_string(),
+    };
+    let value = text(value)?;
+    match value.char_indices().nth(length) {
+        Some((end, _)) => Ok(Value::from(value[..end].trim_end().to_string() + &ending)),
+        None => Ok(Value::from(value)),
+    }
+}
+
+/// Percent-encodes everything but letters, digits and `-._~`, so the text can be a part of a URL.
+fn urlencode(value: &Value, _: &[Value]) -> Result<Value, String> {
+    let mut encoded = String::new();
+    for byte in text(value)?.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            write!(encoded, "%{byte:02X}").unwrap();
+        }
+    }
+    Ok(Value::from(encoded))
+}
+
+/// Writes the value as JSON. It is escaped like any other value, and has no `&`, `<` or `>`, so
+/// it is unchanged between tags, e.g. inside `<script>`, and gets its quotes escaped in attributes.
+fn json(value: &Value, _: &[Value]) -> Result<Value, String> {
+    let mut json = String::new();
+    write_json(value, &mut json);
+    Ok(Value::String(json))
+}
+
+fn write_json(value: &Value, json: &mut String) {
+    match value {
+        Value::String(text) | Value::Safe(text) => write_json_string(text, json),
+        Value::Number(number) if number.is_finite() => write!(json, "{number}").unwrap(),
+        Value::Number(_) => json.push_str("null"),
+        Value::Bool(bool) => write!(json, "{bool}").unwrap(),
+        Value::List(values) => {
+            json.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_json(value, json);
+            }
+            json.push(']');
+        }
+        Value::Map(map) => {
+            // sorted, so pages don't change between builds
+            let mut fields = map.iter().collect::<Vec<_>>();
+            fields.sort_by_key(|(name, _)| *name);
+            json.push('{');
+            for (i, (name, value)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_json_string(name, json);
+                json.push(':');
+                write_json(value, json);
+            }
+            json.push('}');
+        }
+    }
+}
+
+fn write_json_string(text: &str, json: &mut String) {
+    json.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            // `</script>` and `<!--` can't end the script early
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => write!(json, "\\u{:04x}", char as u32).unwrap(),
+            char if char.is_control() => write!(json, "\\u{:04x}", char as u32).unwrap(),
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+}
+
+/// Marks the value as HTML that is inserted without escaping.
+fn safe(value: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(Value::Safe(text(value)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date, json, truncate, urlencode};
+    use crate::Value;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_date() {
+        let format = [Value::from("%d %b %Y")];
+        assert_eq!(
+            Ok(Value::from("17 Oct 2026")),
+            date(&Value::from("2026-10-17"), &format)
+        );
+        assert_eq!(
+            Ok(Value::from("17 Oct 2026")),
+            date(&Value::from("2026-10-17 12:30"), &format)
+        );
+        assert!(date(&Value::from("yesterday"), &format).is_err());
+        assert!(date(&Value::from("2026-10-17"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let summary = Value::from("Zażółć gęślą jaźń");
+        assert_eq!(Ok(Value::from("Zażółć…")), truncate(&summary, &[Value::from(7_usize)]));
+        assert_eq!(
+            Ok(Value::from("Zażółć gęślą...")),
+            truncate(&summary, &[Value::from(13_usize), Value::from("...")])
+        );
+        assert_eq!(Ok(summary.clone()), truncate(&summary, &[Value::from(17_usize)]));
+        assert!(truncate(&summary, &[Value::from("7")]).is_err());
+    }
+
+    #[test]
+    fn test_urlencode() {
+        assert_eq!(
+            Ok(Value::from("a%20b%2Fc%3F~%C5%BC")),
+            urlencode(&Value::from("a b/c?~ż"), &[])
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let value = Value::from(HashMap::from([
+            ("title".to_string(), Value::from("</script> \"quoted\"")),
+            ("tags".to_string(), Value::from(vec!["a", "b"])),
+            ("words".to_string(), Value::from(120_usize)),
+            ("draft".to_string(), Value::from(false)),
+        ]));
+        assert_eq!(
+            Ok(Value::String(
+                r#"{"draft":false,"tags":["a","b"],"title":"\u003c/script\u003e \"quoted\"","words":120}"#.to_string()
+            )),
+            json(&value, &[])
+        );
+    }
+}
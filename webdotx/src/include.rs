@@ -0,0 +1,245 @@
+use crate::template::{template_name, Node, Position, Template};
+use crate::{TemplateError, TemplateErrorKind};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Fills every `{{ include }}` with the nodes of the included template, which can include other
+/// templates in turn. Includes of missing templates render nothing, templates including each other
+/// in a loop are left as they are, and returned errors name them.
+pub(crate) fn resolve_includes(templates: &mut HashMap<String, Template>, templates_path: &Path) -> Vec<TemplateError> {
+    let mut names = templates.keys().cloned().collect::<Vec<_>>();
+    // the same error is reported on every run
+    names.sort();
+
+    let mut includes = Includes {
+        templates,
+        templates_path,
+        resolved: HashMap::new(),
+        chain: vec![],
+        dependencies: HashMap::new(),
+        errors: vec![],
+        reported: HashSet::new(),
+    };
+    for name in &names {
+        if let Err(error) = includes.resolve(name) {
+            includes.chain.clear();
+            // a loop is reported once for every template in it, when the template is resolved
+            if error.template == *name {
+                includes.errors.push(error);
+            }
+        }
+    }
+    let Includes {
+        resolved,
+        dependencies,
+        errors,
+        ..
+    } = includes;
+    for (name, nodes) in resolved {
+        templates.get_mut(&name).unwrap().nodes = nodes;
+    }
+    for (name, included) in dependencies {
+        templates.get_mut(&name).unwrap().dependencies.extend(included);
+    }
+    errors
+}
+
+struct Includes<'t> {
+    templates: &'t HashMap<String, Template>,
+    templates_path: &'t Path,
+    resolved: HashMap<String, Vec<Node>>,
+    /// Templates included by the ones before them, from the first one resolved.
+    chain: Vec<String>,
+    /// Templates included by each template, also by the blocks it inherits.
+    dependencies: HashMap<String, Vec<String>>,
+    errors: Vec<TemplateError>,
+    /// Includes of missing templates already in `errors`, as templates extending the one they are
+    /// written in have them too.
+    reported: HashSet<(String, usize, usize)>,
+}
+
+impl Includes<'_> {
+    /// Returns the nodes of the template with its includes filled.
+    fn resolve(&mut self, name: &str) -> Result<Vec<Node>, TemplateError> {
+        if let Some(nodes) = self.resolved.get(name) {
+            return Ok(nodes.clone());
+        }
+        if let Some(start) = self.chain.iter().position(|including| including == name) {
+            let mut cycle = self.chain[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(TemplateError {
+                template: name.to_string(),
+                kind: TemplateErrorKind::IncludeCycle(cycle),
+            });
+        }
+        self.chain.push(name.to_string());
+        let nodes = self.include_all(&self.templates[name].nodes, name)?;
+        self.chain.pop();
+        self.resolved.insert(name.to_string(), nodes.clone());
+        Ok(nodes)
+    }
+
+    /// Copies the nodes of the template called `name`, with included templates filled in.
+    fn include_all(&mut self, nodes: &[Node], name: &str) -> Result<Vec<Node>, TemplateError> {
+        let mut included_nodes = vec![];
+        for node in nodes {
+            let node = match node {
+                Node::Include {
+                    template,
+                    overrides,
+                    nodes: _,
+                    position,
+                } => {
+                    let included = template_name(self.templates_path, template);
+                    let nodes = if self.templates.contains_key(&included) {
+                        let nodes = self.resolve(&included)?;
+                        self.dependencies.entry(name.to_string()).or_default().push(included);
+                        nodes
+                    } else {
+                        self.unknown_include(template, position, name);
+                        vec![]
+                    };
+                    Node::Include {
+                        template: template.clone(),
+                        overrides: overrides.clone(),
+                        nodes,
+                        position: position.clone(),
+                    }
+                }
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => Node::If {
+                    condition: condition.clone(),
+                    then: self.include_all(then, name)?,
+                    otherwise: self.include_all(otherwise, name)?,
+                },
+                Node::For {
+                    variable,
+                    list,
+                    body,
+                    otherwise,
+                    position,
+                } => Node::For {
+                    variable: variable.clone(),
+                    list: list.clone(),
+                    body: self.include_all(body, name)?,
+                    otherwise: self.include_all(otherwise, name)?,
+                    position: position.clone(),
+                },
+                Node::Block { name: block, body } => Node::Block {
+                    name: block.clone(),
+                    body: self.include_all(body, name)?,
+                },
+                node => node.clone(),
+            };
+            included_nodes.push(node);
+        }
+        Ok(included_nodes)
+    }
+
+    /// Records an include of a missing template, under the template it is written in.
+    fn unknown_include(&mut self, included: &str, position: &Position, name: &str) {
+        let template = match position.template.as_str() {
+            "" => name,
+            template => template,
+        };
+        if self
+            .reported
+            .insert((template.to_string(), position.line, position.column))
+        {
+            self.errors.push(TemplateError {
+                template: template.to_string(),
+                kind: TemplateErrorKind::UnknownInclude {
+                    name: included.to_string(),
+                    line: position.line,
+                    column: position.column,
+                },
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_includes;
+    use crate::{Registry, Template, TemplateErrorKind};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn templates(contents: &[(&str, &str)]) -> HashMap<String, Template> {
+        contents
+            .iter()
+            .map(|(name, content)| (format!("templates/{name}"), Template::new(content)))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_includes() {
+        let mut templates = templates(&[
+            (
+                "page.html",
+                "{{ include \"partials/nav.html\" }}|{{ for page in $pages$ }}{{ include \"partials/nav.html\" with title=$page$ active=\"no\" }}{{ end }}",
+            ),
+            (
+                "partials/nav.html",
+                "{{ include \"partials/link.html\" }}{{ if $active$ == \"yes\" }}*{{ end }}",
+            ),
+            ("partials/link.html", "<a>{{ $title$ }}</a>"),
+        ]);
+        assert!(resolve_includes(&mut templates, Path::new("templates")).is_empty());
+
+        let mut context = crate::Context::from(HashMap::from([
+            ("title".to_string(), "Home".to_string()),
+            ("active".to_string(), "yes".to_string()),
+        ]));
+        context.insert("pages", vec!["One", "Two"]);
+        assert_eq!(
+            "<a>Home</a>*|<a>One</a><a>Two</a>",
+            templates["templates/page.html"]
+                .fill_template(context, &Registry::new())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let mut unknown = templates(&[
+            (
+                "page.html",
+                "{{ if $a$ }}{{ include \"missing.html\" }}{{ end }}{{ include \"nav.html\" }}",
+            ),
+            ("nav.html", "<nav></nav>"),
+        ]);
+        let errors = resolve_includes(&mut unknown, Path::new("templates"));
+        assert_eq!(1, errors.len());
+        assert_eq!("templates/page.html", errors[0].template);
+        assert!(matches!(
+            &errors[0].kind,
+            TemplateErrorKind::UnknownInclude { name, line: 1, column: 13 } if name == "missing.html"
+        ));
+        assert_eq!(
+            "<nav></nav>",
+            unknown["templates/page.html"]
+                .fill_template(HashMap::<String, String>::new(), &Registry::new())
+                .unwrap()
+        );
+
+        let mut cycle = templates(&[
+            ("a.html", "{{ include \"b.html\" }}"),
+            ("b.html", "{{ include \"a.html\" }}"),
+        ]);
+        let errors = resolve_includes(&mut cycle, Path::new("templates"))
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "templates/a.html: templates include each other: templates/a.html -> templates/b.html -> templates/a.html",
+                "templates/b.html: templates include each other: templates/b.html -> templates/a.html -> templates/b.html",
+            ],
+            errors
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::template::{template_name, Node, Template};
+use crate::{TemplateError, TemplateErrorKind};
+use std::{collections::HashMap, path::Path};
+
+/// Replaces the nodes of every template that extends another one with the nodes of its parent,
+/// where blocks are filled with the template's own blocks. Templates extending a missing template,
+/// or each other in a loop, are left as they are, and returned errors name them.
+pub(crate) fn resolve_inheritance(
+    templates: &mut HashMap<String, Template>,
+    templates_path: &Path,
+) -> Vec<TemplateError> {
+    let mut children = templates
+        .iter()
+        .filter(|(_, template)| template.parent.is_some())
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    // the same error is reported on every run
+    children.sort();
+
+    let mut resolved = HashMap::new();
+    let mut errors = vec![];
+    for name in &children {
+        match resolve(name, templates, templates_path, &mut resolved, &mut vec![]) {
+            // an error of an ancestor is reported when the ancestor itself is resolved
+            Err(error) if error.template == *name => errors.push(error),
+            Ok(_) | Err(_) => {}
+        }
+    }
+    for (name, nodes) in resolved {
+        templates.get_mut(&name).unwrap().nodes = nodes;
+    }
+    for name in &children {
+        let template = templates.get_mut(name).unwrap();
+        let (parent, _) = template.parent.as_ref().unwrap();
+        template.dependencies.push(template_name(templates_path, parent));
+    }
+    errors
+}
+
+/// Returns the nodes of the template merged with its ancestors. `chain` holds the templates
+/// extended by the ones before them, from the first one resolved.
+fn resolve(
+    name: &str,
+    templates: &HashMap<String, Template>,
+    templates_path: &Path,
+    resolved: &mut HashMap<String, Vec<Node>>,
+    chain: &mut Vec<String>,
+) -> Result<Vec<Node>, TemplateError> {
+    if let Some(nodes) = resolved.get(name) {
+        return Ok(nodes.clone());
+    }
+    if let Some(start) = chain.iter().position(|ancestor| ancestor == name) {
+        let mut cycle = chain[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(TemplateError {
+            template: name.to_string(),
+            kind: TemplateErrorKind::InheritanceCycle(cycle),
+        });
+    }
+    let template = &templates[name];
+    let Some((parent, position)) = &template.parent else {
+        return Ok(template.nodes.clone());
+    };
+    let parent_name = template_name(templates_path, parent);
+    if !templates.contains_key(&parent_name) {
+        return Err(TemplateError {
+            template: name.to_string(),
+            kind: TemplateErrorKind::UnknownParent {
+                name: parent.clone(),
+                line: position.line,
+                column: position.column,
+            },
+        });
+    }
+
+    chain.push(name.to_string());
+    let parent_nodes = resolve(&parent_name, templates, templates_path, resolved, chain)?;
+    chain.pop();
+
+    let mut blocks = HashMap::new();
+    collect_blocks(&template.nodes, &mut blocks);
+    let nodes = fill_blocks(&parent_nodes, &blocks);
+    resolved.insert(name.to_string(), nodes.clone());
+    Ok(nodes)
+}
+
+/// Collects blocks of a template, including ones nested in other nodes.
+fn collect_blocks<'n>(nodes: &'n [Node], blocks: &mut HashMap<&'n str, &'n [Node]>) {
+    for node in nodes {
+        match node {
+            Node::Block { name, body } => {
+                blocks.insert(name, body);
+                collect_blocks(body, blocks);
+            }
+            Node::If {
+                condition: _,
+                then,
+                otherwise,
+            } => {
+                collect_blocks(then, blocks);
+                collect_blocks(otherwise, blocks);
+            }
+            Node::For {
+                variable: _,
+                list: _,
+                body,
+                otherwise,
+                position: _,
+            } => {
+                collect_blocks(body, blocks);
+                collect_blocks(otherwise, blocks);
+            }
+            // includes are resolved after inheritance
+            Node::Text(_) | Node::Placeholder(_) | Node::Extends { .. } | Node::Include { .. } => {}
+        }
+    }
+}
+
+/// Copies the parent's nodes, replacing bodies of its blocks with the child's blocks. Blocks are
+/// kept, so templates extending the child can replace them again.
+fn fill_blocks(nodes: &[Node], blocks: &HashMap<&str, &[Node]>) -> Vec<Node> {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Block { name, body } => Node::Block {
+                name: name.clone(),
+                body: match blocks.get(name.as_str()) {
+                    // blocks nested in the child's block are already the child's own
+                    Some(body) => body.to_vec(),
+                    None => fill_blocks(body, blocks),
+                },
+            },
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => Node::If {
+                condition: condition.clone(),
+                then: fill_blocks(then, blocks),
+                otherwise: fill_blocks(otherwise, blocks),
+            },
+            Node::For {
+                variable,
+                list,
+                body,
+                otherwise,
+                position,
+            } => Node::For {
+                variable: variable.clone(),
+                list: list.clone(),
+                body: fill_blocks(body, blocks),
+                otherwise: fill_blocks(otherwise, blocks),
+                position: position.clone(),
+            },
+            node => node.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_inheritance;
+    use crate::{Registry, Template, TemplateErrorKind};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn templates(contents: &[(&str, &str)]) -> HashMap<String, Template> {
+        contents
+            .iter()
+            .map(|(name, content)| (format!("templates/{name}"), Template::new(content)))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_inheritance() {
+        let mut templates = templates(&[
+            (
+                "base.html",
+                "<title>{{ block title }}Site{{ end }}</title>{{ block main }}<main>{{ block content }}{{ end }}</main>{{ end }}",
+            ),
+            (
+                "page.html",
+                "{{ extends \"base.html\" }}ignored{{ block content }}{{ $content$ }}{{ end }}",
+            ),
+            (
+                "article.html",
+                "{{ extends \"page.html\" }}{{ block title }}{{ $title$ }} - Site{{ end }}",
+            ),
+        ]);
+        assert!(resolve_inheritance(&mut templates, Path::new("templates")).is_empty());
+
+        let filled_placeholders = HashMap::from([
+            ("title".to_string(), "Hello".to_string()),
+            ("content".to_string(), "text".to_string()),
+        ]);
+        let render = |name: &str| {
+            templates[name]
+                .fill_template(filled_placeholders.clone(), &Registry::new())
+                .unwrap()
+        };
+        assert_eq!("<title>Site</title><main></main>", render("templates/base.html"));
+        assert_eq!("<title>Site</title><main>text</main>", render("templates/page.html"));
+        assert_eq!(
+            "<title>Hello - Site</title><main>text</main>",
+            render("templates/article.html")
+        );
+    }
+
+    #[test]
+    fn test_inheritance_errors() {
+        let mut unknown = templates(&[
+            ("page.html", "\n  {{ extends \"missing.html\" }}"),
+            ("article.html", "{{ extends \"page.html\" }}"),
+        ]);
+        let errors = resolve_inheritance(&mut unknown, Path::new("templates"));
+        assert_eq!(1, errors.len());
+        assert_eq!("templates/page.html", errors[0].template);
+        assert!(matches!(
+            &errors[0].kind,
+            TemplateErrorKind::UnknownParent { name, line: 2, column: 3 } if name == "missing.html"
+        ));
+
+        let mut cycle = templates(&[
+            ("a.html", "{{ extends \"b.html\" }}"),
+            ("b.html", "{{ extends \"a.html\" }}"),
+        ]);
+        let errors = resolve_inheritance(&mut cycle, Path::new("templates"))
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "templates/a.html: templates extend each other: templates/a.html -> templates/b.html -> templates/a.html",
+                "templates/b.html: templates extend each other: templates/b.html -> templates/a.html -> templates/b.html",
+            ],
+            errors
+        );
+    }
+}
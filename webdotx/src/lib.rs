@@ -1,4 +1,19 @@
+mod check;
+mod error;
+mod escape;
+mod filters;
+mod include;
+mod inheritance;
+mod registry;
 mod template;
 pub mod utils;
+mod value;
 
-pub use template::{load_template, load_templates, render, FuncMap, Renderable, Template};
+pub use check::{check, Check, Finding, FindingKind};
+pub use error::{RenderError, RenderErrorKind, TemplateError, TemplateErrorKind};
+pub use filters::Filter;
+pub use registry::{Arguments, Function, Registry};
+pub use template::{
+    load_template, load_templates, load_templates_with_errors, render, RenderContext, Renderable, Template,
+};
+pub use value::{Context, Value};
@@ -0,0 +1,93 @@
+use crate::filters::BUILTIN_FILTERS;
+use crate::{Filter, RenderContext, Value};
+use chrono::Datelike;
+use std::collections::HashMap;
+
+/// Arguments of an autofill call, e.g. `active` in `{{ %navbar active="articles"% }}`.
+pub type Arguments = HashMap<String, Value>;
+
+/// Autofill function, gets the values of the template being filled and the arguments of the call,
+/// and returns HTML put in place of the call, or a message explaining why it can't.
+pub type Function = dyn Fn(&RenderContext, &Arguments) -> Result<String, String> + Send + Sync;
+
+/// Functions and filters available to templates, starts with the built-in ones.
+///
+/// Example use:
+/// ```rust
+/// use std::collections::HashMap;
+/// use webdotx::{Registry, Template, Value};
+///
+/// fn reverse(value: &Value, _: &[Value]) -> Result<Value, String> {
+///     match value {
+///         Value::String(text) => Ok(Value::from(text.chars().rev().collect::<String>())),
+///         _ => Err("expected text".to_string()),
+///     }
+/// }
+///
+/// let mut registry = Registry::new();
+/// registry.register("greeting", |context, arguments| {
+///     let name = context.get("name").and_then(|name| name.as_text()).ok_or("missing name")?;
+///     let greeting = arguments.get("greeting").and_then(|greeting| greeting.as_text());
+///     Ok(format!("{}, {name}!", greeting.as_deref().unwrap_or("Hello")))
+/// });
+/// registry.register_filter("reverse", reverse);
+///
+/// let template = Template::new(r#"{{ %greeting greeting="Hi"% }} {{ $name | reverse | upper $ }}"#);
+/// let filled_placeholders = HashMap::from([("name".to_string(), "webdotx".to_string())]);
+/// assert_eq!("Hi, webdotx! XTODBEW", template.fill_template(filled_placeholders, &registry).unwrap());
+/// ```
+pub struct Registry {
+    functions: HashMap<String, Box<Function>>,
+    filters: HashMap<String, Filter>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        let mut registry = Registry {
+            functions: HashMap::new(),
+            filters: BUILTIN_FILTERS
+                .iter()
+                .map(|(name, filter)| (name.to_string(), *filter))
+                .collect(),
+        };
+        registry.register("hello", |_, _| Ok("hello".to_string()));
+        registry.register("curr_year", |_, _| Ok(chrono::Local::now().year().to_string()));
+        registry
+    }
+
+    /// Makes a function callable as `{{ %name% }}`, replacing a function with the same name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        function: impl Fn(&RenderContext, &Arguments) -> Result<String, String> + Send + Sync + 'static,
+    ) {
+        self.functions.insert(name.to_string(), Box::new(function));
+    }
+
+    /// Makes a filter usable as `{{ $value | name $ }}`, replacing a filter with the same name.
+    pub fn register_filter(&mut self, name: &str, filter: Filter) {
+        self.filters.insert(name.to_string(), filter);
+    }
+
+    pub(crate) fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name).map(Box::as_ref)
+    }
+
+    pub(crate) fn filter(&self, name: &str) -> Option<Filter> {
+        self.filters.get(name).copied()
+    }
+
+    pub(crate) fn function_names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+
+    pub(crate) fn filter_names(&self) -> impl Iterator<Item = &str> {
+        self.filters.keys().map(String::as_str)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
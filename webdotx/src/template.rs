@@ -1,9 +1,39 @@
-use crate::utils::load_files_in_dir_to_string;
+use crate::{
+    error::suggest,
+    escape::{escape, Escape, HtmlContext},
+    include::resolve_includes,
+    inheritance::resolve_inheritance,
+    utils::load_files_in_dir_to_string,
+    Context, Registry, RenderError, RenderErrorKind, TemplateError, TemplateErrorKind, Value,
+};
 use std::{collections::HashMap, path::Path};
 
-/// Fundamental data structure of webdotx, holds template content and parsed placeholders.
+/// Fundamental data structure of webdotx, holds template content parsed into a tree of nodes.
 /// Placeholder always starts with `{{ $`, and ends with `$ }}`, name of the placeholder is between
-/// `$`. Example template:
+/// `$`. Autofill placeholders use `%` instead of `$`, and are filled by calling the function of
+/// that name from the `Registry`, with the arguments of the call, e.g.
+/// `{{ %navbar active="articles" page=$title$% }}`. Parts of the template can be rendered
+/// conditionally with `{{ if condition }}`, `{{ else if condition }}`, `{{ else }}` and
+/// `{{ end }}`, and repeated for every item of a list with `{{ for item in $list$ }}`, `{{ else }}`
+/// and `{{ end }}`, where the `else` part is used for an empty list.
+///
+/// A template can start with `{{ extends "base.html" }}` to reuse another template, replacing only
+/// its `{{ block name }}…{{ end }}` sections with its own blocks of the same name. The parent's
+/// name is relative to the directory passed to `load_templates`, which resolves the inheritance.
+///
+/// `{{ include "partials/navbar.html" }}` renders another template in place, with the same values
+/// as the including template. Values can be added or overridden for the included template with
+/// `{{ include "partials/link.html" with href="/" text=$title$ }}`. Included templates are found
+/// the same way as the parents of extended ones.
+///
+/// Values can be formatted with filters, e.g. `{{ $date | date:"%d %b %Y" | upper $ }}`, each
+/// filter gets the result of the previous one. Built-in filters are `date`, `upper`, `lower`,
+/// `truncate`, `urlencode`, `json` and `safe`, others can be added to the `Registry`.
+///
+/// Values are HTML-escaped, `&`, `<` and `>` between tags, and also quotes inside tags, e.g. in
+/// attribute values. Already rendered HTML is inserted as it is when it is a `Value::Safe`, or when
+/// the placeholder ends with the `safe` filter, e.g. `{{ $content | safe $ }}`. Autofill functions
+/// return HTML, so their results aren't escaped either. Example template:
 ///
 /// ```html
 /// <html>
@@ -14,79 +44,193 @@ use std::{collections::HashMap, path::Path};
 ///
 /// <body>
 ///     <h1>{{ $header$ }}</h1>
+///     {{ if $summary$ }}<p>{{ $summary$ }}</p>{{ end }}
 ///     {{ $content$ }}
+///     <ul>
+///     {{ for tag in $tags$ }}<li>{{ $tag.name$ }}</li>{{ else }}<li>No tags</li>{{ end }}
+///     </ul>
 /// </body>
 ///
 /// </html>
 /// ```
 ///
-/// For that template, `Template` struct internally would look like this:
-/// ```rust ignore
-/// let template = Template {
-///     content: "<html>\n\n<head>\n    <title>{{ $title$ }}</title>\n</head>\n\n<body>\n    <h1>{{ $header$ }}</h1>\n    {{ $content$ }}\n</body>\n\n</html>\n".to_string(),
-///     placeholders: [
-///         Placeholder {
-///             name: "title",
-///             position: Position {
-///                 from: 26,
-///                 to: 38,
-///             },
-///             is_autofill: false,
-///         },
-///         Placeholder {
-///             name: "header",
-///             position: Position {
-///                 from: 33,
-///                 to: 46,
-///             },
-///             is_autofill: false,
-///         },
-///         Placeholder {
-///             name: "content",
-///             position: Position {
-///                 from: 10,
-///                 to: 24,
-///             },
-///             is_autofill: false,
-///         },
-///     ],
-/// };
-/// ```
+/// Conditions can be:
+/// - `$name$`, true if the value is present, not empty and not `false`,
+/// - `defined $name$`, true if the value is present, even if it is empty,
+/// - `$name$ == "text"` and `$name$ != "text"`, comparing the value with a quoted string or with
+///   another `$placeholder$`,
+/// - any of the above preceded by `not`.
 ///
-/// Notice that `position.from` is the index of the first character of the placeholder counting from
-/// the beginning of the template content string if it's the first placeholder, or from the end of
-/// the previous placeholder if it's not the first one.
+/// Inside a loop `$loop.index$` is the index of the item counting from 0, and `$loop.first$` and
+/// `$loop.last$` are `true` for the first and the last item.
 #[derive(Debug)]
 pub struct Template {
-    content: String,
-    placeholders: Vec<Placeholder>,
+    pub(crate) nodes: Vec<Node>,
+    /// Name of the template from `{{ extends "name" }}`, as written in the template, with where
+    /// the tag is.
+    pub(crate) parent: Option<(String, Position)>,
+    /// Names of the templates this one extends or includes, filled in by `load_templates`.
+    pub(crate) dependencies: Vec<String>,
+    /// Tags that look like placeholders but aren't, e.g. `{{ $name }}`, reported by `check`.
+    pub(crate) unbalanced: Vec<Position>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    /// Text copied to the output as it is.
+    Text(String),
+    Placeholder(Placeholder),
+    /// `{{ if condition }}then{{ else }}otherwise{{ end }}`, `otherwise` is empty if there is no
+    /// `else`. `else if` is an `If` as the only node of `otherwise`.
+    If {
+        condition: Condition,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    /// `{{ for variable in $list$ }}body{{ else }}otherwise{{ end }}`, `otherwise` is rendered for
+    /// an empty list.
+    For {
+        variable: String,
+        list: String,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+        position: Position,
+    },
+    /// `{{ block name }}body{{ end }}`, the body can be replaced by a template extending this one.
+    Block {
+        name: String,
+        body: Vec<Node>,
+    },
+    /// `{{ extends "name" }}`, renders nothing.
+    Extends {
+        parent: String,
+        position: Position,
+    },
+    /// `{{ include "name" with key=value }}`, `nodes` are filled in by `load_templates`.
+    Include {
+        template: String,
+        overrides: Vec<(String, Operand)>,
+        nodes: Vec<Node>,
+        position: Position,
+    },
 }
 
-#[derive(Debug, PartialEq)]
-struct Placeholder {
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Placeholder {
     /// Name for a normal placeholder is between '$', for an autofill placeholder it is between '%'.
-    name: String,
-    /// Position is relative, first one is always from the beginning of template content, second
-    /// one is from the end of the first placeholder, third one is from the end of the second
-    /// placeholder. This sidesteps calculations of offsets during template rendering, because we
-    /// don't need to change position when a placeholder is filled with a str of different length
-    /// than the placeholder itself.
-    position: Position,
+    /// Names with dots are paths to fields of map values, e.g. `item.title`.
+    pub(crate) name: String,
     /// This flag indicates if this placeholder is autofillable or not, if it is set then we get
     /// the autofill function by the name of the placeholder.
-    is_autofill: bool,
+    pub(crate) is_autofill: bool,
+    /// Arguments of an autofill call, `key=value` pairs after the name.
+    pub(crate) arguments: Vec<(String, Operand)>,
+    /// Filters applied to the value, in order.
+    pub(crate) filters: Vec<FilterCall>,
+    /// How the value is escaped, depends on the HTML around the placeholder.
+    escape: Escape,
+    pub(crate) position: Position,
+}
+
+/// Where a tag starts in the template it is written in, reported in `RenderError`s.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Position {
+    pub(crate) template: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+impl Position {
+    /// Moves the position past the text.
+    fn advance(&mut self, text: &str) {
+        for char in text.chars() {
+            if char == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Error for a tag that can't be parsed.
+    fn malformed(&self, message: String) -> TemplateError {
+        TemplateError {
+            template: self.template.clone(),
+            kind: TemplateErrorKind::Malformed {
+                message,
+                line: self.line,
+                column: self.column,
+            },
+        }
+    }
+
+    fn error(&self, kind: RenderErrorKind) -> RenderError {
+        RenderError {
+            renderable: None,
+            template: self.template.clone(),
+            line: self.line,
+            column: self.column,
+            kind: Box::new(kind),
+        }
+    }
+}
+
+/// `filter:argument, other`, arguments are quoted strings or numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FilterCall {
+    pub(crate) name: String,
+    arguments: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Condition {
+    /// `$name$`, value is present, not empty and not `false`.
+    Truthy(String),
+    /// `defined $name$`, value is present.
+    Defined(String),
+    /// `$name$ == operand`, `!=` is parsed as `Not(Equals(..))`.
+    Equals(String, Operand),
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Operand {
+    /// `"text"`
+    Literal(String),
+    /// `$name$`
+    Placeholder(String),
 }
 
-#[derive(Debug, PartialEq)]
-struct Position {
-    from: usize,
-    to: usize,
+/// Single `{{ ... }}` tag, or the text between tags.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    Placeholder(Placeholder),
+    If(Condition),
+    ElseIf(Condition),
+    For {
+        variable: String,
+        list: String,
+    },
+    Block(String),
+    Extends(String),
+    Include {
+        template: String,
+        overrides: Vec<(String, Operand)>,
+    },
+    Else,
+    End,
 }
 
 /// Loads templates from the provided directory with provided extension. If extension is `None`, then
 /// all files in the directory are loaded.
 ///
-/// Returns a map of template names (paths) to `Template` structs.
+/// Returns a map of template names (paths) to `Template` structs, with templates that extend
+/// other ones already merged with their parents, and included templates in place. Fails if a
+/// template can't be parsed, if it extends or includes a template that doesn't exist, or if
+/// templates extend or include each other in a loop. `load_templates_with_errors` returns all of
+/// these problems instead of the first one.
 ///
 /// Example use:
 /// ```rust
@@ -100,147 +244,787 @@ struct Position {
 pub fn load_templates(
     templates_path: &Path,
     extension: Option<&str>,
-) -> Result<HashMap<String, Template>, std::io::Error> {
-    let template_strings = load_files_in_dir_to_string(templates_path, extension)?;
+) -> Result<HashMap<String, Template>, TemplateError> {
+    let (templates, errors) = load_templates_with_errors(templates_path, extension);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(templates),
+    }
+}
+
+/// Loads templates like `load_templates`, but keeps going after a problem and returns every
+/// problem found, e.g. for `check`. Templates that can't be parsed keep the tags that could be,
+/// without nesting them, so that their autofill functions and includes are still checked, but
+/// their placeholders for values aren't reported. Includes of missing templates
+/// render nothing, and templates extending missing templates, or templates extending or
+/// including each other in a loop, are kept as they are written.
+pub fn load_templates_with_errors(
+    templates_path: &Path,
+    extension: Option<&str>,
+) -> (HashMap<String, Template>, Vec<TemplateError>) {
+    match load_files_in_dir_to_string(templates_path, extension) {
+        Ok(template_strings) => parse_templates(template_strings, templates_path),
+        Err(error) => {
+            let error = TemplateError {
+                template: templates_path.display().to_string(),
+                kind: TemplateErrorKind::Io(error),
+            };
+            (HashMap::new(), vec![error])
+        }
+    }
+}
+
+/// Parses templates and resolves their inheritance and includes, returning the problems found
+/// in the order of template names.
+pub(crate) fn parse_templates(
+    template_strings: impl IntoIterator<Item = (impl AsRef<Path>, String)>,
+    templates_path: &Path,
+) -> (HashMap<String, Template>, Vec<TemplateError>) {
+    let mut template_strings = template_strings
+        .into_iter()
+        .map(|(path, content)| (path.as_ref().to_str().unwrap().to_string(), content))
+        .collect::<Vec<_>>();
+    // the same errors are reported in the same order on every run
+    template_strings.sort();
     let mut templates = HashMap::new();
-    for (template_path, template_content) in template_strings {
-        let mut template = Template {
-            content: template_content.clone(),
-            placeholders: Vec::new(),
-        };
-        let placeholders = parse_placeholders(&template_content);
-        template.placeholders.extend(placeholders);
-        templates.insert(template_path.to_str().unwrap().to_string(), template);
+    let mut errors = vec![];
+    for (name, content) in template_strings {
+        let (template, template_errors) = Template::parse_with_errors(&content, &name);
+        errors.extend(template_errors);
+        templates.insert(name, template);
     }
-    Ok(templates)
+    errors.extend(resolve_inheritance(&mut templates, templates_path));
+    errors.extend(resolve_includes(&mut templates, templates_path));
+    (templates, errors)
+}
+
+/// Name of a template in the map returned by `load_templates`, from a name used in
+/// `{{ extends }}` or `{{ include }}`.
+pub(crate) fn template_name(templates_path: &Path, name: &str) -> String {
+    templates_path.join(name).to_str().unwrap().to_string()
+}
+
+/// Loads a single template, `{{ extends }}` is ignored, so blocks are rendered as they are, and
+/// `{{ include }}` renders nothing.
+pub fn load_template(template_path: &Path) -> Result<Template, TemplateError> {
+    let name = template_path.to_str().unwrap();
+    let template_string = std::fs::read_to_string(template_path).map_err(|error| TemplateError {
+        template: name.to_string(),
+        kind: TemplateErrorKind::Io(error),
+    })?;
+    Template::parse(&template_string, name)
 }
 
-pub fn load_template(template_path: &Path) -> Result<Template, std::io::Error> {
-    let template_string = std::fs::read_to_string(template_path)?;
-    let mut template = Template {
-        content: template_string.clone(),
-        placeholders: Vec::new(),
+/// Parses template content into nodes, `template` is the name used in errors. Malformed tags are
+/// left out and returned as errors. If tags don't nest, e.g. `else` or `end` don't close anything
+/// or an `if` or `for` has no `end`, the error is returned together with nodes of the autofill
+/// placeholders, includes and `extends` of the template, so that they can still be checked.
+fn parse_placeholders(
+    template_content: &str,
+    template: &str,
+    unbalanced: &mut Vec<Position>,
+) -> (Vec<Node>, Vec<TemplateError>) {
+    let mut errors = vec![];
+    let tags = tokenize(template_content, template, unbalanced, &mut errors);
+    let error = match parse_nodes(&mut tags.clone().into_iter()) {
+        Ok((nodes, None)) => return (nodes, errors),
+        Ok((_, Some((token, position)))) => unexpected(&token, &position),
+        Err(error) => error,
     };
-    let placeholders = parse_placeholders(&template_string);
-    template.placeholders.extend(placeholders);
-    Ok(template)
+    errors.push(error);
+    let nodes = tags
+        .into_iter()
+        .filter_map(|(token, position)| match token {
+            Token::Placeholder(placeholder) if placeholder.is_autofill => Some(Node::Placeholder(placeholder)),
+            Token::Extends(parent) => Some(Node::Extends { parent, position }),
+            Token::Include { template, overrides } => Some(Node::Include {
+                template,
+                overrides,
+                nodes: vec![],
+                position,
+            }),
+            _ => None,
+        })
+        .collect();
+    (nodes, errors)
 }
 
-fn parse_placeholders(template_content: &str) -> Vec<Placeholder> {
-    let mut placeholders = vec![];
-    let mut content = template_content;
+/// Token with where it starts in the template.
+type Tag = (Token, Position);
+type Tokens = std::vec::IntoIter<Tag>;
 
-    while !content.is_empty() {
-        let ((placeholder_start, placeholder_end), is_autofill) = find_first_placeholder(content);
-        let (Some(placeholder_start), Some(placeholder_end)) = (placeholder_start, placeholder_end) else {
-            return placeholders;
+/// Parses nodes until `else`, `else if` or `end`, which is returned along with the nodes, or until
+/// the end of the template.
+fn parse_nodes(tokens: &mut Tokens) -> Result<(Vec<Node>, Option<Tag>), TemplateError> {
+    let mut nodes = vec![];
+    while let Some((token, position)) = tokens.next() {
+        let node = match token {
+            Token::Text(text) => Node::Text(text),
+            Token::Placeholder(placeholder) => Node::Placeholder(placeholder),
+            Token::If(condition) => parse_if(condition, position, tokens)?,
+            Token::For { variable, list } => parse_for(variable, list, position, tokens)?,
+            Token::Block(name) => match parse_nodes(tokens)? {
+                (body, Some((Token::End, _))) => Node::Block { name, body },
+                (_, Some((token, position))) => return Err(unexpected(&token, &position)),
+                (_, None) => return Err(unclosed("block", &position)),
+            },
+            Token::Extends(parent) => Node::Extends { parent, position },
+            Token::Include { template, overrides } => Node::Include {
+                template,
+                overrides,
+                nodes: vec![],
+                position,
+            },
+            terminator => return Ok((nodes, Some((terminator, position)))),
         };
+        nodes.push(node);
+    }
+    Ok((nodes, None))
+}
+
+fn parse_for(variable: String, list: String, position: Position, tokens: &mut Tokens) -> Result<Node, TemplateError> {
+    let (body, terminator) = parse_nodes(tokens)?;
+    let otherwise = match terminator {
+        Some((Token::End, _)) => vec![],
+        Some((Token::Else, _)) => match parse_nodes(tokens)? {
+            (otherwise, Some((Token::End, _))) => otherwise,
+            (_, Some((token, position))) => return Err(unexpected(&token, &position)),
+            (_, None) => return Err(unclosed("for", &position)),
+        },
+        Some((token, position)) => return Err(unexpected(&token, &position)),
+        None => return Err(unclosed("for", &position)),
+    };
+    Ok(Node::For {
+        variable,
+        list,
+        body,
+        otherwise,
+        position,
+    })
+}
+
+fn parse_if(condition: Condition, position: Position, tokens: &mut Tokens) -> Result<Node, TemplateError> {
+    let (then, terminator) = parse_nodes(tokens)?;
+    let otherwise = match terminator {
+        Some((Token::End, _)) => vec![],
+        Some((Token::ElseIf(condition), position)) => vec![parse_if(condition, position, tokens)?],
+        Some((Token::Else, _)) => match parse_nodes(tokens)? {
+            (otherwise, Some((Token::End, _))) => otherwise,
+            (_, Some((token, position))) => return Err(unexpected(&token, &position)),
+            (_, None) => return Err(unclosed("if", &position)),
+        },
+        Some((token, position)) => return Err(unexpected(&token, &position)),
+        None => return Err(unclosed("if", &position)),
+    };
+    Ok(Node::If {
+        condition,
+        then,
+        otherwise,
+    })
+}
+
+fn unclosed(tag: &str, position: &Position) -> TemplateError {
+    TemplateError {
+        template: position.template.clone(),
+        kind: TemplateErrorKind::Unclosed {
+            tag: tag.to_string(),
+            line: position.line,
+            column: position.column,
+        },
+    }
+}
 
-        let name = content[placeholder_start + 4..placeholder_end].to_string();
-        let position = Position {
-            from: placeholder_start,
-            to: placeholder_end + 3,
+/// Error for an `else`, `else if` or `end` token found where it doesn't close anything.
+fn unexpected(token: &Token, position: &Position) -> TemplateError {
+    let tag = match token {
+        Token::Else => "else",
+        Token::ElseIf(_) => "else if",
+        _ => "end",
+    };
+    TemplateError {
+        template: position.template.clone(),
+        kind: TemplateErrorKind::UnexpectedEnd {
+            tag: tag.to_string(),
+            line: position.line,
+            column: position.column,
+        },
+    }
+}
+
+/// Splits template content into tags and text between them. A tag starts with `{{ ` and ends with
+/// the first `}}` after it, anything that doesn't look like a known tag is left as text. Positions
+/// of text that looks like a part of a placeholder are added to `unbalanced`. A tag that starts
+/// like a known one but can't be parsed is left out and added to `errors`.
+fn tokenize(
+    content: &str,
+    template: &str,
+    unbalanced: &mut Vec<Position>,
+    errors: &mut Vec<TemplateError>,
+) -> Vec<Tag> {
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let mut content = content;
+    let mut html = HtmlContext::new();
+    let mut position = Position {
+        template: template.to_string(),
+        line: 1,
+        column: 1,
+    };
+    let mut text_start = position.clone();
+
+    while let Some(start) = content.find("{{ ") {
+        let Some(length) = content[start..].find("}}") else {
+            break;
         };
-        content = &content[position.to + 1..];
-        let placeholder = Placeholder {
-            name,
-            position,
-            is_autofill,
+        let tag_end = start + length + 2;
+        find_unbalanced(&content[..start], &position, unbalanced);
+        position.advance(&content[..start]);
+        let tag_position = position.clone();
+        position.advance(&content[start..tag_end]);
+        let token = match parse_tag(content[start + 3..start + length].trim()) {
+            Ok(token) => token,
+            Err(message) => {
+                // the rest of the template is still parsed, to find other problems
+                errors.push(tag_position.malformed(message));
+                html.advance(&content[..tag_end]);
+                text.push_str(&content[..start]);
+                content = &content[tag_end..];
+                continue;
+            }
         };
-        placeholders.push(placeholder);
+        if token.is_none() && !unbalanced_delimiters(&content[start..tag_end]).is_empty() {
+            unbalanced.push(tag_position.clone());
+        }
+        match token {
+            Some(mut token) => {
+                html.advance(&content[..start]);
+                if let Token::Placeholder(placeholder) = &mut token {
+                    placeholder.escape = html.escape();
+                    placeholder.position = tag_position.clone();
+                }
+                text.push_str(&content[..start]);
+                if !text.is_empty() {
+                    tokens.push((Token::Text(std::mem::take(&mut text)), text_start));
+                }
+                tokens.push((token, tag_position));
+                text_start = position.clone();
+            }
+            None => {
+                html.advance(&content[..tag_end]);
+                text.push_str(&content[..tag_end]);
+            }
+        }
+        content = &content[tag_end..];
     }
+    find_unbalanced(content, &position, unbalanced);
+    text.push_str(content);
+    if !text.is_empty() {
+        tokens.push((Token::Text(text), text_start));
+    }
+    tokens
+}
 
-    placeholders
+/// Adds positions of unbalanced delimiters in text starting at `start` to `unbalanced`.
+fn find_unbalanced(text: &str, start: &Position, unbalanced: &mut Vec<Position>) {
+    for offset in unbalanced_delimiters(text) {
+        let mut position = start.clone();
+        position.advance(&text[..offset]);
+        unbalanced.push(position);
+    }
 }
 
-fn find_first_placeholder(content: &str) -> ((Option<usize>, Option<usize>), bool) {
-    let norm_placeholder_delim_start = "{{ $";
-    let norm_placeholder_delim_end = "$ }}";
-    let autofill_placeholder_delim_start = "{{ %";
-    let autofill_placeholder_delim_end = "% }}";
+/// Offsets of halves of placeholder delimiters in text, e.g. `{{ $` without a matching `$ }}`.
+fn unbalanced_delimiters(text: &str) -> Vec<usize> {
+    const DELIMITERS: [&str; 8] = ["{{ $", "{{ %", "{{$", "{{%", "$ }}", "% }}", "$}}", "%}}"];
+    let mut offsets = vec![];
+    let mut rest = text;
+    while let Some((offset, delimiter)) = DELIMITERS
+        .iter()
+        .filter_map(|delimiter| Some((rest.find(delimiter)?, delimiter)))
+        .min()
+    {
+        offsets.push(text.len() - rest.len() + offset);
+        rest = &rest[offset + delimiter.len()..];
+    }
+    offsets
+}
 
-    let norm_placeholder_start = content.find(norm_placeholder_delim_start);
-    let autofill_placeholder_start = content.find(autofill_placeholder_delim_start);
-    match (norm_placeholder_start, autofill_placeholder_start) {
-        (None, None) => ((None, None), false),
-        (None, Some(autofill_placeholder_start)) => (
-            (
-                Some(autofill_placeholder_start),
-                content.find(autofill_placeholder_delim_end),
-            ),
-            true,
-        ),
-        (Some(norm_placeholder_start), None) => (
-            (Some(norm_placeholder_start), content.find(norm_placeholder_delim_end)),
-            false,
-        ),
-        (Some(norm_placeholder_start), Some(autofill_placeholder_start)) => {
-            if norm_placeholder_start < autofill_placeholder_start {
-                (
-                    (Some(norm_placeholder_start), content.find(norm_placeholder_delim_end)),
-                    false,
-                )
-            } else if autofill_placeholder_start < norm_placeholder_start {
-                (
-                    (
-                        Some(autofill_placeholder_start),
-                        content.find(autofill_placeholder_delim_end),
-                    ),
-                    true,
-                )
+/// Parses the inside of a tag, `None` if it isn't a known tag. Fails with a message if it starts
+/// like a known tag but can't be parsed.
+fn parse_tag(tag: &str) -> Result<Option<Token>, String> {
+    if let Some(placeholder) = parse_placeholder(tag, '$')? {
+        return Ok(Some(Token::Placeholder(placeholder)));
+    }
+    if let Some(placeholder) = parse_placeholder(tag, '%')? {
+        return Ok(Some(Token::Placeholder(placeholder)));
+    }
+    let token = match tag {
+        "else" => Token::Else,
+        "end" => Token::End,
+        _ => {
+            if let Some(condition) = tag.strip_prefix("if ") {
+                Token::If(parse_condition(condition)?)
+            } else if let Some(loop_header) = tag.strip_prefix("for ") {
+                parse_for_header(loop_header)?
+            } else if let Some(name) = tag.strip_prefix("block ") {
+                Token::Block(parse_identifier(name, "block name")?)
+            } else if let Some(parent) = tag.strip_prefix("extends ") {
+                Token::Extends(parse_string(parent, "extends")?)
+            } else if let Some(include) = tag.strip_prefix("include ") {
+                parse_include(include)?
+            } else if let Some(condition) = tag.strip_prefix("else if ") {
+                Token::ElseIf(parse_condition(condition)?)
             } else {
-                unreachable!("Should never reach this, because we cannot have different characters at the same index")
+                return Ok(None);
             }
         }
+    };
+    Ok(Some(token))
+}
+
+/// Parses `$name$` or `$name | filter:argument | other $`, and the same with `%` for autofill,
+/// which can also have arguments, e.g. `%navbar active="articles"%`.
+fn parse_placeholder(tag: &str, delimiter: char) -> Result<Option<Placeholder>, String> {
+    let Some(inner) = tag.strip_prefix(delimiter).and_then(|tag| tag.strip_suffix(delimiter)) else {
+        return Ok(None);
+    };
+    let mut parts = split_unquoted(inner, '|').into_iter();
+    let call = parts.next().unwrap_or_default().trim();
+    let (name, arguments) = match call.split_once(char::is_whitespace) {
+        Some((name, arguments)) if delimiter == '%' => (name, parse_arguments(arguments)?),
+        _ => (call, vec![]),
+    };
+    if delimited(&format!("{delimiter}{name}{delimiter}"), delimiter).is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Placeholder {
+        name: name.to_string(),
+        is_autofill: delimiter == '%',
+        arguments,
+        filters: parts.map(parse_filter_call).collect::<Result<_, _>>()?,
+        escape: Escape::Text,
+        position: Position::default(),
+    }))
+}
+
+fn parse_filter_call(filter: &str) -> Result<FilterCall, String> {
+    let (name, arguments) = filter.split_once(':').unwrap_or((filter, ""));
+    let arguments = if arguments.trim().is_empty() {
+        vec![]
+    } else {
+        split_unquoted(arguments, ',')
+            .into_iter()
+            .map(|argument| match argument.trim().parse::<f64>() {
+                Ok(number) => Ok(Value::Number(number)),
+                Err(_) => parse_string(argument, "filter argument").map(Value::from),
+            })
+            .collect::<Result<_, _>>()?
+    };
+    Ok(FilterCall {
+        name: parse_identifier(name, "filter name")?,
+        arguments,
+    })
+}
+
+/// Splits `text` on every `separator` that isn't in a quoted string.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, char) in text.char_indices() {
+        if char == '"' {
+            in_quotes = !in_quotes;
+        } else if char == separator && !in_quotes {
+            parts.push(&text[start..i]);
+            start = i + 1;
+        }
     }
+    parts.push(&text[start..]);
+    parts
 }
 
-// TODO: think if this type would be better of as a wrapper type for a HashMap instead of an alias
-pub type FuncMap = HashMap<&'static str, &'static dyn Fn() -> &'static str>;
+/// Returns the name if the whole `text` is a name between `delimiter`s, e.g. `$title$`.
+fn delimited(text: &str, delimiter: char) -> Option<&str> {
+    let name = text.strip_prefix(delimiter)?.strip_suffix(delimiter)?;
+    let is_name = !name.is_empty() && !name.contains(char::is_whitespace) && !name.contains(['$', '%']);
+    is_name.then_some(name)
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    let condition = condition.trim();
+    if let Some(negated) = condition.strip_prefix("not ") {
+        return Ok(Condition::Not(Box::new(parse_condition(negated)?)));
+    }
+    if let Some(name) = condition.strip_prefix("defined ") {
+        return Ok(Condition::Defined(condition_name(name)?));
+    }
+    for (operator, negated) in [("==", false), ("!=", true)] {
+        let Some((name, operand)) = condition.split_once(operator) else {
+            continue;
+        };
+        let equals = Condition::Equals(condition_name(name)?, parse_operand(operand, "operand in condition")?);
+        return Ok(if negated {
+            Condition::Not(Box::new(equals))
+        } else {
+            equals
+        });
+    }
+    Ok(Condition::Truthy(condition_name(condition)?))
+}
+
+/// Parses a `"quoted string"` or a `$placeholder$`.
+fn parse_operand(operand: &str, what: &str) -> Result<Operand, String> {
+    let operand = operand.trim();
+    match delimited(operand, '$') {
+        Some(name) => Ok(Operand::Placeholder(name.to_string())),
+        None => parse_string(operand, what).map(Operand::Literal),
+    }
+}
+
+/// Parses `"name" with key="value" other=$placeholder$`, where the `with` part is optional.
+fn parse_include(include: &str) -> Result<Token, String> {
+    let include = include.trim();
+    let name_end = include
+        .get(1..)
+        .and_then(|name| name.find('"'))
+        .map_or(include.len(), |end| end + 2);
+    let template = parse_string(&include[..name_end], "include")?;
+    let rest = include[name_end..].trim();
+    let overrides = if rest.is_empty() {
+        vec![]
+    } else {
+        let Some(rest) = rest.strip_prefix("with ") else {
+            return Err(format!("incorrect include, expected `with key=value`: {include}"));
+        };
+        parse_arguments(rest)?
+    };
+    Ok(Token::Include { template, overrides })
+}
+
+/// Parses `key="value" other=$placeholder$`.
+fn parse_arguments(arguments: &str) -> Result<Vec<(String, Operand)>, String> {
+    let mut parsed = vec![];
+    let mut rest = arguments;
+    while !rest.trim().is_empty() {
+        let Some((key, value)) = rest.trim_start().split_once('=') else {
+            return Err(format!("incorrect arguments, expected `key=value`: {arguments}"));
+        };
+        // values are quoted or between `$`, so they end at the second delimiter
+        let delimiter = value.chars().next().unwrap_or('"');
+        let value_end = value
+            .get(delimiter.len_utf8()..)
+            .and_then(|value| value.find(delimiter))
+            .map_or(value.len(), |end| end + 2 * delimiter.len_utf8());
+        parsed.push((
+            parse_identifier(key, "argument name")?,
+            parse_operand(&value[..value_end], "argument value")?,
+        ));
+        rest = &value[value_end..];
+    }
+    Ok(parsed)
+}
+
+/// Parses `item in $list$`.
+fn parse_for_header(loop_header: &str) -> Result<Token, String> {
+    let Some((variable, list)) = loop_header.split_once(" in ") else {
+        return Err(format!("incorrect loop, expected `for item in $list$`: {loop_header}"));
+    };
+    Ok(Token::For {
+        variable: parse_identifier(variable, "loop variable")?,
+        list: condition_name(list)?,
+    })
+}
+
+/// Parses a name of a loop variable or a block, `what` names it in the error message.
+fn parse_identifier(name: &str, what: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains([' ', '.', '$', '%', '"']) {
+        return Err(format!("incorrect {what}: {name}"));
+    }
+    Ok(name.to_string())
+}
+
+/// Parses a `"quoted string"`.
+fn parse_string(string: &str, what: &str) -> Result<String, String> {
+    match string
+        .trim()
+        .strip_prefix('"')
+        .and_then(|string| string.strip_suffix('"'))
+    {
+        Some(string) => Ok(string.to_string()),
+        None => Err(format!("incorrect {what}, expected a quoted string: {}", string.trim())),
+    }
+}
+
+fn condition_name(name: &str) -> Result<String, String> {
+    match delimited(name.trim(), '$') {
+        Some(name) => Ok(name.to_string()),
+        None => Err(format!("incorrect placeholder in condition: {}", name.trim())),
+    }
+}
 
 impl Template {
-    /// Fills placeholders in the template with the provided values. If value for a placeholder is
-    /// missing, then it returns an error.
+    /// Creates a template from its content, e.g. one embedded with `include_str!`. Panics if the
+    /// content can't be parsed, templates from files are loaded with `load_templates` instead.
+    pub fn new(content: &str) -> Template {
+        Template::parse(content, "").unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Creates a template from content of the file `path`, which is named in errors.
+    pub(crate) fn parse(content: &str, path: &str) -> Result<Template, TemplateError> {
+        let (template, errors) = Template::parse_with_errors(content, path);
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(template),
+        }
+    }
+
+    /// Creates a template like `parse`, but returns every problem found together with what could
+    /// be parsed, see `parse_placeholders`.
+    pub(crate) fn parse_with_errors(content: &str, path: &str) -> (Template, Vec<TemplateError>) {
+        let mut unbalanced = vec![];
+        let (nodes, errors) = parse_placeholders(content, path, &mut unbalanced);
+        let parent = nodes.iter().find_map(|node| match node {
+            Node::Extends { parent, position } => Some((parent.clone(), position.clone())),
+            _ => None,
+        });
+        let template = Template {
+            nodes,
+            parent,
+            dependencies: vec![],
+            unbalanced,
+        };
+        (template, errors)
+    }
+
+    /// Fills placeholders in the template with the provided values, calling autofill functions
+    /// and filters from the registry. Fails if a value for a placeholder is missing, or a
+    /// function or a filter is unknown or fails, with the position of the tag in the template.
     pub fn fill_template(
         &self,
-        filled_placeholders: HashMap<String, String>,
-        autofill_funcs: &Option<FuncMap>,
-    ) -> String {
-        let mut builtin_autofill_funcs: FuncMap = HashMap::new();
-        builtin_autofill_funcs.insert("hello", &hello);
-        builtin_autofill_funcs.insert("curr_year", &curr_year);
-        if let Some(funcs) = autofill_funcs {
-            builtin_autofill_funcs.extend(funcs);
+        filled_placeholders: impl Into<Context>,
+        registry: &Registry,
+    ) -> Result<String, RenderError> {
+        let context = filled_placeholders.into();
+        let mut scope = RenderContext::new(&context);
+        let mut rendered = String::new();
+        fill_nodes(&self.nodes, &mut scope, registry, &mut rendered)?;
+        Ok(rendered)
+    }
+}
+
+/// Values visible while filling a template, loop variables and include overrides shadow the
+/// filled placeholders. Autofill functions get it to see the values of the page being rendered.
+pub struct RenderContext<'c> {
+    context: &'c Context,
+    locals: Vec<(String, Value)>,
+}
+
+impl RenderContext<'_> {
+    pub(crate) fn new(context: &Context) -> RenderContext<'_> {
+        RenderContext {
+            context,
+            locals: vec![],
         }
+    }
 
-        let mut rendered = String::new();
-        let mut content = self.content.as_str();
-        for placeholder in &self.placeholders {
-            rendered.push_str(&content[..placeholder.position.from]);
-            let name = &placeholder.name;
-            let filled_placeholder = if placeholder.is_autofill {
-                let func = &builtin_autofill_funcs.get(name.as_str()).unwrap();
-                func()
-            } else {
-                filled_placeholders.get(name).unwrap()
-            };
-            rendered.push_str(filled_placeholder);
-            content = &content[placeholder.position.to + 1..];
+    /// Finds the value of a dotted path, e.g. `item.title`.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        let (name, fields) = path.split_once('.').unwrap_or((path, ""));
+        match self.locals.iter().rev().find(|(local, _)| local == name) {
+            Some((_, value)) if fields.is_empty() => Some(value),
+            Some((_, value)) => fields.split('.').try_fold(value, |value, name| value.field(name)),
+            None => self.context.get(path),
+        }
+    }
+
+    /// Finds a path with a value that is close to a missing `path`, e.g. `page.meta.author` for
+    /// `page.meta.autor`.
+    pub(crate) fn suggest(&self, path: &str) -> Option<String> {
+        match path.rsplit_once('.') {
+            None => {
+                let locals = self.locals.iter().map(|(name, _)| name.as_str());
+                suggest(path, locals.chain(self.context.names()))
+            }
+            Some((parent, field)) => match self.get(parent) {
+                Some(Value::Map(map)) => {
+                    suggest(field, map.keys().map(String::as_str)).map(|field| format!("{parent}.{field}"))
+                }
+                Some(_) => None,
+                None => self.suggest(parent).map(|parent| format!("{parent}.{field}")),
+            },
+        }
+    }
+
+    fn missing_value(&self, path: &str, position: &Position) -> RenderError {
+        position.error(RenderErrorKind::MissingValue {
+            placeholder: path.to_string(),
+            suggestion: self.suggest(path),
+        })
+    }
+}
+
+fn fill_nodes(
+    nodes: &[Node],
+    scope: &mut RenderContext,
+    registry: &Registry,
+    rendered: &mut String,
+) -> Result<(), RenderError> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => rendered.push_str(text),
+            Node::Placeholder(placeholder) => {
+                let name = &placeholder.name;
+                let position = &placeholder.position;
+                let autofilled;
+                let value = if placeholder.is_autofill {
+                    let Some(function) = registry.function(name) else {
+                        return Err(position.error(RenderErrorKind::UnknownFunction {
+                            name: name.clone(),
+                            suggestion: suggest(name, registry.function_names()),
+                        }));
+                    };
+                    let arguments = placeholder
+                        .arguments
+                        .iter()
+                        .map(|(key, value)| Ok((key.clone(), operand_value(value, scope, position)?)))
+                        .collect::<Result<_, RenderError>>()?;
+                    autofilled = match function(scope, &arguments) {
+                        Ok(html) => Value::Safe(html),
+                        Err(message) => {
+                            return Err(position.error(RenderErrorKind::FunctionFailed {
+                                name: name.clone(),
+                                message,
+                            }))
+                        }
+                    };
+                    &autofilled
+                } else {
+                    match scope.get(name) {
+                        Some(value) => value,
+                        None => return Err(scope.missing_value(name, position)),
+                    }
+                };
+                let filtered;
+                let value = if placeholder.filters.is_empty() {
+                    value
+                } else {
+                    filtered = apply_filters(value, placeholder, registry)?;
+                    &filtered
+                };
+                match value {
+                    Value::String(value) => rendered.push_str(&escape(value, placeholder.escape)),
+                    value => match value.as_text() {
+                        Some(value) => rendered.push_str(&value),
+                        None => return Err(position.error(RenderErrorKind::NotText(name.clone()))),
+                    },
+                }
+            }
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let branch = if is_met(condition, scope) { then } else { otherwise };
+                fill_nodes(branch, scope, registry, rendered)?;
+            }
+            Node::For {
+                variable,
+                list,
+                body,
+                otherwise,
+                position,
+            } => {
+                let items = match scope.get(list) {
+                    Some(Value::List(items)) => items.clone(),
+                    Some(_) => return Err(position.error(RenderErrorKind::NotAList(list.clone()))),
+                    None => return Err(scope.missing_value(list, position)),
+                };
+                if items.is_empty() {
+                    fill_nodes(otherwise, scope, registry, rendered)?;
+                }
+                let count = items.len();
+                for (index, item) in items.into_iter().enumerate() {
+                    let loop_info = HashMap::from([
+                        ("index".to_string(), Value::from(index)),
+                        ("first".to_string(), Value::from(index == 0)),
+                        ("last".to_string(), Value::from(index + 1 == count)),
+                    ]);
+                    scope.locals.push((variable.clone(), item));
+                    scope.locals.push(("loop".to_string(), Value::Map(loop_info)));
+                    let filled = fill_nodes(body, scope, registry, rendered);
+                    scope.locals.truncate(scope.locals.len() - 2);
+                    filled?;
+                }
+            }
+            Node::Block { name: _, body } => fill_nodes(body, scope, registry, rendered)?,
+            Node::Extends { .. } => {}
+            Node::Include {
+                template: _,
+                overrides,
+                nodes,
+                position,
+            } => {
+                let values = overrides
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), operand_value(value, scope, position)?)))
+                    .collect::<Result<Vec<_>, RenderError>>()?;
+                let locals = scope.locals.len();
+                scope.locals.extend(values);
+                let filled = fill_nodes(nodes, scope, registry, rendered);
+                scope.locals.truncate(locals);
+                filled?;
+            }
         }
-        rendered.push_str(content);
-        rendered
     }
+    Ok(())
+}
+
+fn apply_filters(value: &Value, placeholder: &Placeholder, registry: &Registry) -> Result<Value, RenderError> {
+    let mut value = value.clone();
+    for FilterCall { name, arguments } in &placeholder.filters {
+        let Some(filter) = registry.filter(name) else {
+            return Err(placeholder.position.error(RenderErrorKind::UnknownFilter {
+                placeholder: placeholder.name.clone(),
+                filter: name.clone(),
+                suggestion: suggest(name, registry.filter_names()),
+            }));
+        };
+        value = filter(&value, arguments).map_err(|message| {
+            placeholder.position.error(RenderErrorKind::FilterFailed {
+                placeholder: placeholder.name.clone(),
+                filter: name.clone(),
+                message,
+            })
+        })?;
+    }
+    Ok(value)
 }
 
-fn hello() -> &'static str {
-    "hello"
+/// Value of an include override or a function argument, `position` is the tag's position.
+fn operand_value(operand: &Operand, scope: &RenderContext, position: &Position) -> Result<Value, RenderError> {
+    match operand {
+        Operand::Literal(literal) => Ok(Value::from(literal.as_str())),
+        Operand::Placeholder(name) => match scope.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(scope.missing_value(name, position)),
+        },
+    }
 }
 
-fn curr_year() -> &'static str {
-    let date_now = chrono::Local::now().date_naive().to_string();
-    let (year, _) = date_now.split_once("-").unwrap();
-    let year = Box::from(year);
-    Box::leak(year)
+fn is_met(condition: &Condition, scope: &RenderContext) -> bool {
+    match condition {
+        Condition::Truthy(name) => scope.get(name).is_some_and(Value::is_truthy),
+        Condition::Defined(name) => scope.get(name).is_some(),
+        Condition::Equals(name, operand) => match operand {
+            Operand::Literal(literal) => scope
+                .get(name)
+                .and_then(Value::as_text)
+                .is_some_and(|value| value == literal.as_str()),
+            Operand::Placeholder(other) => scope.get(name) == scope.get(other),
+        },
+        Condition::Not(condition) => !is_met(condition, scope),
+    }
 }
 
 /// Renderable allows for rendering of data structures into a string using provided templates.
@@ -249,7 +1033,7 @@ fn curr_year() -> &'static str {
 ///
 /// ```rust
 /// use std::collections::HashMap;
-/// use webdotx::{Renderable, Template, FuncMap};
+/// use webdotx::{Registry, RenderError, Renderable, Template};
 ///
 /// struct Page {
 ///    name: String,
@@ -260,28 +1044,30 @@ fn curr_year() -> &'static str {
 /// }
 ///
 /// impl Renderable for Page {
-///    fn render(&self, templates: &HashMap<String, Template>, autofill_funcs: &Option<FuncMap>) -> Option<String> {
+///    fn render(&self, templates: &HashMap<String, Template>, registry: &Registry) -> Result<Option<String>, RenderError> {
 ///        let filled_placeholders = HashMap::from([
 ///            ("name".to_string(), self.name.clone()),
 ///            ("content".to_string(), self.content.clone()),
 ///            ("test_offset".to_string(), self.test_offset.clone()),
 ///        ]);
 ///        let template = templates.get(&self.template_name).unwrap();
-///        Some(template.fill_template(filled_placeholders, autofill_funcs))
+///        template.fill_template(filled_placeholders, registry).map(Some)
 ///    }
 /// }
 /// ```
 pub trait Renderable {
-    fn render(&self, templates: &HashMap<String, Template>, autofill_funcs: &Option<FuncMap>) -> Option<String>;
+    /// Renders the data structure, `None` if it shouldn't be rendered, e.g. a hidden page.
+    fn render(&self, templates: &HashMap<String, Template>, registry: &Registry)
+        -> Result<Option<String>, RenderError>;
 }
 
 /// Renders all renderables using provided templates. Returns a map of renderable names to rendered
-/// strings.
+/// strings, or the first error, which names the renderable that failed.
 ///
 /// Example use:
 /// ```rust ignore
 /// use std::collections::HashMap;
-/// use webdotx::{render, Renderable, Template};
+/// use webdotx::{render, Registry, RenderError, Renderable, Template};
 ///
 /// struct Page {
 ///   name: String,
@@ -292,7 +1078,7 @@ pub trait Renderable {
 /// }
 ///
 /// impl Renderable for Page {
-///     fn render(&self, templates: &HashMap<String, Template>, autofill_funcs: &FuncMap) -> String {
+///     fn render(&self, templates: &HashMap<String, Template>, registry: &Registry) -> Result<Option<String>, RenderError> {
 ///         let filled_placeholders = HashMap::from([
 ///             ("name".to_string(), self.name.clone()),
 ///             ("content".to_string(), self.content.clone()),
@@ -300,7 +1086,7 @@ pub trait Renderable {
 ///             // other fields
 ///         ]);
 ///         let template = templates.get(&self.template_name).unwrap();
-///         template.fill_template(filled_placeholders)
+///         template.fill_template(filled_placeholders, registry).map(Some)
 ///     }
 /// }
 ///
@@ -326,24 +1112,32 @@ pub trait Renderable {
 /// ]);
 ///
 /// let templates = load_templates(std::path::Path::new("templates")).unwrap();
-/// let rendered = render(&named_renderables, &templates);
+/// let rendered = render(&named_renderables, &templates, &Registry::new()).unwrap();
 /// ```
 pub fn render(
     named_renderables: &HashMap<String, impl Renderable>,
     templates: &HashMap<String, Template>,
-    autofill_funcs: &Option<FuncMap>,
-) -> HashMap<String, String> {
+    registry: &Registry,
+) -> Result<HashMap<String, String>, RenderError> {
     let mut rendered = HashMap::new();
+    let mut page_names = named_renderables.keys().collect::<Vec<_>>();
+    // the same error is reported on every run
+    page_names.sort();
     // TODO: probably could parallelize
-    for (page_name, page) in named_renderables {
-        let rendered_page = page.render(templates, autofill_funcs);
+    for page_name in page_names {
+        let rendered_page = named_renderables[page_name]
+            .render(templates, registry)
+            .map_err(|error| RenderError {
+                renderable: Some(page_name.clone()),
+                ..error
+            })?;
         let Some(rendered_page) = rendered_page else {
             continue;
         };
         let page_without_newlines = remove_newlines(&rendered_page);
         rendered.insert(page_name.to_string(), page_without_newlines);
     }
-    rendered
+    Ok(rendered)
 }
 
 fn remove_newlines(page: &str) -> String {
@@ -372,43 +1166,119 @@ fn remove_newlines(page: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_placeholders, Placeholder, Position, Template};
+    use super::{Condition, Escape, FilterCall, Node, Operand, Placeholder, Position, Template};
+    use crate::{Context, Registry, RenderErrorKind, Value};
     use std::collections::HashMap;
 
+    fn position(column: usize) -> Position {
+        Position {
+            template: "t.html".to_string(),
+            line: 1,
+            column,
+        }
+    }
+
+    fn placeholder(name: &str, is_autofill: bool, column: usize) -> Node {
+        Node::Placeholder(Placeholder {
+            name: name.to_string(),
+            is_autofill,
+            arguments: vec![],
+            filters: vec![],
+            escape: Escape::Text,
+            position: position(column),
+        })
+    }
+
+    fn text(text: &str) -> Node {
+        Node::Text(text.to_string())
+    }
+
     #[test]
     fn test_parse_placeholders() {
         let content = "some text, {{ $name$ }}, autofill placeholder {{ %yes% }}, other text {{ $content$ }}{{ $test_offset$ }} another one {{ %no% }}";
-        let got = parse_placeholders(content);
+        let got = Template::parse(content, "t.html").unwrap().nodes;
         let expected = vec![
-            Placeholder {
-                name: "name".to_string(),
-                position: Position { from: 11, to: 22 },
-                is_autofill: false,
-            },
-            Placeholder {
-                name: "yes".to_string(),
-                position: Position { from: 23, to: 33 },
-                is_autofill: true,
-            },
-            Placeholder {
-                name: "content".to_string(),
-                position: Position { from: 13, to: 27 },
-                is_autofill: false,
-            },
-            Placeholder {
-                name: "test_offset".to_string(),
-                position: Position { from: 0, to: 18 },
-                is_autofill: false,
+            text("some text, "),
+            placeholder("name", false, 12),
+            text(", autofill placeholder "),
+            placeholder("yes", true, 47),
+            text(", other text "),
+            placeholder("content", false, 71),
+            placeholder("test_offset", false, 86),
+            text(" another one "),
+            placeholder("no", true, 118),
+        ];
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_parse_conditions() {
+        let content = "{{ if $a$ }}A{{ else if not defined $b$ }}B{{ else }}{{ if $c$ != \"x\" }}C{{ end }}{{ end }}{{ if $d$ == $e$ }}{{ end }}";
+        let got = Template::parse(content, "t.html").unwrap().nodes;
+        let expected = vec![
+            Node::If {
+                condition: Condition::Truthy("a".to_string()),
+                then: vec![text("A")],
+                otherwise: vec![Node::If {
+                    condition: Condition::Not(Box::new(Condition::Defined("b".to_string()))),
+                    then: vec![text("B")],
+                    otherwise: vec![Node::If {
+                        condition: Condition::Not(Box::new(Condition::Equals(
+                            "c".to_string(),
+                            Operand::Literal("x".to_string()),
+                        ))),
+                        then: vec![text("C")],
+                        otherwise: vec![],
+                    }],
+                }],
             },
-            Placeholder {
-                name: "no".to_string(),
-                position: Position { from: 13, to: 22 },
-                is_autofill: true,
+            Node::If {
+                condition: Condition::Equals("d".to_string(), Operand::Placeholder("e".to_string())),
+                then: vec![],
+                otherwise: vec![],
             },
         ];
         assert_eq!(expected, got);
     }
 
+    #[test]
+    fn test_unknown_tags_are_text() {
+        let content = "{{ $not a name$ }} {{ $name% }} {{x}} {{ $name$";
+        assert_eq!(vec![text(content)], Template::parse(content, "t.html").unwrap().nodes);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("{{ if $a$ }}text", "t.html:1:1: `{{ if }}` without `{{ end }}`"),
+            (
+                "a\n{{ for x in $xs$ }}{{ if $x$ }}{{ end }}",
+                "t.html:2:1: `{{ for }}` without `{{ end }}`",
+            ),
+            ("text {{ end }}", "t.html:1:6: unexpected `{{ end }}`"),
+            (
+                "{{ if $a$ }}{{ else }}{{ else }}{{ end }}",
+                "t.html:1:23: unexpected `{{ else }}`",
+            ),
+            (
+                "{{ for item $list$ }}{{ end }}",
+                "t.html:1:1: incorrect loop, expected `for item in $list$`: item $list$",
+            ),
+            (
+                "<p>{{ include partials/nav.html }}</p>",
+                "t.html:1:4: incorrect include, expected a quoted string: partials/nav.html",
+            ),
+            (
+                "{{ $date | date:%Y $ }}",
+                "t.html:1:1: incorrect filter argument, expected a quoted string: %Y",
+            ),
+        ];
+        for (content, expected) in cases {
+            let error = Template::parse(content, "t.html").unwrap_err();
+            assert_eq!(expected, error.to_string());
+        }
+    }
+
     #[test]
     fn test_render() {
         let filled_placeholders = HashMap::from_iter([
@@ -417,27 +1287,274 @@ mod tests {
             ("test_offset".to_string(), "tested".to_string()),
         ]);
         let template = Template {
-            content: "some text, {{ $name$ }}, other text {{ $content$ }}{{ $test_offset$ }}".to_string(),
-            placeholders: vec![
-                Placeholder {
-                    name: "name".into(),
-                    position: Position { from: 11, to: 22 },
-                    is_autofill: false,
-                },
-                Placeholder {
-                    name: "content".into(),
-                    position: Position { from: 13, to: 27 },
-                    is_autofill: false,
-                },
-                Placeholder {
-                    name: "test_offset".into(),
-                    position: Position { from: 0, to: 18 },
-                    is_autofill: false,
-                },
+            nodes: vec![
+                text("some text, "),
+                placeholder("name", false, 12),
+                text(", other text "),
+                placeholder("content", false, 36),
+                placeholder("test_offset", false, 51),
             ],
+            parent: None,
+            dependencies: vec![],
+            unbalanced: vec![],
         };
-        let got = template.fill_template(filled_placeholders, &None);
+        let got = template.fill_template(filled_placeholders, &Registry::new()).unwrap();
         let expected = "some text, blanktiger, other text some interesting texttested".to_string();
         assert_eq!(expected, got);
     }
+
+    #[test]
+    fn test_render_conditions() {
+        let template = Template::new(
+            "{{ if $summary$ }}<p>{{ $summary$ }}</p>{{ else }}none{{ end }}|\
+            {{ if defined $summary$ }}defined{{ end }}|\
+            {{ if $lang$ == \"pl\" }}pl{{ else if $lang$ != $dir$ }}other{{ end }}|\
+            {{ if not $draft$ }}published{{ end }}",
+        );
+        let fill = |values: &[(&str, &str)]| {
+            let values = values.iter().map(|(name, value)| (name.to_string(), value.to_string()));
+            template
+                .fill_template(HashMap::from_iter(values), &Registry::new())
+                .unwrap()
+        };
+
+        assert_eq!("none|||published", fill(&[]));
+        assert_eq!(
+            "none|defined|pl|published",
+            fill(&[("summary", ""), ("lang", "pl"), ("draft", "false")])
+        );
+        assert_eq!(
+            "<p>Short</p>|defined|other|",
+            fill(&[("summary", "Short"), ("lang", "en"), ("dir", "ltr"), ("draft", "true")])
+        );
+    }
+
+    #[test]
+    fn test_parse_loops() {
+        let content = "{{ for item in $items$ }}{{ $item.title$ }}{{ else }}empty{{ end }}";
+        let expected = vec![Node::For {
+            variable: "item".to_string(),
+            list: "items".to_string(),
+            body: vec![placeholder("item.title", false, 26)],
+            otherwise: vec![text("empty")],
+            position: position(1),
+        }];
+        assert_eq!(expected, Template::parse(content, "t.html").unwrap().nodes);
+    }
+
+    #[test]
+    fn test_render_loops() {
+        let template = Template::new(
+            "{{ for article in $articles$ }}{{ if $loop.first$ }}[{{ end }}\
+            {{ $loop.index$ }}:{{ $article.title$ }} by {{ $author$ }}\
+            {{ for tag in $article.tags$ }} #{{ $tag$ }}{{ end }}\
+            {{ if $loop.last$ }}]{{ else }}, {{ end }}{{ else }}no articles{{ end }}",
+        );
+        let article = |title: &str, tags: Vec<&str>| {
+            Value::from(HashMap::from([
+                ("title".to_string(), Value::from(title)),
+                ("tags".to_string(), Value::from(tags)),
+            ]))
+        };
+        let articles = vec![article("First", vec!["a", "b"]), article("Second", vec![])];
+        let filled_placeholders = HashMap::from([
+            ("articles".to_string(), Value::from(articles)),
+            ("author".to_string(), Value::from("me")),
+        ]);
+        assert_eq!(
+            "[0:First by me #a #b, 1:Second by me]",
+            template.fill_template(filled_placeholders, &Registry::new()).unwrap()
+        );
+
+        let filled_placeholders = HashMap::from([("articles".to_string(), Value::List(vec![]))]);
+        assert_eq!(
+            "no articles",
+            template.fill_template(filled_placeholders, &Registry::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_values() {
+        let template = Template::new(
+            "{{ $page.meta.author$ }}, {{ $page.words$ }} words, {{ $page.draft$ }}, {{ $page.rating$ }}\
+            {{ if $page.words$ == \"120\" }}, long{{ end }}{{ if not $page.draft$ }}, published{{ end }}",
+        );
+        let mut page = HashMap::from([
+            ("words".to_string(), Value::from(120_usize)),
+            ("draft".to_string(), Value::from(false)),
+            ("rating".to_string(), Value::from(4.5)),
+        ]);
+        let meta = HashMap::from([("author".to_string(), "blanktiger".to_string())]);
+        page.insert("meta".to_string(), Value::from(meta));
+        let mut context = Context::new();
+        context.insert("page", page);
+        assert_eq!(
+            "blanktiger, 120 words, false, 4.5, long, published",
+            template.fill_template(context, &Registry::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let content = "{{ $date | date:\"%d | %b\" | upper $}}{{ %year | truncate:2, \"..\"% }}";
+        let expected = vec![
+            Node::Placeholder(Placeholder {
+                name: "date".to_string(),
+                is_autofill: false,
+                arguments: vec![],
+                filters: vec![
+                    FilterCall {
+                        name: "date".to_string(),
+                        arguments: vec![Value::from("%d | %b")],
+                    },
+                    FilterCall {
+                        name: "upper".to_string(),
+                        arguments: vec![],
+                    },
+                ],
+                escape: Escape::Text,
+                position: position(1),
+            }),
+            Node::Placeholder(Placeholder {
+                name: "year".to_string(),
+                is_autofill: true,
+                arguments: vec![],
+                filters: vec![FilterCall {
+                    name: "truncate".to_string(),
+                    arguments: vec![Value::Number(2.0), Value::from("..")],
+                }],
+                escape: Escape::Text,
+                position: position(38),
+            }),
+        ];
+        assert_eq!(expected, Template::parse(content, "t.html").unwrap().nodes);
+    }
+
+    #[test]
+    fn test_render_filters() {
+        let template = Template::new("{{ $date | date:\"%d %b %Y\" | upper $}}, {{ $summary | truncate:4 $ }}");
+        let filled_placeholders = HashMap::from([
+            ("date".to_string(), "2026-10-17".to_string()),
+            ("summary".to_string(), "Long summary".to_string()),
+        ]);
+        assert_eq!(
+            "17 OCT 2026, Long…",
+            template.fill_template(filled_placeholders, &Registry::new()).unwrap()
+        );
+
+        let template =
+            Template::new("<script>let tags = {{ $tags | json $}};</script><div data-tags='{{ $tags | json $}}'>");
+        let filled_placeholders = HashMap::from([("tags".to_string(), Value::from(vec!["a'b", "</script>"]))]);
+        assert_eq!(
+            "<script>let tags = [\"a'b\",\"\\u003c/script\\u003e\"];</script>\
+            <div data-tags='[&quot;a&#39;b&quot;,&quot;\\u003c/script\\u003e&quot;]'>",
+            template.fill_template(filled_placeholders, &Registry::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let template = Template::parse(
+            "<h1>{{ $title$ }}</h1>\n  <p>{{ $page.meta.autor$ }}</p>{{ $name | uper $}}",
+            "templates/page.html",
+        )
+        .unwrap();
+        let mut context = Context::new();
+        context.insert("title", "Title");
+        let meta = HashMap::from([("author".to_string(), "blanktiger".to_string())]);
+        context.insert("page", HashMap::from([("meta".to_string(), Value::from(meta))]));
+        let error = template.fill_template(context.clone(), &Registry::new()).unwrap_err();
+        assert_eq!(
+            "templates/page.html:2:6: missing value for placeholder page.meta.autor, did you mean page.meta.author?",
+            error.to_string()
+        );
+
+        let meta = HashMap::from([("autor".to_string(), "blanktiger".to_string())]);
+        context.insert("page", HashMap::from([("meta".to_string(), Value::from(meta))]));
+        context.insert("name", "name");
+        let error = template.fill_template(context.clone(), &Registry::new()).unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            RenderErrorKind::UnknownFilter { placeholder, filter, suggestion }
+                if placeholder == "name" && filter == "uper" && suggestion.as_deref() == Some("upper")
+        ));
+        assert_eq!((2, 33), (error.line, error.column));
+
+        let template = Template::new("{{ %helo% }}");
+        let error = template.fill_template(context, &Registry::new()).unwrap_err();
+        assert_eq!(
+            "1:1: unknown autofill function helo, did you mean hello?",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_tags_end_at_their_own_delimiters() {
+        // an unfinished tag isn't paired with the end of the next one
+        let template = Template::new("{{ %hello% }} {{ $a }} {{ $b$ }}{{ $c $}}");
+        let filled_placeholders =
+            HashMap::from([("b".to_string(), "B".to_string()), ("c".to_string(), "C".to_string())]);
+        assert_eq!(
+            "hello {{ $a }} BC",
+            template.fill_template(filled_placeholders, &Registry::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_escaped() {
+        let template = Template::new(
+            "<title>{{ $title$ }}</title><a href=\"{{ $link$ }}\" title='{{ $title$ }}'>{{ $content$ }}</a>\
+            {{ $html | safe $}}{{ %hello% }}",
+        );
+        let mut context = Context::from(HashMap::from([
+            ("title".to_string(), "\"Quotes\" & <tags>".to_string()),
+            ("link".to_string(), "/?a=1&b=\"2\"".to_string()),
+            ("html".to_string(), "<br>".to_string()),
+        ]));
+        context.insert("content", Value::Safe("<em>rendered</em>".to_string()));
+        assert_eq!(
+            "<title>\"Quotes\" &amp; &lt;tags&gt;</title>\
+            <a href=\"/?a=1&amp;b=&quot;2&quot;\" title='&quot;Quotes&quot; &amp; &lt;tags&gt;'>\
+            <em>rendered</em></a><br>hello",
+            template.fill_template(context, &Registry::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_functions() {
+        let mut registry = Registry::new();
+        registry.register("nav", |context, arguments| {
+            let page = context
+                .get("title")
+                .and_then(|title| title.as_text())
+                .unwrap_or_default();
+            match arguments.get("active") {
+                Some(Value::String(active)) => Ok(format!("{page}: {active}, ")),
+                _ => Err("active has to be text".to_string()),
+            }
+        });
+        let template = Template::new(
+            "{{ for title in $titles$ }}{{ %nav active=\"a %\" | upper% }}{{ %nav active=$title$% }}{{ end }}",
+        );
+        let mut context = Context::new();
+        context.insert("title", "outer");
+        context.insert("titles", vec!["inner"]);
+        assert_eq!(
+            "INNER: A %, inner: inner, ",
+            template.fill_template(context, &registry).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_failed_function() {
+        let mut registry = Registry::new();
+        registry.register("nav", |_, _| Err("active has to be text".to_string()));
+        let error = Template::new("{{ %nav% }}")
+            .fill_template(Context::new(), &registry)
+            .unwrap_err();
+        assert_eq!(
+            "1:1: autofill function nav failed: active has to be text",
+            error.to_string()
+        );
+    }
 }
@@ -0,0 +1,139 @@
+use std::{borrow::Cow, collections::HashMap};
+
+/// Value a placeholder is filled with. Lists can be iterated over with `{{ for item in $list$ }}`,
+/// fields of maps are reached with dotted names, e.g. `{{ $page.meta.author$ }}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    List(Vec<Value>),
+    Map(HashMap<String, Value>),
+    /// HTML that is already safe to insert into a page, e.g. rendered markdown.
+    Safe(String),
+}
+
+/// Values a template is filled with, names map to values that can be reached with dotted paths.
+///
+/// Example use:
+/// ```rust
+/// use std::collections::HashMap;
+/// use webdotx::{Context, Registry, Template, Value};
+///
+/// let mut context = Context::from(HashMap::from([("title".to_string(), "Home".to_string())]));
+/// context.insert("page", HashMap::from([("words".to_string(), Value::Number(120.0))]));
+/// let template = Template::new("{{ $title$ }}: {{ $page.words$ }} words");
+/// assert_eq!("Home: 120 words", template.fill_template(context, &Registry::new()).unwrap());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Context {
+    values: HashMap<String, Value>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    pub fn insert(&mut self, name: &str, value: impl Into<Value>) {
+        self.values.insert(name.to_string(), value.into());
+    }
+
+    /// Finds the value of a dotted path, e.g. `page.meta.author`.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        let mut names = path.split('.');
+        let value = self.values.get(names.next()?)?;
+        names.try_fold(value, |value, name| value.field(name))
+    }
+    /// Names of the values, without the paths to their fields.
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+}
+
+impl<V: Into<Value>> From<HashMap<String, V>> for Context {
+    fn from(values: HashMap<String, V>) -> Self {
+        Context {
+            values: values.into_iter().map(|(name, value)| (name, value.into())).collect(),
+        }
+    }
+}
+
+impl Value {
+    /// Text a placeholder is replaced with, `None` for lists and maps.
+    pub fn as_text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Value::String(string) | Value::Safe(string) => Some(Cow::Borrowed(string)),
+            Value::Number(number) => Some(Cow::Owned(number.to_string())),
+            Value::Bool(bool) => Some(Cow::Owned(bool.to_string())),
+            Value::List(_) | Value::Map(_) => None,
+        }
+    }
+
+    /// Looks up a field of a map, `None` for other values.
+    pub(crate) fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(name),
+            _ => None,
+        }
+    }
+
+    /// Strings are true unless empty or `false`, numbers unless 0, lists and maps unless empty.
+    pub(crate) fn is_truthy(&self) -> bool {
+        match self {
+            Value::String(string) | Value::Safe(string) => !string.is_empty() && string != "false",
+            Value::Number(number) => *number != 0.0,
+            Value::Bool(bool) => *bool,
+            Value::List(list) => !list.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl<V: Into<Value>> From<Vec<V>> for Value {
+    fn from(values: Vec<V>) -> Self {
+        Value::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<V: Into<Value>> From<HashMap<String, V>> for Value {
+    fn from(values: HashMap<String, V>) -> Self {
+        Value::Map(values.into_iter().map(|(name, value)| (name, value.into())).collect())
+    }
+}